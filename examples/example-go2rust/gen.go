@@ -28,7 +28,7 @@ typedef struct ListRef {
 } ListRef;
 
 const void c_rust2go_internal_drop(void*);
-const void c_G2RCall_demo_log(const void*);
+const void c_G2RCall_demo_log(const void*, const void*);
 const void c_G2RCall_demo_convert_name(const void*, const void*);
 */
 import "C"
@@ -261,18 +261,27 @@ func refDemoResponse(p *DemoResponse, buffer *[]byte) C.DemoResponseRef {
 type G2RCallImpl struct{}
 
 func (G2RCallImpl) demo_log(name *string, age *uint8) {
+	_internal_slot := [3]unsafe.Pointer{}
 	_internal_params := [2]unsafe.Pointer{}
 	name_ref, name_buffer := cvt_ref(cntString, refString)(name)
 	_internal_params[0] = unsafe.Pointer(&name_ref)
 	age_ref, age_buffer := cvt_ref(cntC_uint8_t, refC_uint8_t)(age)
 	_internal_params[1] = unsafe.Pointer(&age_ref)
-	asmcall.CallFuncG0P1(unsafe.Pointer(C.c_G2RCall_demo_log), unsafe.Pointer(&_internal_params))
+	asmcall.CallFuncG0P2(unsafe.Pointer(C.c_G2RCall_demo_log), unsafe.Pointer(&_internal_slot), unsafe.Pointer(&_internal_params))
+	runtime.KeepAlive(_internal_slot)
 	runtime.KeepAlive(_internal_params)
 	runtime.KeepAlive(name_buffer)
+	runtime.KeepAlive(name)
 	runtime.KeepAlive(age_buffer)
+	runtime.KeepAlive(age)
+	if _internal_slot[2] != nil {
+		_internal_panic := ownString(*(*C.StringRef)(_internal_slot[2]))
+		asmcall.CallFuncG0P1(unsafe.Pointer(C.c_rust2go_internal_drop), unsafe.Pointer(_internal_slot[1]))
+		panic("rust panicked: " + _internal_panic)
+	}
 }
 func (G2RCallImpl) demo_convert_name(user *DemoUser) string {
-	_internal_slot := [2]unsafe.Pointer{}
+	_internal_slot := [3]unsafe.Pointer{}
 	_internal_params := [1]unsafe.Pointer{}
 	user_ref, user_buffer := cvt_ref(cntDemoUser, refDemoUser)(user)
 	_internal_params[0] = unsafe.Pointer(&user_ref)
@@ -280,6 +289,12 @@ func (G2RCallImpl) demo_convert_name(user *DemoUser) string {
 	runtime.KeepAlive(_internal_slot)
 	runtime.KeepAlive(_internal_params)
 	runtime.KeepAlive(user_buffer)
+	runtime.KeepAlive(user)
+	if _internal_slot[2] != nil {
+		_internal_panic := ownString(*(*C.StringRef)(_internal_slot[2]))
+		asmcall.CallFuncG0P1(unsafe.Pointer(C.c_rust2go_internal_drop), unsafe.Pointer(_internal_slot[1]))
+		panic("rust panicked: " + _internal_panic)
+	}
 	val := ownString(*(*C.StringRef)(_internal_slot[0]))
 	asmcall.CallFuncG0P1(unsafe.Pointer(C.c_rust2go_internal_drop), unsafe.Pointer(_internal_slot[1]))
 	return val
@@ -88,8 +88,13 @@ func ringHandleDemoCall2(ptr unsafe.Pointer, pool *ants.MultiPool, post_func fun
 }
 
 //export RingsInitDemoCall
-func RingsInitDemoCall(crr, crw C.QueueMeta) {
-	ringsInit(crr, crw, []func(unsafe.Pointer, *ants.MultiPool, func(interface{}, []byte, uint)){ringHandleDemoCall0, ringHandleDemoCall1, ringHandleDemoCall2})
+func RingsInitDemoCall(crr, crw C.QueueMeta, goPools, goPoolSize C.int64_t) {
+	ringsInit(crr, crw, []func(unsafe.Pointer, *ants.MultiPool, func(interface{}, []byte, uint)){ringHandleDemoCall0, ringHandleDemoCall1, ringHandleDemoCall2}, int(goPools), int(goPoolSize))
+}
+
+//export CDemoCall_abi_hash
+func CDemoCall_abi_hash() C.uint64_t {
+	return C.uint64_t(0x4ce69661d56cafcf)
 }
 
 // An alternative impl of unsafe.String for go1.18
@@ -338,10 +343,7 @@ func refDemoResponse(p *DemoResponse, buffer *[]byte) C.DemoResponseRef {
 	}
 }
 
-func ringsInit(crr, crw C.QueueMeta, fns []func(unsafe.Pointer, *ants.MultiPool, func(interface{}, []byte, uint))) {
-	const MULTIPOOL_SIZE = 8
-	const SIZE_PER_POOL = -1
-
+func ringsInit(crr, crw C.QueueMeta, fns []func(unsafe.Pointer, *ants.MultiPool, func(interface{}, []byte, uint)), multipoolSize int, sizePerPool int) {
 	type Storage struct {
 		resp   interface{}
 		buffer []byte
@@ -382,7 +384,10 @@ func ringsInit(crr, crw C.QueueMeta, fns []func(unsafe.Pointer, *ants.MultiPool,
 	gw := rrq.Write()
 
 	slab := mem_ring.NewMultiSlab[Storage]()
-	pool, _ := ants.NewMultiPool(MULTIPOOL_SIZE, SIZE_PER_POOL, ants.RoundRobin)
+	pool, err := ants.NewMultiPool(multipoolSize, sizePerPool, ants.RoundRobin)
+	if err != nil {
+		panic("rust2go: unable to create go pools of shm calls: " + err.Error())
+	}
 
 	gr.RunHandler(func(p Payload) {
 		if p.Flag == CALL {
@@ -48,6 +48,8 @@ pub struct DemoResponse {
 // `#[rust2go::r2g(binding)]` or `#[rust2go::r2g(binding = binding)]`
 // If you want to set queue size manually(by default it is 4096), use:
 // `#[rust2go::r2g(queue_size = 4096)]`
+// Method attributes can also be set for all methods of the trait, and opted out with `#[no_xxx]`:
+// `#[rust2go::r2g(mem, send)]` with `#[no_mem]` on a method
 #[rust2go::r2g(binding = binding, queue_size = 40960)]
 pub trait DemoCall {
     #[mem]
@@ -118,6 +118,11 @@ func CDemoCall_demo_sum(a C.int32_t, b C.int32_t, slot *C.void, cb *C.void) {
 	runtime.KeepAlive(buffer)
 }
 
+//export CDemoCall_abi_hash
+func CDemoCall_abi_hash() C.uint64_t {
+	return C.uint64_t(0x1b01c7afbd70ef09)
+}
+
 // An alternative impl of unsafe.String for go1.18
 func unsafeString(ptr *byte, length int) string {
 	sliceHeader := &reflect.SliceHeader{
@@ -31,16 +31,104 @@ typedef struct DemoUserRef {
   struct StringRef name;
   uint8_t age;
 } DemoUserRef;
+
+const void c_rust2go_internal_drop(void*);
+
+void c_rust2go_callback_call(void*, void*, void*);
+void c_rust2go_callback_release(void*);
+
+void c_rust2go_stream_send(void*, void*);
+void c_rust2go_stream_close(void*);
 */
 import "C"
 import (
+	"context"
 	"reflect"
 	"runtime"
+	"sync"
 	"unsafe"
 
 	"github.com/ihciah/rust2go/asmcall"
 )
 
+type r2gCallback struct {
+	mu    sync.Mutex
+	ptr   unsafe.Pointer
+	owned bool
+}
+
+func (c *r2gCallback) call(callFn func(unsafe.Pointer, unsafe.Pointer, unsafe.Pointer, unsafe.Pointer), dropFn func(unsafe.Pointer, unsafe.Pointer), arg unsafe.Pointer) bool {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	if c.ptr == nil {
+		return false
+	}
+	slot := [3]unsafe.Pointer{}
+	callFn(unsafe.Pointer(C.c_rust2go_callback_call), c.ptr, arg, unsafe.Pointer(&slot))
+	if slot[2] != nil {
+		msg := ownString(*(*C.StringRef)(slot[2]))
+		dropFn(unsafe.Pointer(C.c_rust2go_internal_drop), slot[1])
+		panic("rust panicked: " + msg)
+	}
+	return true
+}
+func (c *r2gCallback) done() {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	if c.owned {
+		C.c_rust2go_callback_release(c.ptr)
+	}
+	c.ptr = nil
+}
+
+var r2gStreams sync.Map
+
+type r2gStream struct {
+	ptr    unsafe.Pointer
+	ready  chan struct{}
+	cancel context.CancelFunc
+}
+
+type r2gStreamSend struct {
+	item   unsafe.Pointer
+	status uint8
+}
+
+func (s *r2gStream) send(ctx context.Context, callFn func(unsafe.Pointer, unsafe.Pointer, unsafe.Pointer), item unsafe.Pointer) bool {
+	arg := r2gStreamSend{item: item}
+	callFn(unsafe.Pointer(C.c_rust2go_stream_send), s.ptr, unsafe.Pointer(&arg))
+	switch arg.status {
+	case 0:
+		return true
+	case 1:
+		select {
+		case <-s.ready:
+			return true
+		case <-ctx.Done():
+			return false
+		}
+	default:
+		return false
+	}
+}
+
+//export rust2go_stream_wake
+func rust2go_stream_wake(stream unsafe.Pointer) {
+	if s, ok := r2gStreams.Load(uintptr(stream)); ok {
+		select {
+		case s.(*r2gStream).ready <- struct{}{}:
+		default:
+		}
+	}
+}
+
+//export rust2go_stream_cancel
+func rust2go_stream_cancel(stream unsafe.Pointer) {
+	if s, ok := r2gStreams.Load(uintptr(stream)); ok {
+		s.(*r2gStream).cancel()
+	}
+}
+
 var DemoCallImpl DemoCall
 
 type DemoCall interface {
@@ -48,6 +136,8 @@ type DemoCall interface {
 	demo_check(req *DemoComplicatedRequest) DemoResponse
 	demo_check_async(req *DemoComplicatedRequest) DemoResponse
 	demo_check_async_safe(req *DemoComplicatedRequest) DemoResponse
+	demo_progress(req *DemoComplicatedRequest, progress func(uint32) bool) DemoResponse
+	demo_users(ctx context.Context, n *uint32, yield func(DemoUser) bool)
 }
 
 //export CDemoCall_demo_oneway
@@ -93,6 +183,51 @@ func CDemoCall_demo_check_async_safe(req C.DemoComplicatedRequestRef, slot *C.vo
 	}()
 }
 
+//export CDemoCall_demo_progress
+func CDemoCall_demo_progress(req C.DemoComplicatedRequestRef, progress *C.void, slot *C.void, cb *C.void) {
+	_new_req := newDemoComplicatedRequest(req)
+	_cb_progress := &r2gCallback{ptr: unsafe.Pointer(progress), owned: false}
+	_new_progress := func(_arg uint32) bool {
+		_arg_ref, buffer := cvt_ref(cntC_uint32_t, refC_uint32_t)(&_arg)
+		_ok := _cb_progress.call(asmcall.CallFuncG0P3, asmcall.CallFuncG0P1, unsafe.Pointer(&_arg_ref))
+		runtime.KeepAlive(_arg)
+		runtime.KeepAlive(buffer)
+		return _ok
+	}
+	resp := DemoCallImpl.demo_progress(&_new_req, _new_progress)
+	_cb_progress.done()
+	resp_ref, buffer := cvt_ref(cntDemoResponse, refDemoResponse)(&resp)
+	asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&resp_ref), unsafe.Pointer(slot))
+	runtime.KeepAlive(resp_ref)
+	runtime.KeepAlive(resp)
+	runtime.KeepAlive(buffer)
+}
+
+//export CDemoCall_demo_users
+func CDemoCall_demo_users(n C.uint32_t, _stream *C.void) {
+	_new_n := newC_uint32_t(n)
+	_ctx, _cancel := context.WithCancel(context.Background())
+	_s := &r2gStream{ptr: unsafe.Pointer(_stream), ready: make(chan struct{}, 1), cancel: _cancel}
+	r2gStreams.Store(uintptr(unsafe.Pointer(_stream)), _s)
+	go func() {
+		DemoCallImpl.demo_users(_ctx, &_new_n, func(_item DemoUser) bool {
+			_item_ref, buffer := cvt_ref(cntDemoUser, refDemoUser)(&_item)
+			_ok := _s.send(_ctx, asmcall.CallFuncG0P2, unsafe.Pointer(&_item_ref))
+			runtime.KeepAlive(_item)
+			runtime.KeepAlive(buffer)
+			return _ok
+		})
+		r2gStreams.Delete(uintptr(unsafe.Pointer(_stream)))
+		_cancel()
+		C.c_rust2go_stream_close(unsafe.Pointer(_stream))
+	}()
+}
+
+//export CDemoCall_abi_hash
+func CDemoCall_abi_hash() C.uint64_t {
+	return C.uint64_t(0x558d95cc76ba5585)
+}
+
 // An alternative impl of unsafe.String for go1.18
 func unsafeString(ptr *byte, length int) string {
 	sliceHeader := &reflect.SliceHeader{
@@ -1,6 +1,7 @@
 package main
 
 import (
+	"context"
 	"fmt"
 	"time"
 )
@@ -35,3 +36,21 @@ func (Demo) demo_check_async_safe(req *DemoComplicatedRequest) DemoResponse {
 	fmt.Printf("[Go-call async drop_safe] Golang returned result, pass: %v\n", req.balabala[0] == 1)
 	return resp
 }
+
+func (Demo) demo_progress(req *DemoComplicatedRequest, progress func(uint32) bool) DemoResponse {
+	for i := range req.users {
+		fmt.Printf("[Go-callback] Golang checked user %d\n", i)
+		progress(uint32(i + 1))
+	}
+	return DemoResponse{pass: true}
+}
+
+func (Demo) demo_users(ctx context.Context, n *uint32, yield func(DemoUser) bool) {
+	for i := uint32(0); i < *n; i++ {
+		if !yield(DemoUser{name: fmt.Sprintf("user%d", i), age: uint8(20 + i)}) {
+			fmt.Printf("[Go-stream] Rust stream is dropped\n")
+			return
+		}
+	}
+	fmt.Printf("[Go-stream] Golang sent %d users\n", *n)
+}
@@ -1,9 +1,11 @@
 mod user;
 use std::{
     future::{Future, poll_fn},
+    pin::pin,
     time::Instant,
 };
 
+use rust2go::Stream;
 use user::{DemoCall, DemoCallImpl, DemoComplicatedRequest, DemoUser};
 
 #[tokio::main]
@@ -38,6 +40,9 @@ async fn main() {
 
     println!("========== Start async drop_safe demo ==========");
     drop_safe().await;
+
+    println!("========== Start callback and stream demo ==========");
+    callback_and_stream().await;
 }
 
 // Call an async golang function.
@@ -89,3 +94,28 @@ async fn drop_safe() {
     tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
     println!("[Rust-async drop_safe] It is expected to see golang side memory safe");
 }
+
+// Golang calls the closure during the call, and sends the items of the stream one by one.
+async fn callback_and_stream() {
+    let req = DemoComplicatedRequest {
+        users: vec![
+            DemoUser {
+                name: "chihai".to_string(),
+                age: 28,
+            };
+            3
+        ],
+        balabala: vec![1],
+    };
+    let mut checked = 0;
+    let pass = DemoCallImpl::demo_progress(&req, |n| checked = n).pass;
+    println!("[Rust-callback] User pass: {pass}, checked: {checked}");
+
+    let mut users = pin!(DemoCallImpl::demo_users(3));
+    while let Some(user) = poll_fn(|cx| users.as_mut().poll_next(cx)).await {
+        println!(
+            "[Rust-stream] Received name: {}, age: {}",
+            user.name, user.age
+        );
+    }
+}
@@ -44,6 +44,9 @@ pub struct DemoResponse {
 // Otherwise, use async style:
 // Both `async fn` and `impl Future` styles are supported.
 //
+// A callback param (`impl FnMut(T) + Send`) is called by golang during the call, and a
+// returned `impl Stream` yields the items sent by golang.
+//
 // If you want to use your own binding mod name, use:
 // `#[rust2go::r2g(binding)]`
 #[rust2go::r2g]
@@ -57,4 +60,9 @@ pub trait DemoCall {
     fn demo_check_async_safe(
         req: DemoComplicatedRequest,
     ) -> impl std::future::Future<Output = DemoResponse>;
+    fn demo_progress(
+        req: &DemoComplicatedRequest,
+        progress: impl FnMut(u32) + Send,
+    ) -> DemoResponse;
+    fn demo_users(n: u32) -> impl rust2go::Stream<Item = DemoUser> + Send;
 }
@@ -0,0 +1,598 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::{collections::HashMap, fmt};
+
+use crate::diagnostic::{Error, SourceFile};
+use crate::schema::{
+    G2RMethodSchema, G2RTraitSchema, ParamSchema, R2GMethodSchema, Schema, StructSchema, TypeSchema,
+};
+use clap::Parser;
+
+#[derive(Parser, Debug, Clone)]
+pub struct CheckArgs {
+    /// Old interface, a rust file or a json schema dumped by `schema`
+    #[arg(long)]
+    pub old: String,
+
+    /// New interface, a rust file or a json schema dumped by `schema`
+    #[arg(long)]
+    pub new: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The old Go library and the new Rust binary(or vice versa) cannot work together.
+    Breaking(String),
+    /// Additions or renames that keep the memory layout and calling convention.
+    Compatible(String),
+}
+
+impl Change {
+    pub const fn is_breaking(&self) -> bool {
+        matches!(self, Change::Breaking(_))
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Breaking(msg) => write!(f, "breaking: {msg}"),
+            Change::Compatible(msg) => write!(f, "compatible: {msg}"),
+        }
+    }
+}
+
+impl fmt::Display for TypeSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeSchema::Primitive { name } | TypeSchema::Struct { name } => write!(f, "{name}"),
+            TypeSchema::String => write!(f, "String"),
+            TypeSchema::List { elem } => write!(f, "Vec<{elem}>"),
+            TypeSchema::Handle { name } => write!(f, "Handle<{name}>"),
+            TypeSchema::Tuple { elems } => {
+                let elems: Vec<_> = elems.iter().map(|e| e.to_string()).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+        }
+    }
+}
+
+fn load_schema(path: &str) -> Result<Schema, Error> {
+    if path.ends_with(".json") {
+        let content = std::fs::read_to_string(path).map_err(|source| Error::Io {
+            context: format!("unable to read {path}"),
+            source,
+        })?;
+        serde_json::from_str(&content).map_err(|source| Error::Json {
+            path: path.to_string(),
+            source,
+        })
+    } else {
+        let src = SourceFile::open(path)?;
+        Schema::new(&src.file).map_err(|e| src.error(e))
+    }
+}
+
+/// Compare two interfaces and print the changes.
+/// Returns false if there is any breaking change.
+pub fn check(args: &CheckArgs) -> Result<bool, Error> {
+    let changes = diff(&load_schema(&args.old)?, &load_schema(&args.new)?);
+    for change in changes.iter() {
+        println!("{change}");
+    }
+    let breaking = changes.iter().filter(|c| c.is_breaking()).count();
+    println!(
+        "{breaking} breaking, {} compatible change(s)",
+        changes.len() - breaking
+    );
+    Ok(breaking == 0)
+}
+
+pub fn diff(old: &Schema, new: &Schema) -> Vec<Change> {
+    let mut out = Vec::new();
+    diff_structs(&old.structs, &new.structs, &mut out);
+
+    let new_r2g: HashMap<_, _> = new.r2g_traits.iter().map(|t| (&t.name, t)).collect();
+    for old_t in old.r2g_traits.iter() {
+        let Some(new_t) = new_r2g.get(&old_t.name) else {
+            out.push(Change::Breaking(format!("trait {} removed", old_t.name)));
+            continue;
+        };
+        // The go library serves the calls of a parent with the impl of its children, so the
+        // calls routed by rust side may find no impl.
+        for p in old_t.parents.iter().filter(|p| !new_t.parents.contains(p)) {
+            out.push(Change::Breaking(format!(
+                "trait {} no longer extends {p}",
+                old_t.name
+            )));
+        }
+        for p in new_t.parents.iter().filter(|p| !old_t.parents.contains(p)) {
+            out.push(Change::Breaking(format!(
+                "trait {} now extends {p}",
+                old_t.name
+            )));
+        }
+        diff_methods(
+            &old_t.name,
+            &old_t.methods,
+            &new_t.methods,
+            &mut out,
+            diff_r2g_method,
+        );
+    }
+    for new_t in new.r2g_traits.iter() {
+        if !old.r2g_traits.iter().any(|t| t.name == new_t.name) {
+            out.push(Change::Compatible(format!("trait {} added", new_t.name)));
+        }
+    }
+
+    let new_g2r: HashMap<_, _> = new.g2r_traits.iter().map(|t| (&t.name, t)).collect();
+    for old_t in old.g2r_traits.iter() {
+        let Some(new_t) = new_g2r.get(&old_t.name) else {
+            out.push(Change::Breaking(format!("trait {} removed", old_t.name)));
+            continue;
+        };
+        // The mode changes the slot of the return value, schemas without it are in the default
+        // mode.
+        let rust_panic = |t: &G2RTraitSchema| t.rust_panic.clone().unwrap_or("resume".into());
+        if rust_panic(old_t) != rust_panic(new_t) {
+            out.push(Change::Breaking(format!(
+                "trait {} rust panic mode changed from {} to {}",
+                old_t.name,
+                rust_panic(old_t),
+                rust_panic(new_t)
+            )));
+        }
+        diff_methods(
+            &old_t.name,
+            &old_t.methods,
+            &new_t.methods,
+            &mut out,
+            diff_g2r_method,
+        );
+    }
+    for new_t in new.g2r_traits.iter() {
+        if !old.g2r_traits.iter().any(|t| t.name == new_t.name) {
+            out.push(Change::Compatible(format!("trait {} added", new_t.name)));
+        }
+    }
+    out
+}
+
+fn diff_structs(old: &[StructSchema], new: &[StructSchema], out: &mut Vec<Change>) {
+    let new_map: HashMap<_, _> = new.iter().map(|s| (&s.name, s)).collect();
+    for old_s in old.iter() {
+        let name = &old_s.name;
+        let Some(new_s) = new_map.get(name) else {
+            out.push(Change::Breaking(format!("struct {name} removed")));
+            continue;
+        };
+        if old_s.fields.len() != new_s.fields.len() {
+            out.push(Change::Breaking(format!(
+                "struct {name} field count changed from {} to {}",
+                old_s.fields.len(),
+                new_s.fields.len()
+            )));
+            continue;
+        }
+        for (idx, (of, nf)) in old_s.fields.iter().zip(new_s.fields.iter()).enumerate() {
+            if of.ty != nf.ty {
+                out.push(Change::Breaking(format!(
+                    "struct {name} field {idx} changed from {}: {} to {}: {}",
+                    of.name, of.ty, nf.name, nf.ty
+                )));
+            } else if of.name != nf.name {
+                out.push(Change::Compatible(format!(
+                    "struct {name} field {idx} renamed from {} to {}",
+                    of.name, nf.name
+                )));
+            }
+        }
+    }
+    for new_s in new.iter() {
+        if !old.iter().any(|s| s.name == new_s.name) {
+            out.push(Change::Compatible(format!("struct {} added", new_s.name)));
+        }
+    }
+}
+
+trait Named {
+    fn name(&self) -> &str;
+}
+
+impl Named for R2GMethodSchema {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Named for G2RMethodSchema {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn diff_methods<M: Named>(
+    trait_name: &str,
+    old: &[M],
+    new: &[M],
+    out: &mut Vec<Change>,
+    diff_method: fn(&str, &M, &M, &mut Vec<Change>),
+) {
+    for old_m in old.iter() {
+        let Some(new_m) = new.iter().find(|m| m.name() == old_m.name()) else {
+            out.push(Change::Breaking(format!(
+                "method {trait_name}::{} removed",
+                old_m.name()
+            )));
+            continue;
+        };
+        diff_method(
+            &format!("{trait_name}::{}", old_m.name()),
+            old_m,
+            new_m,
+            out,
+        );
+    }
+    for new_m in new.iter() {
+        if !old.iter().any(|m| m.name() == new_m.name()) {
+            out.push(Change::Compatible(format!(
+                "method {trait_name}::{} added",
+                new_m.name()
+            )));
+        }
+    }
+}
+
+fn diff_signature(
+    name: &str,
+    (old_params, old_ret): (&[ParamSchema], &Option<TypeSchema>),
+    (new_params, new_ret): (&[ParamSchema], &Option<TypeSchema>),
+    out: &mut Vec<Change>,
+) {
+    let fmt_params = |params: &[ParamSchema]| {
+        params
+            .iter()
+            .map(|p| p.ty.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let old_tys = old_params.iter().map(|p| &p.ty);
+    if old_params.len() != new_params.len() || !old_tys.eq(new_params.iter().map(|p| &p.ty)) {
+        out.push(Change::Breaking(format!(
+            "method {name} parameters changed from ({}) to ({})",
+            fmt_params(old_params),
+            fmt_params(new_params)
+        )));
+    }
+    if old_ret != new_ret {
+        let fmt_ret =
+            |ret: &Option<TypeSchema>| ret.as_ref().map_or("()".to_string(), |r| r.to_string());
+        out.push(Change::Breaking(format!(
+            "method {name} return type changed from {} to {}",
+            fmt_ret(old_ret),
+            fmt_ret(new_ret)
+        )));
+    }
+}
+
+fn diff_r2g_method(
+    name: &str,
+    old: &R2GMethodSchema,
+    new: &R2GMethodSchema,
+    out: &mut Vec<Change>,
+) {
+    diff_signature(name, (&old.params, &old.ret), (&new.params, &new.ret), out);
+    if old.is_async != new.is_async {
+        out.push(Change::Breaking(format!(
+            "method {name} changed from {} to {}",
+            if old.is_async { "async" } else { "sync" },
+            if new.is_async { "async" } else { "sync" },
+        )));
+    }
+    if old.receiver != new.receiver || old.constructor != new.constructor {
+        let kind = |m: &R2GMethodSchema| match (m.receiver, m.constructor) {
+            (true, _) => "&self method",
+            (_, true) => "constructor",
+            _ => "static method",
+        };
+        out.push(Change::Breaking(format!(
+            "method {name} changed from {} to {}",
+            kind(old),
+            kind(new)
+        )));
+    }
+    if old.callback.as_ref().map(|c| &c.ty) != new.callback.as_ref().map(|c| &c.ty) {
+        let fmt_callback = |c: &Option<ParamSchema>| {
+            c.as_ref()
+                .map_or("none".to_string(), |c| format!("Fn({})", c.ty))
+        };
+        out.push(Change::Breaking(format!(
+            "method {name} callback changed from {} to {}",
+            fmt_callback(&old.callback),
+            fmt_callback(&new.callback)
+        )));
+    }
+    if old.stream != new.stream {
+        let fmt_stream = |s: &Option<TypeSchema>| {
+            s.as_ref()
+                .map_or("none".to_string(), |s| format!("Stream<{s}>"))
+        };
+        out.push(Change::Breaking(format!(
+            "method {name} stream changed from {} to {}",
+            fmt_stream(&old.stream),
+            fmt_stream(&new.stream)
+        )));
+    }
+    if old.stream_param.as_ref().map(|s| &s.ty) != new.stream_param.as_ref().map(|s| &s.ty) {
+        let fmt_stream_param = |s: &Option<ParamSchema>| {
+            s.as_ref()
+                .map_or("none".to_string(), |s| format!("Stream<{}>", s.ty))
+        };
+        out.push(Change::Breaking(format!(
+            "method {name} stream param changed from {} to {}",
+            fmt_stream_param(&old.stream_param),
+            fmt_stream_param(&new.stream_param)
+        )));
+    }
+    // Flags of the abi header which change the go export or the response.
+    let flags = |m: &R2GMethodSchema| {
+        [
+            (m.cancel, "cancellable"),
+            (m.timeout_ms.is_some(), "bounded by a deadline"),
+            (m.go_panic.is_some(), "recovering go panics"),
+        ]
+    };
+    for ((o, desc), (n, _)) in flags(old).into_iter().zip(flags(new)) {
+        if o != n {
+            let now = if n { "now" } else { "no longer" };
+            out.push(Change::Breaking(format!("method {name} is {now} {desc}")));
+        }
+    }
+    // Only the go interface changes, the exports take the same refs.
+    if old.go_pass_struct != new.go_pass_struct {
+        let passing = |m: &R2GMethodSchema| {
+            if m.go_pass_struct {
+                "values"
+            } else {
+                "pointers"
+            }
+        };
+        out.push(Change::Compatible(format!(
+            "method {name} go params changed from {} to {}",
+            passing(old),
+            passing(new)
+        )));
+    }
+    if let (Some(o), Some(n)) = (old.timeout_ms, new.timeout_ms) {
+        if o != n {
+            out.push(Change::Compatible(format!(
+                "method {name} default deadline changed from {o}ms to {n}ms"
+            )));
+        }
+    }
+    if old.go_panic.is_some() && new.go_panic.is_some() && old.go_panic != new.go_panic {
+        out.push(Change::Compatible(format!(
+            "method {name} go panic mode changed from {} to {}",
+            old.go_panic.as_deref().unwrap_or_default(),
+            new.go_panic.as_deref().unwrap_or_default()
+        )));
+    }
+    match (old.mem_call_id, new.mem_call_id) {
+        (Some(o), Some(n)) if o != n => out.push(Change::Breaking(format!(
+            "method {name} mem call_id renumbered from {o} to {n}"
+        ))),
+        (Some(_), None) => out.push(Change::Breaking(format!(
+            "method {name} is no longer based on shared memory"
+        ))),
+        (None, Some(_)) => out.push(Change::Breaking(format!(
+            "method {name} is now based on shared memory"
+        ))),
+        _ => (),
+    }
+    if old.mem_call_id.is_some() && new.mem_call_id.is_some() && old.mem_ring != new.mem_ring {
+        out.push(Change::Breaking(format!(
+            "method {name} moved from shm ring {} to {}",
+            old.mem_ring, new.mem_ring
+        )));
+    }
+}
+
+fn diff_g2r_method(
+    name: &str,
+    old: &G2RMethodSchema,
+    new: &G2RMethodSchema,
+    out: &mut Vec<Change>,
+) {
+    diff_signature(name, (&old.params, &old.ret), (&new.params, &new.ret), out);
+    if old.is_async != new.is_async {
+        out.push(Change::Breaking(format!(
+            "method {name} changed from {} to {}",
+            if old.is_async { "async" } else { "sync" },
+            if new.is_async { "async" } else { "sync" },
+        )));
+    }
+    // Go side calls the same rust export either way.
+    if old.cgo != new.cgo {
+        let now = if new.cgo { "now" } else { "no longer" };
+        out.push(Change::Compatible(format!(
+            "method {name} is {now} called with cgo"
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust2go_common::common::RawRsFile;
+
+    use super::*;
+
+    fn schema(src: &str) -> Schema {
+        Schema::new(&RawRsFile::new(src).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn detect_breaking() {
+        let old = schema(
+            r#"
+            pub struct DemoUser { pub name: String, pub age: u8 }
+            #[rust2go::r2g]
+            pub trait DemoCall {
+                #[mem]
+                fn demo_oneway(req: &DemoUser);
+                #[mem]
+                #[drop_safe]
+                async fn demo_check(req: DemoUser) -> DemoUser;
+            }
+            "#,
+        );
+        let new = schema(
+            r#"
+            pub struct DemoUser { pub age: u8, pub name: String }
+            pub struct DemoExtra { pub id: u32 }
+            #[rust2go::r2g]
+            pub trait DemoCall {
+                #[mem]
+                fn demo_new(req: &DemoUser);
+                #[mem]
+                fn demo_oneway(req: &DemoUser);
+                #[mem]
+                #[drop_safe]
+                async fn demo_check(req: DemoUser) -> DemoUser;
+            }
+            "#,
+        );
+        let changes = diff(&old, &new);
+        let breaking: Vec<_> = changes.iter().filter(|c| c.is_breaking()).collect();
+        // 2 reordered fields and 2 renumbered call ids
+        assert_eq!(breaking.len(), 4);
+        assert!(changes.contains(&Change::Compatible("struct DemoExtra added".to_string())));
+        assert!(changes.contains(&Change::Compatible(
+            "method DemoCall::demo_new added".to_string()
+        )));
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn detect_r2g_flags() {
+        let old = schema(
+            r#"
+            pub struct DemoUser { pub name: String }
+            #[rust2go::r2g]
+            pub trait DemoCall {
+                #[drop_safe]
+                async fn demo_check(req: DemoUser) -> DemoUser;
+                #[drop_safe]
+                #[timeout_ms = 100]
+                async fn demo_slow(req: DemoUser) -> DemoUser;
+                fn demo_value(req: &DemoUser);
+            }
+            "#,
+        );
+        let new = schema(
+            r#"
+            pub struct DemoUser { pub name: String }
+            #[rust2go::r2g(go_panic = "result", cancel)]
+            pub trait DemoCall {
+                #[drop_safe]
+                #[timeout_ms = 100]
+                async fn demo_check(req: DemoUser) -> DemoUser;
+                #[drop_safe]
+                #[timeout_ms = 200]
+                async fn demo_slow(req: DemoUser) -> DemoUser;
+                #[go_pass_struct]
+                fn demo_value(req: &DemoUser);
+            }
+            "#,
+        );
+        let changes = diff(&old, &new);
+        for msg in [
+            "method DemoCall::demo_check is now cancellable",
+            "method DemoCall::demo_check is now bounded by a deadline",
+            "method DemoCall::demo_check is now recovering go panics",
+            "method DemoCall::demo_slow is now recovering go panics",
+        ] {
+            assert!(
+                changes.contains(&Change::Breaking(msg.to_string())),
+                "{msg}"
+            );
+        }
+        assert!(changes.contains(&Change::Compatible(
+            "method DemoCall::demo_slow default deadline changed from 100ms to 200ms".to_string()
+        )));
+        assert!(changes.contains(&Change::Compatible(
+            "method DemoCall::demo_value go params changed from pointers to values".to_string()
+        )));
+    }
+
+    #[test]
+    fn detect_parents() {
+        let traits = r#"
+            pub struct DemoUser { pub name: String }
+            #[rust2go::r2g]
+            pub trait Users {
+                fn get(req: DemoUser) -> DemoUser;
+            }
+            #[rust2go::r2g]
+            pub trait Audit {
+                fn log(req: DemoUser);
+            }
+            "#;
+        let old = schema(&format!(
+            "{traits}#[rust2go::r2g] pub trait Admin: Users {{ fn ban(req: DemoUser); }}"
+        ));
+        let new = schema(&format!(
+            "{traits}#[rust2go::r2g] pub trait Admin: Audit {{ fn ban(req: DemoUser); }}"
+        ));
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Change::Breaking("trait Admin no longer extends Users".to_string()),
+                Change::Breaking("trait Admin now extends Audit".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_g2r_changes() {
+        let old = schema(
+            r#"
+            pub struct DemoUser { pub name: String }
+            #[rust2go::g2r]
+            pub trait DemoCallback {
+                fn demo_get(req: DemoUser) -> DemoUser;
+            }
+            "#,
+        );
+        let new = schema(
+            r#"
+            pub struct DemoUser { pub name: String }
+            #[rust2go::g2r(rust_panic = "result")]
+            pub trait DemoCallback {
+                #[cgo_call]
+                async fn demo_get(req: DemoUser) -> DemoUser;
+            }
+            "#,
+        );
+        let changes = diff(&old, &new);
+        assert_eq!(
+            changes,
+            vec![
+                Change::Breaking(
+                    "trait DemoCallback rust panic mode changed from resume to result".to_string()
+                ),
+                Change::Breaking(
+                    "method DemoCallback::demo_get changed from sync to async".to_string()
+                ),
+                Change::Compatible(
+                    "method DemoCallback::demo_get is now called with cgo".to_string()
+                ),
+            ]
+        );
+
+        // Schemas dumped before the mode is recorded are in the default mode.
+        let mut legacy = old.clone();
+        legacy.g2r_traits[0].rust_panic = None;
+        assert!(diff(&legacy, &old).is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::{fmt, io};
+
+use proc_macro2::Span;
+use rust2go_common::{common::RawRsFile, lint::Warning};
+
+/// An error located in the source rust file, rendered like rustc diagnostics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub file: String,
+    /// 1-based line, 0 if the location is unknown.
+    pub line: usize,
+    /// 1-based column.
+    pub column: usize,
+    /// Source line of the offending item.
+    pub source_line: String,
+    /// Width of the offending item on the source line.
+    pub width: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        context: String,
+        source: io::Error,
+    },
+    Json {
+        path: String,
+        source: serde_json::Error,
+    },
+    Source(Vec<Diagnostic>),
+    /// Invalid arguments, or a failure without a location.
+    Invalid(String),
+}
+
+/// A parsed rust file with its path and content, used to locate errors.
+pub struct SourceFile {
+    pub path: String,
+    pub content: String,
+    pub file: RawRsFile,
+}
+
+impl SourceFile {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path).map_err(|source| Error::Io {
+            context: format!("unable to read {path}"),
+            source,
+        })?;
+        Self::parse(path, content)
+    }
+
+    pub fn parse(path: &str, content: String) -> Result<Self, Error> {
+        match RawRsFile::new(&content) {
+            Ok(file) => Ok(SourceFile {
+                path: path.to_string(),
+                content,
+                file,
+            }),
+            Err(e) => Err(Error::from_syn(path, &content, e)),
+        }
+    }
+
+    /// Locate the syn error in this file.
+    pub fn error(&self, e: syn::Error) -> Error {
+        Error::from_syn(&self.path, &self.content, e)
+    }
+
+    /// Locate the lint warning in this file.
+    pub fn warning(&self, w: &Warning) -> Diagnostic {
+        Diagnostic::new(
+            Level::Warning,
+            &self.path,
+            &self.content,
+            w.span,
+            w.message.clone(),
+        )
+    }
+}
+
+impl Error {
+    pub fn from_syn(path: &str, content: &str, e: syn::Error) -> Self {
+        Error::Source(
+            e.into_iter()
+                .map(|e| Diagnostic::new(Level::Error, path, content, e.span(), e.to_string()))
+                .collect(),
+        )
+    }
+}
+
+impl Diagnostic {
+    fn new(level: Level, path: &str, content: &str, span: Span, message: String) -> Self {
+        let (start, end) = (span.start(), span.end());
+        let source_line = match start.line {
+            0 => String::new(),
+            line => content
+                .lines()
+                .nth(line - 1)
+                .unwrap_or_default()
+                .to_string(),
+        };
+        let width = if end.line == start.line {
+            end.column.saturating_sub(start.column)
+        } else {
+            source_line.chars().count().saturating_sub(start.column)
+        };
+        Diagnostic {
+            level,
+            message,
+            file: path.to_string(),
+            line: start.line,
+            column: start.column + 1,
+            source_line,
+            width: width.max(1),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        };
+        writeln!(f, "{level}: {}", self.message)?;
+        if self.line == 0 {
+            return write!(f, " --> {}", self.file);
+        }
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{gutter}--> {}:{}:{}", self.file, self.line, self.column)?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(
+            f,
+            "{gutter} | {}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(self.width)
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { context, source } => write!(f, "error: {context}: {source}"),
+            Error::Json { path, source } => write!(f, "error: unable to parse {path}: {source}"),
+            Error::Invalid(message) => write!(f, "error: {message}"),
+            Error::Source(diagnostics) => {
+                for (idx, d) in diagnostics.iter().enumerate() {
+                    if idx != 0 {
+                        writeln!(f)?;
+                        writeln!(f)?;
+                    }
+                    write!(f, "{d}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            Error::Source(_) | Error::Invalid(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_location() {
+        let src = "pub struct A {\n    pub a: Vec,\n}\n";
+        let file = SourceFile::parse("user.rs", src.to_string()).unwrap();
+        let Err(Error::Source(diagnostics)) = file
+            .file
+            .convert_structs_levels()
+            .map_err(|e| file.error(e))
+        else {
+            panic!("expect source error");
+        };
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, 12));
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error: list type must have one angle bracketed argument\n --> user.rs:2:12\n  |\n2 |     pub a: Vec,\n  |            ^^^"
+        );
+    }
+}
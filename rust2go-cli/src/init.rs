@@ -0,0 +1,464 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, ValueEnum};
+
+use crate::Error;
+
+#[derive(Parser, Debug, Clone)]
+pub struct InitArgs {
+    /// Directory of the new project, it will be created if not exists
+    #[arg(default_value = ".")]
+    pub path: PathBuf,
+
+    /// Crate name, defaults to the directory name
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Async runtime used by the rust side
+    #[arg(long, value_enum, default_value_t = Runtime::Tokio)]
+    pub runtime: Runtime,
+
+    /// Use shared memory based calls(rust to go only)
+    #[arg(long, default_value = "false")]
+    pub shm: bool,
+
+    /// Calling direction
+    #[arg(long, value_enum, default_value_t = Direction::R2g)]
+    pub direction: Direction,
+
+    /// Overwrite existing files
+    #[arg(long, default_value = "false")]
+    pub force: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    Tokio,
+    Monoio,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Rust calls Go
+    R2g,
+    /// Go calls Rust
+    G2r,
+    /// Both directions
+    Bidirectional,
+}
+
+impl Direction {
+    const fn r2g(self) -> bool {
+        matches!(self, Direction::R2g | Direction::Bidirectional)
+    }
+
+    const fn g2r(self) -> bool {
+        matches!(self, Direction::G2r | Direction::Bidirectional)
+    }
+}
+
+/// Create the project layout: Cargo.toml, build.rs, src/ and go/.
+/// Existing files are kept unless `force` is set.
+pub fn init(args: &InitArgs) -> Result<(), Error> {
+    if args.shm && !args.direction.r2g() {
+        return Err(Error::Invalid(
+            "shm is only supported when rust calls go".to_string(),
+        ));
+    }
+    let io_err = |context: &str, path: &Path| {
+        let context = format!("unable to {context} {}", path.display());
+        move |source| Error::Io { context, source }
+    };
+    std::fs::create_dir_all(&args.path).map_err(io_err("create", &args.path))?;
+    let name = match &args.name {
+        Some(name) => name.clone(),
+        None => args
+            .path
+            .canonicalize()
+            .map_err(io_err("resolve", &args.path))?
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.to_string())
+            .ok_or_else(|| {
+                Error::Invalid("unable to infer crate name, set it with --name".to_string())
+            })?,
+    };
+
+    let mut files = vec![
+        ("Cargo.toml", cargo_toml(&name, args)),
+        ("src/user.rs", user_rs(args)),
+        ("go/go.mod", format!("module {name}\n\ngo 1.18\n")),
+    ];
+    if args.direction.r2g() {
+        files.push(("build.rs", BUILD_RS.to_string()));
+        files.push(("src/main.rs", main_rs(args)));
+        files.push(("go/impl.go", impl_go(args)));
+    } else {
+        files.push(("src/lib.rs", LIB_RS.to_string()));
+        files.push((
+            "go/main.go",
+            MAIN_GO.replace("{lib}", &name.replace('-', "_")),
+        ));
+    }
+
+    for (file, content) in files {
+        let path = args.path.join(file);
+        write_file(&path, &content, args.force).map_err(io_err("write", &path))?;
+    }
+
+    println!("Next steps:");
+    if args.direction.r2g() {
+        println!("  1. Edit src/user.rs to define your structs and traits.");
+        println!("  2. Run `cargo build`, go/gen.go will be generated by build.rs.");
+        println!("  3. Run `go mod tidy` in go/ and implement the interfaces in go/impl.go.");
+    } else {
+        println!("  1. Edit src/user.rs to define your structs and traits.");
+        println!("  2. Run `rust2go-cli --src src/user.rs --dst go/gen.go --without-main`.");
+        println!("  3. Run `cargo build --release` and copy the static lib into go/.");
+        println!("  4. Run `go mod tidy` and `go build .` in go/.");
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, content: &str, force: bool) -> std::io::Result<()> {
+    if path.exists() && !force {
+        println!("skip {} (already exists)", path.display());
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content)?;
+    println!("create {}", path.display());
+    Ok(())
+}
+
+fn cargo_toml(name: &str, args: &InitArgs) -> String {
+    let mut deps = String::from("rust2go = { version = \"0.4\" }\n");
+    if args.direction.r2g() {
+        match args.runtime {
+            Runtime::Tokio => deps.push_str("tokio = { version = \"1\", features = [\"full\"] }\n"),
+            Runtime::Monoio => {
+                deps.push_str("monoio = { version = \"0.2\", features = [\"sync\"] }\n")
+            }
+        }
+        if args.shm {
+            match args.runtime {
+                Runtime::Tokio => deps.push_str("rust2go-mem-ffi = { version = \"0.2\", default-features = false, features = [\"tokio\"] }\n"),
+                Runtime::Monoio => deps.push_str("rust2go-mem-ffi = { version = \"0.2\" }\n"),
+            }
+        }
+    }
+    let lib = if args.direction.r2g() {
+        ""
+    } else {
+        "\n[lib]\ncrate-type = [\"cdylib\", \"staticlib\"]\n"
+    };
+    let build_deps = if args.direction.r2g() {
+        "\n[build-dependencies]\nrust2go = { version = \"0.4\", features = [\"build\"] }\n"
+    } else {
+        ""
+    };
+    format!(
+        "[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n{lib}\n[dependencies]\n{deps}{build_deps}"
+    )
+}
+
+fn user_rs(args: &InitArgs) -> String {
+    let mut out = String::new();
+    if args.direction.r2g() {
+        out.push_str(USER_RS_BINDING);
+    }
+    out.push_str(USER_RS_STRUCTS);
+    if args.direction.r2g() {
+        out.push_str(if args.shm {
+            USER_RS_R2G_SHM
+        } else {
+            USER_RS_R2G
+        });
+    }
+    if args.direction.g2r() {
+        out.push_str(USER_RS_G2R);
+    }
+    out
+}
+
+fn main_rs(args: &InitArgs) -> String {
+    let attr = match args.runtime {
+        Runtime::Tokio => "#[tokio::main]",
+        Runtime::Monoio => "#[monoio::main(timer_enabled = true)]",
+    };
+    let mut out = MAIN_RS.replace("{runtime}", attr);
+    if args.direction.g2r() {
+        out.push_str(G2R_IMPL_RS);
+    }
+    out
+}
+
+fn impl_go(args: &InitArgs) -> String {
+    let mut out = IMPL_GO.to_string();
+    if args.direction.g2r() {
+        out = out.replace("{g2r}", "\tG2RCallImpl{}.demo_log(&user.name, &user.age)\n");
+    } else {
+        out = out.replace("{g2r}", "");
+    }
+    out
+}
+
+const BUILD_RS: &str = r#"fn main() {
+    rust2go::Builder::new()
+        .with_go_src("./go")
+        .with_regen("./src/user.rs", "./go/gen.go")
+        .build();
+}
+"#;
+
+const USER_RS_BINDING: &str = r#"// Include the generated binding file.
+pub mod binding {
+    #![allow(warnings)]
+    rust2go::r2g_include_binding!();
+}
+
+"#;
+
+const USER_RS_STRUCTS: &str = r#"// Define your own structs. You must derive `rust2go::R2G` for each struct.
+#[derive(rust2go::R2G, Clone)]
+pub struct DemoUser {
+    pub name: String,
+    pub age: u8,
+}
+
+#[derive(rust2go::R2G, Clone, Copy)]
+pub struct DemoResponse {
+    pub pass: bool,
+}
+"#;
+
+const USER_RS_R2G: &str = r#"
+// Define the call trait(Rust -> Go).
+#[rust2go::r2g]
+pub trait DemoCall {
+    fn demo_oneway(req: &DemoUser);
+    fn demo_check(req: &DemoUser) -> DemoResponse;
+    #[drop_safe]
+    async fn demo_check_async(req: DemoUser) -> DemoResponse;
+}
+"#;
+
+const USER_RS_R2G_SHM: &str = r#"
+// Define the call trait(Rust -> Go) based on shared memory.
+#[rust2go::r2g(queue_size = 4096)]
+pub trait DemoCall {
+    #[mem]
+    fn demo_oneway(req: &DemoUser);
+    #[mem]
+    #[drop_safe]
+    async fn demo_check_async(req: DemoUser) -> DemoResponse;
+}
+"#;
+
+const USER_RS_G2R: &str = r#"
+// Define the call trait(Go -> Rust).
+#[rust2go::g2r]
+pub trait G2RCall {
+    fn demo_log(name: String, age: u8);
+}
+"#;
+
+const MAIN_RS: &str = r#"mod user;
+
+use user::{DemoCall, DemoCallImpl, DemoUser};
+
+{runtime}
+async fn main() {
+    let user = DemoUser {
+        name: "rust2go".to_string(),
+        age: 1,
+    };
+    #[allow(unused_unsafe)]
+    unsafe {
+        DemoCallImpl::demo_oneway(&user)
+    };
+    let resp = DemoCallImpl::demo_check_async(user).await;
+    println!("pass: {}", resp.pass);
+}
+"#;
+
+const G2R_IMPL_RS: &str = r#"
+impl user::G2RCall for user::G2RCallImpl {
+    fn demo_log(name: String, age: u8) {
+        println!("[Rust] log user {name} and age {age}");
+    }
+}
+"#;
+
+const LIB_RS: &str = r#"mod user;
+
+impl user::G2RCall for user::G2RCallImpl {
+    fn demo_log(name: String, age: u8) {
+        println!("[Rust] log user {name} and age {age}");
+    }
+}
+"#;
+
+const IMPL_GO: &str = r#"package main
+
+import "fmt"
+
+type Demo struct{}
+
+func init() {
+	DemoCallImpl = Demo{}
+}
+
+func (Demo) demo_oneway(user *DemoUser) {
+	fmt.Printf("[Go] received name: %s, age: %d\n", user.name, user.age)
+{g2r}}
+
+func (Demo) demo_check(user *DemoUser) DemoResponse {
+	return DemoResponse{pass: user.age > 0}
+}
+
+func (Demo) demo_check_async(user *DemoUser) DemoResponse {
+	return DemoResponse{pass: user.age > 0}
+}
+"#;
+
+const MAIN_GO: &str = r#"package main
+
+/*
+// For statically link: #cgo LDFLAGS: ./lib{lib}.a
+// For dynamically link: #cgo LDFLAGS: -L. -l{lib}
+#cgo LDFLAGS: ./lib{lib}.a
+*/
+import "C"
+
+func main() {
+	user := DemoUser{
+		name: "rust2go",
+		age:  1,
+	}
+	G2RCallImpl{}.demo_log(&user.name, &user.age)
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(path: PathBuf) -> InitArgs {
+        InitArgs {
+            path,
+            name: Some("demo".to_string()),
+            runtime: Runtime::Tokio,
+            shm: false,
+            direction: Direction::R2g,
+            force: false,
+        }
+    }
+
+    #[test]
+    fn invalid_args() {
+        let root =
+            std::env::temp_dir().join(format!("rust2go_init_invalid_{}", std::process::id()));
+        let mut shm_g2r = args(root.join("shm"));
+        (shm_g2r.shm, shm_g2r.direction) = (true, Direction::G2r);
+        assert!(matches!(init(&shm_g2r), Err(Error::Invalid(_))));
+        assert!(!root.join("shm").exists());
+
+        // The project directory is a file.
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("file"), "").unwrap();
+        let Err(e) = init(&args(root.join("file"))) else {
+            panic!("expect io error");
+        };
+        assert!(e.to_string().starts_with("error: unable to create "));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    fn read(root: &Path, file: &str) -> String {
+        std::fs::read_to_string(root.join(file)).unwrap()
+    }
+
+    #[test]
+    fn r2g_layout() {
+        let root = std::env::temp_dir().join(format!("rust2go_init_r2g_{}", std::process::id()));
+        let mut r2g = args(root.join("my-app"));
+        (r2g.name, r2g.runtime) = (None, Runtime::Monoio);
+        init(&r2g).unwrap();
+        let project = &r2g.path;
+        for file in [
+            "build.rs",
+            "src/main.rs",
+            "src/user.rs",
+            "go/go.mod",
+            "go/impl.go",
+        ] {
+            assert!(project.join(file).is_file(), "{file} is not created");
+        }
+        assert!(!project.join("src/lib.rs").exists());
+        assert!(!project.join("go/main.go").exists());
+        // The name is inferred from the directory.
+        assert!(read(project, "Cargo.toml").contains("name = \"my-app\""));
+        assert!(read(project, "Cargo.toml").contains("monoio"));
+        assert!(read(project, "go/go.mod").starts_with("module my-app\n"));
+        assert!(read(project, "src/main.rs").contains("#[monoio::main(timer_enabled = true)]"));
+        assert!(!read(project, "src/user.rs").contains("g2r"));
+        assert!(!read(project, "go/impl.go").contains("G2RCallImpl"));
+
+        // Existing files are kept unless forced.
+        let mut shm = r2g.clone();
+        shm.shm = true;
+        init(&shm).unwrap();
+        assert!(!read(project, "src/user.rs").contains("#[mem]"));
+        shm.force = true;
+        init(&shm).unwrap();
+        assert!(read(project, "src/user.rs").contains("#[mem]"));
+        assert!(read(project, "Cargo.toml").contains("rust2go-mem-ffi"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn g2r_layout() {
+        let root = std::env::temp_dir().join(format!("rust2go_init_g2r_{}", std::process::id()));
+        let mut g2r = args(root.clone());
+        g2r.direction = Direction::G2r;
+        init(&g2r).unwrap();
+        for file in ["src/lib.rs", "src/user.rs", "go/go.mod", "go/main.go"] {
+            assert!(root.join(file).is_file(), "{file} is not created");
+        }
+        for file in ["build.rs", "src/main.rs", "go/impl.go"] {
+            assert!(!root.join(file).exists(), "{file} is created");
+        }
+        assert!(read(&root, "Cargo.toml").contains("crate-type = [\"cdylib\", \"staticlib\"]"));
+        assert!(!read(&root, "src/user.rs").contains("#[rust2go::r2g"));
+        assert!(read(&root, "go/main.go").contains("./libdemo.a"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn bidirectional_layout() {
+        let root = std::env::temp_dir().join(format!("rust2go_init_bi_{}", std::process::id()));
+        let mut bi = args(root.clone());
+        bi.direction = Direction::Bidirectional;
+        init(&bi).unwrap();
+        for file in [
+            "build.rs",
+            "src/main.rs",
+            "src/user.rs",
+            "go/go.mod",
+            "go/impl.go",
+        ] {
+            assert!(root.join(file).is_file(), "{file} is not created");
+        }
+        assert!(!root.join("src/lib.rs").exists());
+        assert!(!root.join("go/main.go").exists());
+        let user = read(&root, "src/user.rs");
+        assert!(user.contains("pub trait DemoCall") && user.contains("pub trait G2RCall"));
+        assert!(read(&root, "src/main.rs").contains("impl user::G2RCall for user::G2RCallImpl"));
+        assert!(read(&root, "go/impl.go").contains("G2RCallImpl{}.demo_log"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
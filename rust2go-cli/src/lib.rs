@@ -1,10 +1,42 @@
 // Copyright 2024 ihciah. All Rights Reserved.
 
-use std::io::Cursor;
+use clap::{Parser, Subcommand};
+use rust2go_common::{common::RawRsFile, g2r::RustPanicMode};
 
-use clap::Parser;
-use itertools::Itertools as _;
-use rust2go_common::common::RawRsFile;
+pub mod check;
+pub mod diagnostic;
+pub mod init;
+pub mod lint;
+pub mod schema;
+pub mod watch;
+
+use diagnostic::SourceFile;
+pub use diagnostic::{Diagnostic, Error};
+
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub generate: Option<Args>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Create a new project with build.rs, binding module and go side skeleton
+    Init(init::InitArgs),
+    /// Dump the interface model as a json schema
+    Schema(schema::SchemaArgs),
+    /// Report ABI breaking changes between two versions of the interface
+    Check(check::CheckArgs),
+    /// Regenerate go code whenever the source file changes
+    Watch(watch::WatchArgs),
+    /// Report risky trait declarations and unused structs
+    Lint(lint::LintArgs),
+}
 
 #[derive(Parser, Debug, Default, Clone)]
 #[command(author, version, about, long_about = None)]
@@ -30,36 +62,19 @@ pub struct Args {
     pub no_fmt: bool,
 }
 
-pub fn generate(args: &Args) {
+pub fn generate(args: &Args) -> Result<(), Error> {
     // Read and parse rs file.
-    let file_content = std::fs::read_to_string(&args.src).expect("Unable to read file");
-    let raw_file = RawRsFile::new(file_content);
+    let src = SourceFile::open(&args.src)?;
+    let raw_file = &src.file;
 
-    // Convert to Ref structs and write to output file.
-    let (name_mapping, ref_content) = raw_file
-        .convert_structs_to_ref()
-        .expect("Unable to convert to ref");
-    std::fs::write(&args.dst, ref_content.to_string()).expect("Unable to write file");
+    // Generate C declarations of Ref structs.
+    #[cfg(not(feature = "cbindgen"))]
+    let mut importc = raw_file.convert_structs_to_c().map_err(|e| src.error(e))?;
+    #[cfg(feature = "cbindgen")]
+    let mut importc = cbindgen_structs(&src)?;
 
-    // Convert output file with cbindgen.
-    let mut cbuilder = cbindgen::Builder::new()
-        .with_language(cbindgen::Language::C)
-        .with_src(&args.dst)
-        .with_header("// Generated by rust2go. Please DO NOT edit this C part manually.");
-    for name in name_mapping.values().map(|n| n.to_string()).sorted() {
-        cbuilder = cbuilder.include_item(name);
-    }
-    let mut output = Vec::<u8>::new();
-    cbuilder
-        .generate()
-        .expect("Unable to generate bindings")
-        .write(Cursor::new(&mut output));
-
-    // Convert headers into golang.
-    let mut importc = String::from_utf8(output).expect("Unable to convert to string");
-
-    let r2g_traits = raw_file.convert_r2g_trait().unwrap();
-    let g2r_traits = raw_file.convert_g2r_trait().unwrap();
+    let r2g_traits = raw_file.convert_r2g_trait().map_err(|e| src.error(e))?;
+    let g2r_traits = raw_file.convert_g2r_trait().map_err(|e| src.error(e))?;
     macro_rules! r2g_any {
         ($f: expr) => {
             r2g_traits.iter().any(|t| t.fns().iter().any($f))
@@ -80,19 +95,52 @@ pub fn generate(args: &Args) {
         };
     }
     let use_shm = r2g_any!(|f| f.mem_call_id().is_some());
-    let use_runtime =
-        r2g_any!(|f| f.mem_call_id().is_none()) || g2r_traits.iter().any(|t| !t.fns().is_empty());
+    let use_cancel = r2g_any!(|f| f.cancel());
+    let use_timeout = r2g_any!(|f| f.timeout_ms().is_some());
+    let use_recover = r2g_any!(|f| f.go_panic().is_some());
+    let use_callback = r2g_any!(|f| f.callback().is_some());
+    let use_stream = r2g_any!(|f| f.stream().is_some());
+    let use_rust_stream = r2g_any!(|f| f.stream_param().is_some());
+    let use_rust_panic_result = g2r_traits
+        .iter()
+        .any(|t| !t.fns().is_empty() && t.rust_panic() == RustPanicMode::Result);
+    let use_handle = !raw_file.handles().map_err(|e| src.error(e))?.is_empty();
+    let use_runtime = r2g_any!(|f| f.mem_call_id().is_none())
+        || g2r_traits.iter().any(|t| !t.fns().is_empty())
+        || use_handle;
     let use_cgocall =
         r2g_any!(|f| f.mem_call_id().is_none() && f.cgo_callback()) || g2r_any!(|f| f.cgo_call());
-    let use_asmcall =
-        r2g_any!(|f| f.mem_call_id().is_none() && !f.cgo_callback() && f.ret().is_some())
-            || g2r_any!(|f| !f.cgo_call());
+    let use_asmcall = r2g_any!(|f| f.mem_call_id().is_none()
+        && !f.cgo_callback()
+        && (f.ret().is_some()
+            || f.go_panic().is_some()
+            || f.callback().is_some()
+            || f.stream().is_some()))
+        || g2r_any!(|f| !f.cgo_call());
     if use_shm {
         importc.push_str(RawRsFile::go_shm_include());
     }
-    if g2r_traits.iter().any(|t| t.has_ret()) {
+    let use_g2r_async = g2r_traits.iter().any(|t| t.has_async());
+    let use_go_object = r2g_traits.iter().any(|t| t.has_object());
+    // Panics of g2r calls and callbacks are dropped by go side.
+    if g2r_traits.iter().any(|t| !t.fns().is_empty()) || use_callback {
         importc.push_str(RawRsFile::go_internal_drop());
     }
+    if use_g2r_async {
+        importc.push_str(RawRsFile::go_g2r_wake_include());
+    }
+    if use_handle {
+        importc.push_str(RawRsFile::go_handle_include());
+    }
+    if use_callback {
+        importc.push_str(RawRsFile::go_callback_include());
+    }
+    if use_stream {
+        importc.push_str(RawRsFile::go_stream_include());
+    }
+    if use_rust_stream {
+        importc.push_str(RawRsFile::go_rust_stream_include());
+    }
     g2r_traits.iter().for_each(|t| {
         importc.push_str(&t.to_importc());
     });
@@ -102,40 +150,113 @@ pub fn generate(args: &Args) {
         "mem_ring \"github.com/ihciah/rust2go/mem-ring\"\n\"github.com/panjf2000/ants/v2\"\n"
     );
     let import_runtime = or_empty!(use_runtime, "\"runtime\"\n");
+    let import_context = or_empty!(use_cancel || use_timeout || use_stream, "\"context\"\n");
+    let import_sync = or_empty!(
+        use_cancel || use_callback || use_stream || use_rust_stream,
+        "\"sync\"\n"
+    );
+    let import_handle = or_empty!(use_handle, "\"sync/atomic\"\n");
+    let import_timeout = or_empty!(use_timeout, "\"time\"\n");
+    let import_recover = or_empty!(use_recover, "\"fmt\"\n");
+    let import_rust_panic = or_empty!(use_rust_panic_result, "\"errors\"\n");
+    let import_cgo_handle = or_empty!(use_g2r_async || use_go_object, "\"runtime/cgo\"\n");
     let import_cgocall = or_empty!(use_cgocall, "\"github.com/ihciah/rust2go/cgocall\"\n");
     let import_asmcall = or_empty!(use_asmcall, "\"github.com/ihciah/rust2go/asmcall\"\n");
     let import_118 = or_empty!(args.go118, "\"reflect\"\n");
 
     let mut go_content = format!(
-        "package main\n\n/*\n{importc}*/\nimport \"C\"\nimport (\n\"unsafe\"\n{import_runtime}{import_118}{import_shm}\n{import_cgocall}{import_asmcall})\n"
+        "package main\n\n/*\n{importc}*/\nimport \"C\"\nimport (\n\"unsafe\"\n{import_runtime}{import_context}{import_sync}{import_handle}{import_timeout}{import_recover}{import_rust_panic}{import_cgo_handle}{import_118}{import_shm}\n{import_cgocall}{import_asmcall})\n"
     );
-    let levels = raw_file.convert_structs_levels().unwrap();
-    r2g_traits.iter().for_each(|t| {
-        go_content.push_str(&t.generate_go_interface());
-        go_content.push_str(&t.generate_go_exports(&levels));
-    });
-    go_content.push_str(
-        &raw_file
-            .convert_structs_to_go(&levels, args.go118)
-            .expect("Unable to generate go structs"),
-    );
-    if use_shm {
-        go_content.push_str(RawRsFile::go_shm_ring_init());
-    }
-    g2r_traits.iter().for_each(|t| {
-        go_content.push_str(&t.to_go(&levels));
-    });
+    let gen_go = || -> syn::Result<String> {
+        let mut out = String::new();
+        let levels = raw_file.convert_structs_levels()?;
+        let hashes = raw_file.convert_structs_hashes()?;
+        out.push_str(&raw_file.convert_consts_to_go()?);
+        if use_cancel {
+            out.push_str(RawRsFile::go_cancels());
+        }
+        if use_recover {
+            out.push_str(RawRsFile::go_recover());
+        }
+        if use_callback {
+            out.push_str(RawRsFile::go_callbacks());
+        }
+        if use_stream {
+            out.push_str(RawRsFile::go_streams());
+        }
+        if use_rust_stream {
+            out.push_str(RawRsFile::go_rust_streams());
+        }
+        for t in r2g_traits.iter() {
+            out.push_str(&t.generate_go_interface()?);
+            out.push_str(&t.generate_go_exports(&levels)?);
+            out.push_str(&t.generate_go_abi_hash(&hashes)?);
+        }
+        out.push_str(&raw_file.convert_structs_to_go(&levels, args.go118)?);
+        if use_shm {
+            out.push_str(RawRsFile::go_shm_ring_init());
+        }
+        for t in g2r_traits.iter() {
+            out.push_str(&t.to_go(&levels)?);
+        }
+        if use_g2r_async {
+            out.push_str(RawRsFile::go_g2r_wake());
+        }
+        Ok(out)
+    };
+    go_content.push_str(&gen_go().map_err(|e| src.error(e))?);
     if !args.without_main {
         go_content.push_str("func main() {}\n");
     }
 
-    std::fs::write(&args.dst, go_content).expect("Unable to write file");
+    std::fs::write(&args.dst, go_content).map_err(|source| Error::Io {
+        context: format!("unable to write {}", args.dst),
+        source,
+    })?;
 
     if !args.no_fmt {
         std::process::Command::new("go")
             .arg("fmt")
             .arg(&args.dst)
             .status()
-            .unwrap();
+            .map_err(|source| Error::Io {
+                context: "unable to run go fmt".to_string(),
+                source,
+            })?;
+    }
+    Ok(())
+}
+
+// Generate C declarations of Ref structs with cbindgen.
+#[cfg(feature = "cbindgen")]
+fn cbindgen_structs(src: &SourceFile) -> Result<String, Error> {
+    use itertools::Itertools as _;
+
+    // Convert to Ref structs and write to a temporary file.
+    let (name_mapping, ref_content) = src
+        .file
+        .convert_structs_to_ref()
+        .map_err(|e| src.error(e))?;
+    let tmp = std::env::temp_dir().join(format!("rust2go_ref_{}.rs", std::process::id()));
+    std::fs::write(&tmp, ref_content.to_string()).map_err(|source| Error::Io {
+        context: format!("unable to write {}", tmp.display()),
+        source,
+    })?;
+
+    // Convert the temporary file with cbindgen.
+    let mut cbuilder = cbindgen::Builder::new()
+        .with_language(cbindgen::Language::C)
+        .with_src(&tmp)
+        .with_header("// Generated by rust2go. Please DO NOT edit this C part manually.");
+    for name in name_mapping.values().map(|n| n.to_string()).sorted() {
+        cbuilder = cbuilder.include_item(name);
     }
+    let mut output = Vec::<u8>::new();
+    let bindings = cbuilder.generate();
+    let _ = std::fs::remove_file(&tmp);
+    bindings
+        .map_err(|e| Error::Invalid(format!("unable to generate C bindings: {e}")))?
+        .write(std::io::Cursor::new(&mut output));
+    String::from_utf8(output)
+        .map_err(|e| Error::Invalid(format!("C bindings are not valid utf-8: {e}")))
 }
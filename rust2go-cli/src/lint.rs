@@ -0,0 +1,50 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use clap::Parser;
+
+use crate::diagnostic::{Diagnostic, Error, SourceFile};
+
+#[derive(Parser, Debug, Clone)]
+pub struct LintArgs {
+    /// Path of source rust file
+    #[arg(short, long)]
+    pub src: String,
+
+    /// Exit with a non-zero code if there is any warning
+    #[arg(long, default_value = "false")]
+    pub deny_warnings: bool,
+}
+
+/// Lint the interface file and locate the warnings.
+pub fn lint_file(src: &SourceFile) -> Result<Vec<Diagnostic>, Error> {
+    let warnings = src.file.lint().map_err(|e| src.error(e))?;
+    Ok(warnings.iter().map(|w| src.warning(w)).collect())
+}
+
+/// Print warnings of the interface file.
+/// Returns false if there is any warning and warnings are denied.
+pub fn lint(args: &LintArgs) -> Result<bool, Error> {
+    let src = SourceFile::open(&args.src)?;
+    let warnings = lint_file(&src)?;
+    for w in warnings.iter() {
+        println!("{w}\n");
+    }
+    println!("{} warning(s)", warnings.len());
+    Ok(!args.deny_warnings || warnings.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_warning() {
+        let src = "pub struct A {\n    pub a: u8,\n}\n";
+        let src = SourceFile::parse("user.rs", src.to_string()).unwrap();
+        let warnings = lint_file(&src).unwrap();
+        assert_eq!(
+            warnings[0].to_string(),
+            "warning: struct `A` is not used by any r2g or g2r trait\n --> user.rs:1:12\n  |\n1 | pub struct A {\n  |            ^"
+        );
+    }
+}
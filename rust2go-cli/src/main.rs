@@ -1,9 +1,31 @@
 // Copyright 2024 ihciah. All Rights Reserved.
 
 use clap::Parser;
-use rust2go_cli::{generate, Args};
+use rust2go_cli::{
+    check::check, generate, init::init, lint::lint, schema::dump_schema, watch::watch, Cli, Command,
+};
 
 fn main() {
-    let args = Args::parse();
-    generate(&args);
+    let cli = Cli::parse();
+    let result = match (cli.command, cli.generate) {
+        (Some(Command::Init(args)), _) => init(&args),
+        (Some(Command::Schema(args)), _) => dump_schema(&args),
+        (Some(Command::Check(args)), _) => match check(&args) {
+            Ok(true) => Ok(()),
+            Ok(false) => std::process::exit(1),
+            Err(e) => Err(e),
+        },
+        (Some(Command::Watch(args)), _) => watch(&args),
+        (Some(Command::Lint(args)), _) => match lint(&args) {
+            Ok(true) => Ok(()),
+            Ok(false) => std::process::exit(1),
+            Err(e) => Err(e),
+        },
+        (None, Some(args)) => generate(&args),
+        (None, None) => unreachable!("clap requires either a subcommand or --src/--dst"),
+    };
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,341 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use clap::Parser;
+use rust2go_common::{
+    common::{ParamType, ParamTypeInner, RawRsFile},
+    g2r::RustPanicMode,
+    r2g::GoPanicMode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostic::{Error, SourceFile};
+
+/// Bump it when the schema format changes incompatibly.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Parser, Debug, Clone)]
+pub struct SchemaArgs {
+    /// Path of source rust file
+    #[arg(short, long)]
+    pub src: String,
+
+    /// Path of destination json file, print to stdout if not set
+    #[arg(short, long)]
+    pub dst: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub version: u32,
+    pub structs: Vec<StructSchema>,
+    pub r2g_traits: Vec<R2GTraitSchema>,
+    pub g2r_traits: Vec<G2RTraitSchema>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StructSchema {
+    pub name: String,
+    pub mem_type: MemType,
+    pub fields: Vec<FieldSchema>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldSchema {
+    pub name: String,
+    pub ty: TypeSchema,
+}
+
+/// Same as `rust2go::MemType`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemType {
+    Primitive,
+    SimpleWrapper,
+    Complex,
+}
+
+impl From<u8> for MemType {
+    fn from(level: u8) -> Self {
+        match level {
+            0 => MemType::Primitive,
+            1 => MemType::SimpleWrapper,
+            _ => MemType::Complex,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TypeSchema {
+    Primitive { name: String },
+    String,
+    Struct { name: String },
+    List { elem: Box<TypeSchema> },
+    Tuple { elems: Vec<TypeSchema> },
+    Handle { name: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ParamSchema {
+    pub name: String,
+    pub ty: TypeSchema,
+    pub reference: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct R2GTraitSchema {
+    pub name: String,
+    /// R2g supertraits embedded in the go interface.
+    #[serde(default)]
+    pub parents: Vec<String>,
+    pub methods: Vec<R2GMethodSchema>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct R2GMethodSchema {
+    pub name: String,
+    pub params: Vec<ParamSchema>,
+    pub ret: Option<TypeSchema>,
+    pub is_async: bool,
+    pub is_safe: bool,
+    pub mem_call_id: Option<usize>,
+    /// Dedicated ring of the shm call, 0 for the ring shared by the trait.
+    #[serde(default)]
+    pub mem_ring: usize,
+    pub drop_safe: bool,
+    pub drop_safe_ret: bool,
+    pub send: bool,
+    pub cgo_callback: bool,
+    pub go_pass_struct: bool,
+    #[serde(default)]
+    pub cancel: bool,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// How recovered go panics are surfaced, "resume" or "result".
+    #[serde(default)]
+    pub go_panic: Option<String>,
+    /// Method of the go object, which takes `&self`.
+    #[serde(default)]
+    pub receiver: bool,
+    /// Constructor of the go object, which returns `Self`.
+    #[serde(default)]
+    pub constructor: bool,
+    /// The last param taking a rust closure, with the type of its argument.
+    #[serde(default)]
+    pub callback: Option<ParamSchema>,
+    /// Item type of the returned stream.
+    #[serde(default)]
+    pub stream: Option<TypeSchema>,
+    /// The last param taking a rust stream, with the type of its item.
+    #[serde(default)]
+    pub stream_param: Option<ParamSchema>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct G2RTraitSchema {
+    pub name: String,
+    pub methods: Vec<G2RMethodSchema>,
+    /// How rust panics are surfaced to go, "resume" or "result".
+    #[serde(default)]
+    pub rust_panic: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct G2RMethodSchema {
+    pub name: String,
+    pub params: Vec<ParamSchema>,
+    pub ret: Option<TypeSchema>,
+    pub cgo: bool,
+    /// Async method, whose go export takes the handle of the waker.
+    #[serde(default)]
+    pub is_async: bool,
+}
+
+impl TypeSchema {
+    fn new(ty: &ParamType) -> syn::Result<Self> {
+        Ok(match &ty.inner {
+            ParamTypeInner::Primitive(name) => TypeSchema::Primitive {
+                name: name.to_string(),
+            },
+            ParamTypeInner::Custom(name) if name == "String" => TypeSchema::String,
+            ParamTypeInner::Custom(name) => TypeSchema::Struct {
+                name: name.to_string(),
+            },
+            ParamTypeInner::List(_, elem) => TypeSchema::List {
+                elem: Box::new(TypeSchema::new(elem)?),
+            },
+            ParamTypeInner::Tuple(elems) => TypeSchema::Tuple {
+                elems: elems
+                    .iter()
+                    .map(TypeSchema::new)
+                    .collect::<syn::Result<_>>()?,
+            },
+            ParamTypeInner::Handle(_, name) => TypeSchema::Handle {
+                name: name.to_string(),
+            },
+        })
+    }
+}
+
+impl ParamSchema {
+    fn new(p: &rust2go_common::common::Param) -> syn::Result<Self> {
+        Ok(ParamSchema {
+            name: p.name.to_string(),
+            ty: TypeSchema::new(&p.ty)?,
+            reference: p.ty.is_reference,
+        })
+    }
+}
+
+impl Schema {
+    pub fn new(raw_file: &RawRsFile) -> syn::Result<Self> {
+        let levels = raw_file.convert_structs_levels()?;
+        let mut structs = Vec::new();
+        for s in raw_file.structs() {
+            let mut fields = Vec::with_capacity(s.fields.len());
+            for field in s.fields.iter() {
+                let name = field
+                    .ident
+                    .as_ref()
+                    .ok_or_else(|| {
+                        syn::Error::new_spanned(field, "only named fields are supported")
+                    })?
+                    .to_string();
+                let ty = TypeSchema::new(&ParamType::try_from(&field.ty)?)?;
+                fields.push(FieldSchema { name, ty });
+            }
+            structs.push(StructSchema {
+                name: s.ident.to_string(),
+                mem_type: levels.get(&s.ident).copied().unwrap_or_default().into(),
+                fields,
+            });
+        }
+
+        let mut r2g_traits = Vec::new();
+        for t in raw_file.convert_r2g_trait()? {
+            let mut methods = Vec::with_capacity(t.fns().len());
+            for f in t.fns() {
+                methods.push(R2GMethodSchema {
+                    name: f.name().to_string(),
+                    params: f
+                        .params()
+                        .iter()
+                        .map(ParamSchema::new)
+                        .collect::<syn::Result<_>>()?,
+                    ret: f.ret().map(TypeSchema::new).transpose()?,
+                    is_async: f.is_async(),
+                    is_safe: f.is_safe(),
+                    mem_call_id: f.mem_call_id(),
+                    mem_ring: f.mem_ring(),
+                    drop_safe: f.drop_safe(),
+                    drop_safe_ret: f.drop_safe_ret_params(),
+                    send: f.ret_send(),
+                    cgo_callback: f.cgo_callback(),
+                    go_pass_struct: f.go_pass_struct(),
+                    cancel: f.cancel(),
+                    timeout_ms: f.timeout_ms(),
+                    go_panic: f.go_panic().map(|m| {
+                        match m {
+                            GoPanicMode::Resume => "resume",
+                            GoPanicMode::Result => "result",
+                        }
+                        .to_string()
+                    }),
+                    receiver: f.receiver(),
+                    constructor: f.constructor(),
+                    callback: f.callback().map(ParamSchema::new).transpose()?,
+                    stream: f.stream().map(TypeSchema::new).transpose()?,
+                    stream_param: f.stream_param().map(ParamSchema::new).transpose()?,
+                });
+            }
+            r2g_traits.push(R2GTraitSchema {
+                name: t.name().to_string(),
+                parents: t.parents().iter().map(|p| p.to_string()).collect(),
+                methods,
+            });
+        }
+
+        let mut g2r_traits = Vec::new();
+        for t in raw_file.convert_g2r_trait()? {
+            let mut methods = Vec::with_capacity(t.fns().len());
+            for f in t.fns() {
+                methods.push(G2RMethodSchema {
+                    name: f.name().to_string(),
+                    params: f
+                        .params()
+                        .iter()
+                        .map(ParamSchema::new)
+                        .collect::<syn::Result<_>>()?,
+                    ret: f.ret().map(TypeSchema::new).transpose()?,
+                    cgo: f.cgo_call(),
+                    is_async: f.is_async(),
+                });
+            }
+            g2r_traits.push(G2RTraitSchema {
+                name: t.name().to_string(),
+                methods,
+                rust_panic: Some(
+                    match t.rust_panic() {
+                        RustPanicMode::Resume => "resume",
+                        RustPanicMode::Result => "result",
+                    }
+                    .to_string(),
+                ),
+            });
+        }
+
+        Ok(Schema {
+            version: SCHEMA_VERSION,
+            structs,
+            r2g_traits,
+            g2r_traits,
+        })
+    }
+}
+
+pub fn dump_schema(args: &SchemaArgs) -> Result<(), Error> {
+    let src = SourceFile::open(&args.src)?;
+    let schema = Schema::new(&src.file).map_err(|e| src.error(e))?;
+    let output = serde_json::to_string_pretty(&schema).expect("Unable to serialize schema");
+    match &args.dst {
+        Some(dst) => std::fs::write(dst, output).map_err(|source| Error::Io {
+            context: format!("unable to write {dst}"),
+            source,
+        }),
+        None => {
+            println!("{output}");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_roundtrip() {
+        let raw = r#"
+        pub struct DemoUser {
+            pub name: String,
+            pub ids: Vec<Vec<u32>>,
+        }
+        #[rust2go::r2g]
+        pub trait DemoCall {
+            fn demo_oneway(req: &DemoUser);
+            #[mem]
+            #[drop_safe]
+            async fn demo_check(req: DemoUser) -> DemoUser;
+        }
+        "#;
+        let schema = Schema::new(&RawRsFile::new(raw).unwrap()).unwrap();
+        assert_eq!(schema.structs[0].mem_type, MemType::Complex);
+        let methods = &schema.r2g_traits[0].methods;
+        assert_eq!(methods[0].mem_call_id, None);
+        assert!(methods[0].params[0].reference);
+        assert_eq!(methods[1].mem_call_id, Some(0));
+        assert!(methods[1].drop_safe && methods[1].is_safe);
+
+        let json = serde_json::to_string(&schema).unwrap();
+        assert_eq!(serde_json::from_str::<Schema>(&json).unwrap(), schema);
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use clap::Parser;
+use syn::{Item, Meta};
+
+use crate::{generate, Args};
+
+#[derive(Parser, Debug, Clone)]
+pub struct WatchArgs {
+    #[command(flatten)]
+    pub generate: Args,
+
+    /// Polling interval in milliseconds
+    #[arg(long, default_value_t = 500)]
+    pub interval: u64,
+}
+
+/// Regenerate go code whenever the source file or its modules change.
+/// Errors are reported and the watcher keeps running.
+pub fn watch(args: &WatchArgs) -> ! {
+    let src = Path::new(&args.generate.src);
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut last = Vec::new();
+    loop {
+        let stamps: Vec<_> = files.iter().map(|f| modified(f)).collect();
+        if files.is_empty() || stamps != last {
+            // Modules may be added or removed, so collect them again.
+            files = watched_files(src);
+            last = files.iter().map(|f| modified(f)).collect();
+            match generate(&args.generate) {
+                Ok(()) => println!("generated {}", args.generate.dst),
+                Err(e) => eprintln!("{e}"),
+            }
+            println!("watching {} file(s) for changes...", files.len());
+        }
+        std::thread::sleep(Duration::from_millis(args.interval));
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The source file and all modules declared in it with `mod xxx;`, transitively.
+pub fn watched_files(src: &Path) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    collect_file(src, &mut out);
+    out
+}
+
+fn collect_file(file: &Path, out: &mut Vec<PathBuf>) {
+    if out.iter().any(|f| f == file) {
+        return;
+    }
+    out.push(file.to_path_buf());
+    let Ok(content) = std::fs::read_to_string(file) else {
+        return;
+    };
+    let Ok(syntax) = syn::parse_file(&content) else {
+        return;
+    };
+    let parent = file.parent().unwrap_or(Path::new(""));
+    // Child modules of mod.rs, lib.rs and main.rs live in the same directory,
+    // others live in the directory named after the file.
+    let dir = match file.file_stem().and_then(|s| s.to_str()) {
+        Some("mod" | "lib" | "main") | None => parent.to_path_buf(),
+        Some(stem) => parent.join(stem),
+    };
+    collect_items(&syntax.items, parent, &dir, out);
+}
+
+fn collect_items(items: &[Item], parent: &Path, dir: &Path, out: &mut Vec<PathBuf>) {
+    for item in items {
+        let Item::Mod(m) = item else {
+            continue;
+        };
+        let name = m.ident.to_string();
+        let path_attr = m.attrs.iter().find_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) if nv.path.is_ident("path") => match &nv.value {
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(s),
+                    ..
+                }) => Some(s.value()),
+                _ => None,
+            },
+            _ => None,
+        });
+        match (&m.content, path_attr) {
+            (Some((_, items)), _) => collect_items(items, parent, &dir.join(&name), out),
+            (None, Some(path)) => collect_file(&parent.join(path), out),
+            (None, None) => {
+                let file = dir.join(format!("{name}.rs"));
+                if file.exists() {
+                    collect_file(&file, out);
+                } else {
+                    collect_file(&dir.join(&name).join("mod.rs"), out);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_modules() {
+        let root = std::env::temp_dir().join(format!("rust2go_watch_{}", std::process::id()));
+        std::fs::create_dir_all(root.join("user/inner")).unwrap();
+        std::fs::write(root.join("user.rs"), "mod a; mod inner { mod b; }").unwrap();
+        std::fs::write(root.join("user/a.rs"), "pub struct A {}").unwrap();
+        std::fs::write(root.join("user/inner/b.rs"), "mod a;").unwrap();
+
+        let files = watched_files(&root.join("user.rs"));
+        assert_eq!(
+            files,
+            vec![
+                root.join("user.rs"),
+                root.join("user/a.rs"),
+                root.join("user/inner/b.rs"),
+                // not exists but still watched
+                root.join("user/inner/b/a/mod.rs"),
+            ]
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
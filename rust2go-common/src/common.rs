@@ -2,8 +2,11 @@
 
 use proc_macro2::{Span, TokenStream};
 use quote::{format_ident, quote, ToTokens};
-use std::collections::HashMap;
-use syn::{Error, File, Ident, Item, PathSegment, Result, Type};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use syn::{
+    Attribute, Error, Expr, ExprLit, File, Ident, Item, ItemConst, ItemStruct, ItemTrait, Lit,
+    Meta, PathSegment, Result, Type,
+};
 
 use crate::{g2r::G2RTraitRepr, r2g::R2GTraitRepr};
 
@@ -12,10 +15,23 @@ pub struct RawRsFile {
 }
 
 impl RawRsFile {
-    pub fn new<S: AsRef<str>>(src: S) -> Self {
-        let src = src.as_ref();
-        let syntax = syn::parse_file(src).expect("Unable to parse file");
-        RawRsFile { file: syntax }
+    pub fn new<S: AsRef<str>>(src: S) -> Result<Self> {
+        let syntax = syn::parse_file(src.as_ref())?;
+        Ok(RawRsFile { file: syntax })
+    }
+
+    pub fn structs(&self) -> impl Iterator<Item = &ItemStruct> {
+        self.file.items.iter().filter_map(|item| match item {
+            Item::Struct(s) => Some(s),
+            _ => None,
+        })
+    }
+
+    pub fn consts(&self) -> impl Iterator<Item = &ItemConst> {
+        self.file.items.iter().filter_map(|item| match item {
+            Item::Const(c) => Some(c),
+            _ => None,
+        })
     }
 
     pub fn go_internal_drop() -> &'static str {
@@ -24,6 +40,211 @@ const void c_rust2go_internal_drop(void*);
 "#
     }
 
+    pub fn go_handle_include() -> &'static str {
+        r#"
+void c_rust2go_handle_release(uintptr_t);
+"#
+    }
+
+    pub fn go_g2r_wake_include() -> &'static str {
+        r#"
+void rust2go_g2r_wake(uintptr_t, void*);
+"#
+    }
+
+    // Called by rust side with the output of async g2r call, the handle holds the channel of the
+    // waiting goroutine.
+    pub fn go_g2r_wake() -> &'static str {
+        "//export rust2go_g2r_wake\nfunc rust2go_g2r_wake(handle C.uintptr_t, slot unsafe.Pointer) {\nh := cgo.Handle(handle)\ndone := h.Value().(chan [3]unsafe.Pointer)\nh.Delete()\ndone <- *(*[3]unsafe.Pointer)(slot)\n}\n"
+    }
+
+    // Cancel functions of the running cancellable calls, keyed by the slot pointer.
+    pub fn go_cancels() -> &'static str {
+        "var r2gCancels sync.Map\n"
+    }
+
+    // Deferred by calls of go impl with recovered panics, the message is never empty.
+    pub fn go_recover() -> &'static str {
+        "func r2gRecover(msg *string) {\nif r := recover(); r != nil {\n*msg = fmt.Sprint(r)\nif *msg == \"\" {\n*msg = \"panic\"\n}\n}\n}\n"
+    }
+
+    pub fn go_callback_include() -> &'static str {
+        r#"
+void c_rust2go_callback_call(void*, void*, void*);
+void c_rust2go_callback_release(void*);
+"#
+    }
+
+    // Rust closure passed to a go method. Calls are serialized, and the ones after the method
+    // returns are skipped and return false, since the closure is gone or dropped if it is owned
+    // by go side. A panic of the closure is resumed on the calling goroutine.
+    pub fn go_callbacks() -> &'static str {
+        r#"
+type r2gCallback struct {
+    mu sync.Mutex
+    ptr unsafe.Pointer
+    owned bool
+}
+func (c *r2gCallback) call(callFn func(unsafe.Pointer, unsafe.Pointer, unsafe.Pointer, unsafe.Pointer), dropFn func(unsafe.Pointer, unsafe.Pointer), arg unsafe.Pointer) bool {
+    c.mu.Lock()
+    defer c.mu.Unlock()
+    if c.ptr == nil {
+        return false
+    }
+    slot := [3]unsafe.Pointer{}
+    callFn(unsafe.Pointer(C.c_rust2go_callback_call), c.ptr, arg, unsafe.Pointer(&slot))
+    if slot[2] != nil {
+        msg := ownString(*(*C.StringRef)(slot[2]))
+        dropFn(unsafe.Pointer(C.c_rust2go_internal_drop), slot[1])
+        panic("rust panicked: " + msg)
+    }
+    return true
+}
+func (c *r2gCallback) done() {
+    c.mu.Lock()
+    defer c.mu.Unlock()
+    if c.owned {
+        C.c_rust2go_callback_release(c.ptr)
+    }
+    c.ptr = nil
+}
+"#
+    }
+
+    pub fn go_stream_include() -> &'static str {
+        r#"
+void c_rust2go_stream_send(void*, void*);
+void c_rust2go_stream_close(void*);
+"#
+    }
+
+    // Streams returned to rust side, keyed by the pointer of rust side. An item is rejected if
+    // the stream is dropped, and the sender waits for rust side to wake it if the buffer is full.
+    pub fn go_streams() -> &'static str {
+        r#"
+var r2gStreams sync.Map
+type r2gStream struct {
+    ptr unsafe.Pointer
+    ready chan struct{}
+    cancel context.CancelFunc
+}
+type r2gStreamSend struct {
+    item unsafe.Pointer
+    status uint8
+}
+func (s *r2gStream) send(ctx context.Context, callFn func(unsafe.Pointer, unsafe.Pointer, unsafe.Pointer), item unsafe.Pointer) bool {
+    arg := r2gStreamSend{item: item}
+    callFn(unsafe.Pointer(C.c_rust2go_stream_send), s.ptr, unsafe.Pointer(&arg))
+    switch arg.status {
+    case 0:
+        return true
+    case 1:
+        select {
+        case <-s.ready:
+            return true
+        case <-ctx.Done():
+            return false
+        }
+    default:
+        return false
+    }
+}
+//export rust2go_stream_wake
+func rust2go_stream_wake(stream unsafe.Pointer) {
+    if s, ok := r2gStreams.Load(uintptr(stream)); ok {
+        select {
+        case s.(*r2gStream).ready <- struct{}{}:
+        default:
+        }
+    }
+}
+//export rust2go_stream_cancel
+func rust2go_stream_cancel(stream unsafe.Pointer) {
+    if s, ok := r2gStreams.Load(uintptr(stream)); ok {
+        s.(*r2gStream).cancel()
+    }
+}
+"#
+    }
+
+    pub fn go_rust_stream_include() -> &'static str {
+        r#"
+void c_rust2go_rust_stream_next(void*, void*);
+void c_rust2go_rust_stream_release(void*);
+"#
+    }
+
+    // Rust streams passed to go methods, keyed by the pointer of rust side. Items are polled by
+    // `run` one at a time when the previous one is received, and polling stops when the stream
+    // ends or the method returns, when the stream is dropped if it is owned by go side.
+    pub fn go_rust_streams() -> &'static str {
+        r#"
+var r2gRustStreams sync.Map
+type r2gRustStream struct {
+    ptr unsafe.Pointer
+    owned bool
+    ready chan struct{}
+    stopped chan struct{}
+    exited chan struct{}
+}
+type r2gRustStreamNext struct {
+    item unsafe.Pointer
+    status uint8
+}
+func newR2gRustStream(ptr unsafe.Pointer, owned bool) *r2gRustStream {
+    s := &r2gRustStream{ptr: ptr, owned: owned, ready: make(chan struct{}, 1), stopped: make(chan struct{}), exited: make(chan struct{})}
+    r2gRustStreams.Store(uintptr(ptr), s)
+    return s
+}
+func (s *r2gRustStream) next() unsafe.Pointer {
+    for {
+        arg := r2gRustStreamNext{}
+        C.c_rust2go_rust_stream_next(s.ptr, unsafe.Pointer(&arg))
+        switch arg.status {
+        case 0:
+            return arg.item
+        case 1:
+            select {
+            case <-s.ready:
+            case <-s.stopped:
+                return nil
+            }
+        default:
+            return nil
+        }
+    }
+}
+func (s *r2gRustStream) run(send func(unsafe.Pointer) bool) {
+    defer func() {
+        r2gRustStreams.Delete(uintptr(s.ptr))
+        if s.owned {
+            C.c_rust2go_rust_stream_release(s.ptr)
+        }
+        close(s.exited)
+    }()
+    for {
+        item := s.next()
+        if item == nil || !send(item) {
+            return
+        }
+    }
+}
+func (s *r2gRustStream) stop() {
+    close(s.stopped)
+    <-s.exited
+}
+//export rust2go_rust_stream_wake
+func rust2go_rust_stream_wake(stream unsafe.Pointer) {
+    if s, ok := r2gRustStreams.Load(uintptr(stream)); ok {
+        select {
+        case s.(*r2gRustStream).ready <- struct{}{}:
+        default:
+        }
+    }
+}
+"#
+    }
+
     pub fn go_shm_include() -> &'static str {
         r#"
 typedef struct QueueMeta {
@@ -41,10 +262,7 @@ typedef struct QueueMeta {
 
     pub fn go_shm_ring_init() -> &'static str {
         r#"
-        func ringsInit(crr, crw C.QueueMeta, fns []func(unsafe.Pointer, *ants.MultiPool, func(interface{}, []byte, uint))) {
-            const MULTIPOOL_SIZE = 8
-            const SIZE_PER_POOL = -1
-
+        func ringsInit(crr, crw C.QueueMeta, fns []func(unsafe.Pointer, *ants.MultiPool, func(interface{}, []byte, uint)), multipoolSize int, sizePerPool int) {
             type Storage struct {
                 resp   interface{}
                 buffer []byte
@@ -85,7 +303,10 @@ typedef struct QueueMeta {
             gw := rrq.Write()
 
             slab := mem_ring.NewMultiSlab[Storage]()
-            pool, _ := ants.NewMultiPool(MULTIPOOL_SIZE, SIZE_PER_POOL, ants.RoundRobin)
+            pool, err := ants.NewMultiPool(multipoolSize, sizePerPool, ants.RoundRobin)
+            if err != nil {
+                panic("rust2go: unable to create go pools of shm calls: " + err.Error())
+            }
 
             gr.RunHandler(func(p Payload) {
                 if p.Flag == CALL {
@@ -175,7 +396,7 @@ typedef struct QueueMeta {
                         let field_name = field
                             .clone()
                             .ident
-                            .ok_or_else(|| serr!("only named fields are supported"))?;
+                            .ok_or_else(|| serr!(&field, "only named fields are supported"))?;
                         let field_type = ParamType::try_from(&field.ty)?;
                         field_names.push(field_name);
                         field_types.push(field_type.to_rust_ref(None));
@@ -190,9 +411,117 @@ typedef struct QueueMeta {
                 _ => continue,
             }
         }
+        for tuple in self.tuples()? {
+            let name = tuple.tuple_name().unwrap();
+            let name_ref = format_ident!("{name}Ref");
+            let field_names =
+                (0..tuple.tuple_elems().unwrap().len()).map(|i| format_ident!("f{i}"));
+            let field_types = tuple
+                .tuple_elems()
+                .unwrap()
+                .iter()
+                .map(|e| e.to_rust_ref(None));
+            out.extend(quote! {
+                #[repr(C)]
+                pub struct #name_ref {
+                    #(pub #field_names: #field_types,)*
+                }
+            });
+            name_mapping.insert(name, name_ref);
+        }
         Ok((name_mapping, out))
     }
 
+    // C declarations of the Ref structs, which is the same as cbindgen output of
+    // `convert_structs_to_ref`.
+    // Structs are sorted by name and dependencies are always declared first.
+    pub fn convert_structs_to_c(&self) -> Result<String> {
+        // Ref struct name -> (fields, dependencies)
+        let mut items = BTreeMap::<String, (Vec<String>, Vec<String>)>::new();
+        items.insert(
+            "StringRef".to_string(),
+            (
+                vec![
+                    "const uint8_t *ptr".to_string(),
+                    "uintptr_t len".to_string(),
+                ],
+                vec![],
+            ),
+        );
+        items.insert(
+            "ListRef".to_string(),
+            (
+                vec!["const void *ptr".to_string(), "uintptr_t len".to_string()],
+                vec![],
+            ),
+        );
+        let add_field = |field_name: &dyn std::fmt::Display,
+                         field_type: &ParamType,
+                         (fields, deps): &mut (Vec<String>, Vec<String>)|
+         -> Result<()> {
+            match &field_type.inner {
+                ParamTypeInner::Primitive(_) | ParamTypeInner::Handle(..) => (),
+                ParamTypeInner::Custom(c)
+                    if c != "String" && !self.structs().any(|s| &s.ident == c) =>
+                {
+                    sbail!(c, format!("struct {c} not found"))
+                }
+                _ => deps.push(field_type.to_c(false)?),
+            }
+            fields.push(format!("{} {field_name}", field_type.to_c(true)?));
+            Ok(())
+        };
+        for s in self.structs() {
+            let mut item = (Vec::with_capacity(s.fields.len()), Vec::new());
+            for field in s.fields.iter() {
+                let field_name = field
+                    .ident
+                    .as_ref()
+                    .ok_or_else(|| serr!(field, "only named fields are supported"))?;
+                add_field(field_name, &ParamType::try_from(&field.ty)?, &mut item)?;
+            }
+            items.insert(format!("{}Ref", s.ident), item);
+        }
+        // Tuples are declared as structs with fields f0, f1 and so on.
+        for tuple in self.tuples()? {
+            let mut item = (Vec::new(), Vec::new());
+            for (i, elem) in tuple.tuple_elems().unwrap().iter().enumerate() {
+                add_field(&format!("f{i}"), elem, &mut item)?;
+            }
+            items.insert(tuple.to_c(false)?, item);
+        }
+
+        fn visit<'a>(
+            name: &'a str,
+            items: &'a BTreeMap<String, (Vec<String>, Vec<String>)>,
+            visited: &mut HashSet<&'a str>,
+            out: &mut String,
+        ) {
+            if !visited.insert(name) {
+                return;
+            }
+            // Dependencies are checked when collecting items.
+            let (fields, deps) = &items[name];
+            for dep in deps.iter() {
+                visit(dep, items, visited, out);
+            }
+            out.push_str(&format!("\ntypedef struct {name} {{\n"));
+            for field in fields.iter() {
+                out.push_str(&format!("  {field};\n"));
+            }
+            out.push_str(&format!("}} {name};\n"));
+        }
+
+        let mut out = String::from(
+            "// Generated by rust2go. Please DO NOT edit this C part manually.\n\n#include <stdarg.h>\n#include <stdbool.h>\n#include <stdint.h>\n#include <stdlib.h>\n",
+        );
+        let mut visited = HashSet::new();
+        for name in items.keys() {
+            visit(name, &items, &mut visited, &mut out);
+        }
+        Ok(out)
+    }
+
     // go structs define and newStruct/refStruct function impl.
     pub fn convert_structs_to_go(
         &self,
@@ -405,204 +734,480 @@ typedef struct QueueMeta {
                 // }
                 Item::Struct(s) => {
                     let struct_name = s.ident.to_string();
+                    out.push_str(&Docs::new(&s.attrs).to_go(""));
                     out.push_str(&format!("type {struct_name} struct {{\n"));
+                    let mut fields = Vec::with_capacity(s.fields.len());
                     for field in s.fields.iter() {
                         let field_name = field
                             .ident
                             .as_ref()
-                            .ok_or_else(|| serr!("only named fields are supported"))?
+                            .ok_or_else(|| serr!(field, "only named fields are supported"))?
                             .to_string();
                         let field_type = ParamType::try_from(&field.ty)?;
-                        out.push_str(&format!("    {} {}\n", field_name, field_type.to_go()));
+                        out.push_str(&Docs::new(&field.attrs).to_go("    "));
+                        out.push_str(&format!("    {} {}\n", field_name, field_type.to_go()?));
+                        fields.push((field_name, field_type));
                     }
                     out.push_str("}\n");
+                    let level = ParamType::struct_level(&s.ident, levels)?;
+                    out.push_str(&go_struct_converters(&struct_name, &fields, level, levels)?);
+                }
+                _ => continue,
+            }
+        }
+        // for example, convert (DemoUser, u32) to
+        // type Tuple_DemoUser_u32 struct {
+        //     f0 DemoUser
+        //     f1 uint32
+        // }
+        // and the same converters as structs.
+        for tuple in self.tuples()? {
+            let name = tuple.tuple_name().unwrap();
+            out.push_str(&format!("type {name} struct {{\n"));
+            let mut fields = Vec::new();
+            for (i, elem) in tuple.tuple_elems().unwrap().iter().enumerate() {
+                out.push_str(&format!("    f{i} {}\n", elem.to_go()?));
+                fields.push((format!("f{i}"), elem.clone()));
+            }
+            out.push_str("}\n");
+            let level = ParamType::struct_level(&name, levels)?;
+            out.push_str(&go_struct_converters(
+                &name.to_string(),
+                &fields,
+                level,
+                levels,
+            )?);
+        }
+        let handles = self.handles()?;
+        if !handles.is_empty() {
+            out.push_str(GO_RUST_HANDLE);
+        }
+        // for example, Handle<Session> is converted to
+        // type Session struct {
+        //     *rustHandle
+        // }
+        // and the converters, each go value holds a reference of the rust object.
+        for name in handles {
+            out.push_str(&format!("// {name} is a rust object, which is released by Close or go GC.\ntype {name} struct {{\n*rustHandle\n}}\n"));
+            out.push_str(&format!(
+                "func new{name}(p C.uintptr_t) {name} {{\nreturn {name}{{newRustHandle(p)}}\n}}\n"
+            ));
+            out.push_str(&format!("func cnt{name}(_ *{name}, _ *uint) [0]C.uintptr_t {{ return [0]C.uintptr_t{{}} }}\n"));
+            out.push_str(&format!("func ref{name}(p *{name}, _ *[]byte) C.uintptr_t {{\nreturn p.rustHandle.ref()\n}}\n"));
+        }
+        Ok(out)
+    }
 
-                    // newStruct
-                    out.push_str(&format!(
-                        "func new{struct_name}(p C.{struct_name}Ref) {struct_name}{{\nreturn {struct_name}{{\n"
-                    ));
-                    for field in s.fields.iter() {
-                        let field_name = field.ident.as_ref().unwrap().to_string();
-                        let field_type = ParamType::try_from(&field.ty)?;
-                        let (new_f, _) = field_type.c_to_go_field_converter(levels);
-                        out.push_str(&format!("{field_name}: {new_f}(p.{field_name}),\n",));
-                    }
-                    out.push_str("}\n}\n");
+    // Traits marked with #[r2g] or #[g2r].
+    fn traits_with<'a>(&'a self, mark: &'a str) -> impl Iterator<Item = &'a ItemTrait> {
+        self.file.items.iter().filter_map(move |item| match item {
+            Item::Trait(t)
+                if t.attrs
+                    .iter()
+                    .any(|attr| attr.meta.path().segments.last().unwrap().ident == mark) =>
+            {
+                Some(t)
+            }
+            _ => None,
+        })
+    }
 
-                    // ownStruct
-                    out.push_str(&format!(
-                        "func own{struct_name}(p C.{struct_name}Ref) {struct_name}{{\nreturn {struct_name}{{\n"
-                    ));
-                    for field in s.fields.iter() {
-                        let field_name = field.ident.as_ref().unwrap().to_string();
-                        let field_type = ParamType::try_from(&field.ty)?;
-                        let own_f = field_type.c_to_go_field_converter_owned();
-                        out.push_str(&format!("{field_name}: {own_f}(p.{field_name}),\n",));
-                    }
-                    out.push_str("}\n}\n");
-
-                    // cntStruct
-                    let level = *levels.get(&s.ident).unwrap();
-                    out.push_str(&format!(
-                        "func cnt{struct_name}(s *{struct_name}, cnt *uint) [0]C.{struct_name}Ref {{\n"
-                    ));
-                    let mut used = false;
-                    if level == 2 {
-                        for field in s.fields.iter() {
-                            let field_name = field.ident.as_ref().unwrap().to_string();
-                            let field_type = ParamType::try_from(&field.ty)?;
-                            let (counter_f, level) = field_type.go_to_c_field_counter(levels);
-                            if level == 2 {
-                                out.push_str(&format!("{counter_f}(&s.{field_name}, cnt)\n"));
-                                used = true;
-                            }
-                        }
-                    }
-                    if !used {
-                        out.push_str("_ = s\n_ = cnt\n");
-                    }
-                    out.push_str(&format!("return [0]C.{struct_name}Ref{{}}\n"));
-                    out.push_str("}\n");
+    pub fn r2g_traits(&self) -> impl Iterator<Item = &ItemTrait> {
+        self.traits_with("r2g")
+    }
 
-                    // refStruct
-                    out.push_str(&format!(
-                        "func ref{struct_name}(p *{struct_name}, buffer *[]byte) C.{struct_name}Ref{{\nreturn C.{struct_name}Ref{{\n"
-                    ));
-                    for field in s.fields.iter() {
-                        let field_name = field.ident.as_ref().unwrap().to_string();
-                        let field_type = ParamType::try_from(&field.ty)?;
-                        let (ref_f, _) = field_type.go_to_c_field_converter(levels);
-                        out.push_str(&format!(
-                            "{field_name}: {ref_f}(&p.{field_name}, buffer),\n",
-                        ));
-                    }
-                    out.push_str("}\n}\n");
+    pub fn g2r_traits(&self) -> impl Iterator<Item = &ItemTrait> {
+        self.traits_with("g2r")
+    }
+
+    // Tuples used by method params and returns, sorted by name and deduplicated.
+    pub fn tuples(&self) -> Result<Vec<ParamType>> {
+        let mut out = BTreeMap::new();
+        let mut add = |ty: &ParamType| {
+            if let Some(name) = ty.tuple_name() {
+                out.entry(name.to_string()).or_insert_with(|| ParamType {
+                    is_reference: false,
+                    ..ty.clone()
+                });
+            }
+        };
+        for t in self.convert_r2g_trait()? {
+            for f in t.fns() {
+                f.params().iter().for_each(|p| add(p.ty()));
+                f.ret().into_iter().for_each(&mut add);
+            }
+        }
+        for t in self.convert_g2r_trait()? {
+            for f in t.fns() {
+                f.params().iter().for_each(|p| add(p.ty()));
+                f.ret().into_iter().for_each(&mut add);
+            }
+        }
+        Ok(out.into_values().collect())
+    }
+
+    // Types of rust objects in Handle<T>, which are declared as opaque go types, sorted by name
+    // and deduplicated.
+    pub fn handles(&self) -> Result<Vec<Ident>> {
+        fn add(ty: &ParamType, out: &mut BTreeMap<String, Ident>) {
+            match &ty.inner {
+                ParamTypeInner::Handle(_, name) => {
+                    out.entry(name.to_string()).or_insert_with(|| name.clone());
                 }
-                _ => continue,
+                ParamTypeInner::List(_, elem) => add(elem, out),
+                ParamTypeInner::Tuple(elems) => elems.iter().for_each(|e| add(e, out)),
+                ParamTypeInner::Primitive(_) | ParamTypeInner::Custom(_) => (),
             }
         }
-        Ok(out)
+        let mut out = BTreeMap::new();
+        for s in self.structs() {
+            for field in s.fields.iter() {
+                add(&ParamType::try_from(&field.ty)?, &mut out);
+            }
+        }
+        for t in self.convert_r2g_trait()? {
+            for f in t.fns() {
+                f.params().iter().for_each(|p| add(p.ty(), &mut out));
+                f.ret().into_iter().for_each(|r| add(r, &mut out));
+            }
+        }
+        for t in self.convert_g2r_trait()? {
+            for f in t.fns() {
+                f.params().iter().for_each(|p| add(p.ty(), &mut out));
+                f.ret().into_iter().for_each(|r| add(r, &mut out));
+            }
+        }
+        if let Some(s) = self
+            .structs()
+            .find(|s| out.contains_key(&s.ident.to_string()))
+        {
+            sbail!(
+                &s.ident,
+                format!(
+                    "struct {} conflicts with the go type of Handle<{}>",
+                    s.ident, s.ident
+                )
+            )
+        }
+        Ok(out.into_values().collect())
     }
 
     pub fn convert_r2g_trait(&self) -> Result<Vec<R2GTraitRepr>> {
-        let out: Vec<R2GTraitRepr> = self
-            .file
-            .items
-            .iter()
-            .filter_map(|item| match item {
-                Item::Trait(t)
-                    if t.attrs
-                        .iter()
-                        .any(|attr| attr.meta.path().segments.last().unwrap().ident == "r2g") =>
-                {
-                    Some(t)
-                }
-                _ => None,
-            })
+        let mut traits = self
+            .r2g_traits()
             .map(|trat| trat.try_into())
             .collect::<Result<Vec<R2GTraitRepr>>>()?;
-        Ok(out)
+        for t in traits.iter() {
+            for parent in t.parents() {
+                match traits.iter().find(|p| p.name() == parent) {
+                    None => sbail!(
+                        parent,
+                        format!("supertrait {parent} must be a r2g trait in the same file")
+                    ),
+                    Some(p) if p.has_object() => sbail!(
+                        parent,
+                        format!("supertrait {parent} holds go objects and cannot be extended")
+                    ),
+                    Some(_) => (),
+                }
+            }
+        }
+        // Descendants of each trait, the nearest first.
+        let mut all = Vec::with_capacity(traits.len());
+        for t in traits.iter() {
+            let mut descendants: Vec<Ident> = Vec::new();
+            let mut queue = vec![t.name().clone()];
+            while let Some(name) = queue.pop() {
+                for child in traits.iter().filter(|c| c.parents().contains(&name)) {
+                    if !descendants.contains(child.name()) {
+                        descendants.push(child.name().clone());
+                        queue.insert(0, child.name().clone());
+                    }
+                }
+            }
+            all.push(descendants);
+        }
+        for (t, descendants) in traits.iter_mut().zip(all) {
+            t.set_descendants(descendants);
+        }
+        Ok(traits)
     }
 
     pub fn convert_g2r_trait(&self) -> Result<Vec<G2RTraitRepr>> {
-        let out: Vec<G2RTraitRepr> = self
-            .file
-            .items
-            .iter()
-            .filter_map(|item| match item {
-                Item::Trait(t)
-                    if t.attrs
-                        .iter()
-                        .any(|attr| attr.meta.path().segments.last().unwrap().ident == "g2r") =>
-                {
-                    Some(t)
-                }
-                _ => None,
-            })
-            .map(|trat| trat.try_into())
-            .collect::<Result<Vec<G2RTraitRepr>>>()?;
-        Ok(out)
+        self.g2r_traits().map(|trat| trat.try_into()).collect()
     }
 
     // 0->Primitive
     // 1->SimpleWrapper
     // 2->Complex
     pub fn convert_structs_levels(&self) -> Result<HashMap<Ident, u8>> {
-        enum Node {
-            List(Box<Node>),
-            NamedStruct(Ident),
-            Primitive,
-        }
-        fn type_to_node(ty: &Type) -> Result<Node> {
-            let seg = type_to_segment(ty)?;
-            match seg.ident.to_string().as_str() {
-                "Vec" => {
-                    let inside = match &seg.arguments {
-                        syn::PathArguments::AngleBracketed(ga) => match ga.args.last().unwrap() {
-                            syn::GenericArgument::Type(ty) => ty,
-                            _ => panic!("list generic must be a type"),
-                        },
-                        _ => panic!("list type must have angle bracketed arguments"),
-                    };
-                    Ok(Node::List(Box::new(type_to_node(inside)?)))
-                }
-                "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize"
-                | "bool" | "char" | "f32" | "f64" => Ok(Node::Primitive),
-                _ => Ok(Node::NamedStruct(seg.ident.clone())),
-            }
-        }
-        fn node_level(
-            node: &Node,
-            items: &HashMap<Ident, Vec<Node>>,
+        fn type_level(
+            ty: &ParamType,
+            items: &HashMap<Ident, Vec<ParamType>>,
             out: &mut HashMap<Ident, u8>,
-        ) -> u8 {
-            match node {
-                Node::List(inner) => (1 + node_level(inner, items, out)).min(2),
-                Node::NamedStruct(ident) if ident.to_string().as_str() == "String" => 1,
-                Node::NamedStruct(name) => {
-                    if let Some(lv) = out.get(name) {
-                        return *lv;
+        ) -> Result<u8> {
+            Ok(match &ty.inner {
+                ParamTypeInner::List(_, elem) => (1 + type_level(elem, items, out)?).min(2),
+                ParamTypeInner::Custom(name) if name == "String" => 1,
+                ParamTypeInner::Custom(name) => struct_level(name, items, out)?,
+                ParamTypeInner::Primitive(_) => 0,
+                ParamTypeInner::Handle(..) => 1,
+                ParamTypeInner::Tuple(elems) => {
+                    let mut lv = 0;
+                    for elem in elems.iter() {
+                        lv = lv.max(type_level(elem, items, out)?);
                     }
-                    let lv = items
-                        .get(name)
-                        .map(|nodes| {
-                            nodes
-                                .iter()
-                                .map(|n| node_level(n, items, out))
-                                .max()
-                                .unwrap_or(0)
-                        })
-                        .unwrap();
-                    out.insert(name.clone(), lv);
                     lv
                 }
-                Node::Primitive => 0,
+            })
+        }
+        fn struct_level(
+            name: &Ident,
+            items: &HashMap<Ident, Vec<ParamType>>,
+            out: &mut HashMap<Ident, u8>,
+        ) -> Result<u8> {
+            if let Some(lv) = out.get(name) {
+                return Ok(*lv);
             }
+            let Some(fields) = items.get(name) else {
+                sbail!(name, format!("struct {name} not found"))
+            };
+            let mut lv = 0;
+            for field in fields.iter() {
+                lv = lv.max(type_level(field, items, out)?);
+            }
+            out.insert(name.clone(), lv);
+            Ok(lv)
         }
-        let mut items = HashMap::<Ident, Vec<Node>>::new();
-        for item in self.file.items.iter() {
-            match item {
-                Item::Struct(s) => {
-                    let mut fields = Vec::new();
-                    for field in &s.fields {
-                        fields.push(type_to_node(&field.ty)?);
+        let mut items = HashMap::<Ident, Vec<ParamType>>::new();
+        for s in self.structs() {
+            let fields = s
+                .fields
+                .iter()
+                .map(|field| ParamType::try_from(&field.ty))
+                .collect::<Result<_>>()?;
+            items.insert(s.ident.clone(), fields);
+        }
+
+        let mut out = HashMap::new();
+        for name in items.keys() {
+            struct_level(name, &items, &mut out)?;
+        }
+        for tuple in self.tuples()? {
+            let lv = type_level(&tuple, &items, &mut out)?;
+            out.insert(tuple.tuple_name().unwrap(), lv);
+        }
+        out.insert(Ident::new("String", Span::call_site()), 1);
+        Ok(out)
+    }
+
+    // Layout hash of each struct, same as `<T as rust2go::ToRef>::LAYOUT_HASH`.
+    pub fn convert_structs_hashes(&self) -> Result<HashMap<Ident, u64>> {
+        fn struct_hash(
+            name: &Ident,
+            items: &HashMap<Ident, &ItemStruct>,
+            out: &mut HashMap<Ident, u64>,
+        ) -> Result<u64> {
+            if let Some(hash) = out.get(name) {
+                return Ok(*hash);
+            }
+            let Some(s) = items.get(name) else {
+                sbail!(name, format!("struct {name} not found"))
+            };
+            let mut children = Vec::with_capacity(s.fields.len());
+            for field in s.fields.iter() {
+                children.push(type_hash(&ParamType::try_from(&field.ty)?, items, out)?);
+            }
+            let hash = rust2go_convert::layout_hash("Struct", &children);
+            out.insert(name.clone(), hash);
+            Ok(hash)
+        }
+        fn type_hash(
+            ty: &ParamType,
+            items: &HashMap<Ident, &ItemStruct>,
+            out: &mut HashMap<Ident, u64>,
+        ) -> Result<u64> {
+            match &ty.inner {
+                ParamTypeInner::Primitive(name) => {
+                    Ok(rust2go_convert::layout_hash(&name.to_string(), &[]))
+                }
+                ParamTypeInner::Custom(name) if name == "String" => {
+                    Ok(rust2go_convert::layout_hash("String", &[]))
+                }
+                ParamTypeInner::Custom(name) => struct_hash(name, items, out),
+                ParamTypeInner::Handle(..) => Ok(rust2go_convert::layout_hash("Handle", &[])),
+                ParamTypeInner::List(_, elem) => Ok(rust2go_convert::layout_hash(
+                    "Vec",
+                    &[type_hash(elem, items, out)?],
+                )),
+                ParamTypeInner::Tuple(elems) => {
+                    let mut children = Vec::with_capacity(elems.len());
+                    for elem in elems.iter() {
+                        children.push(type_hash(elem, items, out)?);
                     }
-                    items.insert(s.ident.clone(), fields);
+                    Ok(rust2go_convert::layout_hash("Tuple", &children))
                 }
-                _ => continue,
             }
         }
 
+        let items: HashMap<Ident, &ItemStruct> =
+            self.structs().map(|s| (s.ident.clone(), s)).collect();
         let mut out = HashMap::new();
         for name in items.keys() {
-            let lv = node_level(&Node::NamedStruct(name.clone()), &items, &mut out);
-            out.insert(name.clone(), lv);
+            struct_hash(name, &items, &mut out)?;
         }
-        out.insert(Ident::new("String", Span::call_site()), 1);
+        out.insert(
+            Ident::new("String", Span::call_site()),
+            rust2go_convert::layout_hash("String", &[]),
+        );
         Ok(out)
     }
 }
 
+// Reference of a rust object held by go values of Handle<T>. Rust side takes another reference
+// when it receives the handle, so it can be closed once go side doesn't use it.
+const GO_RUST_HANDLE: &str = r#"
+type rustHandle struct {
+    ptr uintptr
+}
+func newRustHandle(p C.uintptr_t) *rustHandle {
+    h := &rustHandle{ptr: uintptr(p)}
+    runtime.SetFinalizer(h, (*rustHandle).Close)
+    return h
+}
+// Close releases the rust object, the handle can't be passed to rust after that.
+func (h *rustHandle) Close() {
+    if h == nil {
+        return
+    }
+    if p := atomic.SwapUintptr(&h.ptr, 0); p != 0 {
+        runtime.SetFinalizer(h, nil)
+        C.c_rust2go_handle_release(C.uintptr_t(p))
+    }
+}
+func (h *rustHandle) ref() C.uintptr_t {
+    if h == nil {
+        return 0
+    }
+    return C.uintptr_t(atomic.LoadUintptr(&h.ptr))
+}
+"#;
+
+// newStruct/ownStruct/cntStruct/refStruct functions of a go struct.
+fn go_struct_converters(
+    struct_name: &str,
+    fields: &[(String, ParamType)],
+    level: u8,
+    levels: &HashMap<Ident, u8>,
+) -> Result<String> {
+    let mut out = String::new();
+
+    // newStruct
+    out.push_str(&format!(
+        "func new{struct_name}(p C.{struct_name}Ref) {struct_name}{{\nreturn {struct_name}{{\n"
+    ));
+    for (field_name, field_type) in fields.iter() {
+        let (new_f, _) = field_type.c_to_go_field_converter(levels)?;
+        out.push_str(&format!("{field_name}: {new_f}(p.{field_name}),\n",));
+    }
+    out.push_str("}\n}\n");
+
+    // ownStruct
+    out.push_str(&format!(
+        "func own{struct_name}(p C.{struct_name}Ref) {struct_name}{{\nreturn {struct_name}{{\n"
+    ));
+    for (field_name, field_type) in fields.iter() {
+        let own_f = field_type.c_to_go_field_converter_owned()?;
+        out.push_str(&format!("{field_name}: {own_f}(p.{field_name}),\n",));
+    }
+    out.push_str("}\n}\n");
+
+    // cntStruct
+    out.push_str(&format!(
+        "func cnt{struct_name}(s *{struct_name}, cnt *uint) [0]C.{struct_name}Ref {{\n"
+    ));
+    let mut used = false;
+    if level == 2 {
+        for (field_name, field_type) in fields.iter() {
+            let (counter_f, level) = field_type.go_to_c_field_counter(levels)?;
+            if level == 2 {
+                out.push_str(&format!("{counter_f}(&s.{field_name}, cnt)\n"));
+                used = true;
+            }
+        }
+    }
+    if !used {
+        out.push_str("_ = s\n_ = cnt\n");
+    }
+    out.push_str(&format!("return [0]C.{struct_name}Ref{{}}\n"));
+    out.push_str("}\n");
+
+    // refStruct
+    out.push_str(&format!(
+        "func ref{struct_name}(p *{struct_name}, buffer *[]byte) C.{struct_name}Ref{{\nreturn C.{struct_name}Ref{{\n"
+    ));
+    for (field_name, field_type) in fields.iter() {
+        let (ref_f, _) = field_type.go_to_c_field_converter(levels)?;
+        out.push_str(&format!(
+            "{field_name}: {ref_f}(&p.{field_name}, buffer),\n",
+        ));
+    }
+    out.push_str("}\n}\n");
+    Ok(out)
+}
+
+// Doc comments of a rust item, carried to the generated go and rust code.
+#[derive(Default, Clone)]
+pub struct Docs(Vec<Attribute>);
+
+impl Docs {
+    pub fn new(attrs: &[Attribute]) -> Self {
+        Docs(
+            attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("doc"))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .filter_map(|attr| match &attr.meta {
+                Meta::NameValue(nv) => match &nv.value {
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(s), ..
+                    }) => Some(s.value()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .flat_map(|s| {
+                s.lines()
+                    .map(|l| l.trim_end().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    // Go line comments, each line prefixed with the indent.
+    pub fn to_go(&self, indent: &str) -> String {
+        self.lines()
+            .into_iter()
+            .map(|l| format!("{indent}//{l}\n"))
+            .collect()
+    }
+}
+
+impl ToTokens for Docs {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        for attr in self.0.iter() {
+            attr.to_tokens(tokens);
+        }
+    }
+}
+
 pub struct Param {
     pub name: Ident,
     pub ty: ParamType,
@@ -614,15 +1219,22 @@ impl Param {
     }
 }
 
+#[derive(Clone)]
 pub struct ParamType {
     pub inner: ParamTypeInner,
     pub is_reference: bool,
 }
 
+#[derive(Clone)]
 pub enum ParamTypeInner {
     Primitive(Ident),
     Custom(Ident),
-    List(Type),
+    // Vec<T> with the parsed element type.
+    List(Box<Type>, Box<ParamType>),
+    // Tuples are only allowed as method params and returns, and passed to go as structs.
+    Tuple(Vec<ParamType>),
+    // Handle<T> of a rust object, passed to go as an opaque type named T.
+    Handle(Box<Type>, Ident),
 }
 
 impl ToTokens for ParamType {
@@ -630,11 +1242,7 @@ impl ToTokens for ParamType {
         if self.is_reference {
             tokens.extend(quote! {&});
         }
-        match &self.inner {
-            ParamTypeInner::Primitive(ty) => ty.to_tokens(tokens),
-            ParamTypeInner::Custom(ty) => ty.to_tokens(tokens),
-            ParamTypeInner::List(ty) => ty.to_tokens(tokens),
-        }
+        self.to_owned_tokens().to_tokens(tokens);
     }
 }
 
@@ -654,14 +1262,41 @@ impl TryFrom<&Type> for ParamType {
             "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64" | "usize" | "isize"
             | "bool" | "char" | "f32" | "f64" => {
                 if !seg.arguments.is_none() {
-                    sbail!("primitive types with arguments are not supported")
+                    sbail!(seg, "primitive types with arguments are not supported")
                 }
                 ParamTypeInner::Primitive(seg.ident.clone())
             }
-            "Vec" => ParamTypeInner::List(ty.clone()),
+            "Vec" => {
+                let inside = match &seg.arguments {
+                    syn::PathArguments::AngleBracketed(ga) if ga.args.len() == 1 => {
+                        match ga.args.first() {
+                            Some(syn::GenericArgument::Type(ty)) => ty,
+                            _ => sbail!(ga, "list generic must be a type"),
+                        }
+                    }
+                    _ => sbail!(seg, "list type must have one angle bracketed argument"),
+                };
+                ParamTypeInner::List(Box::new(ty.clone()), Box::new(ParamType::try_from(inside)?))
+            }
+            "Handle" => {
+                let inside = match &seg.arguments {
+                    syn::PathArguments::AngleBracketed(ga) if ga.args.len() == 1 => {
+                        match ga.args.first() {
+                            Some(syn::GenericArgument::Type(ty)) => ty,
+                            _ => sbail!(ga, "handle generic must be a type"),
+                        }
+                    }
+                    _ => sbail!(seg, "handle type must have one angle bracketed argument"),
+                };
+                let name = type_to_segment(inside)?;
+                if !name.arguments.is_none() {
+                    sbail!(name, "handle of types with arguments is not supported")
+                }
+                ParamTypeInner::Handle(Box::new(ty.clone()), name.ident.clone())
+            }
             _ => {
                 if !seg.arguments.is_none() {
-                    sbail!("custom types with arguments are not supported")
+                    sbail!(seg, "custom types with arguments are not supported")
                 }
                 ParamTypeInner::Custom(seg.ident.clone())
             }
@@ -674,34 +1309,140 @@ impl TryFrom<&Type> for ParamType {
 }
 
 impl ParamType {
-    pub fn to_c(&self, with_struct: bool) -> String {
-        let struct_ = if with_struct { "struct " } else { "" };
+    // Type of method params and returns, which can also be a tuple of other types.
+    pub fn try_from_fn(ty: &Type) -> Result<Self> {
+        let (is_reference, inner) = match ty {
+            Type::Reference(r) => (true, r.elem.as_ref()),
+            ty => (false, ty),
+        };
+        let Type::Tuple(t) = inner else {
+            return ParamType::try_from(ty);
+        };
+        if t.elems.is_empty() {
+            sbail!(t, "empty tuples are not supported")
+        }
+        let mut elems = Vec::with_capacity(t.elems.len());
+        for elem in t.elems.iter() {
+            if matches!(elem, Type::Reference(_)) {
+                sbail!(elem, "references in tuples are not supported")
+            }
+            elems.push(ParamType::try_from(elem)?);
+        }
+        Ok(ParamType {
+            inner: ParamTypeInner::Tuple(elems),
+            is_reference,
+        })
+    }
+}
+
+// Rust primitive -> C type.
+fn primitive_to_c(name: &Ident) -> Result<&'static str> {
+    Ok(match name.to_string().as_str() {
+        "u8" => "uint8_t",
+        "u16" => "uint16_t",
+        "u32" => "uint32_t",
+        "u64" => "uint64_t",
+        "i8" => "int8_t",
+        "i16" => "int16_t",
+        "i32" => "int32_t",
+        "i64" => "int64_t",
+        "bool" => "bool",
+        "char" => "uint32_t",
+        "usize" => "uintptr_t",
+        "isize" => "intptr_t",
+        "f32" => "float",
+        "f64" => "double",
+        _ => sbail!(name, format!("unrecognized rust primitive type {name}")),
+    })
+}
+
+// Go helper of primitive, like newC_uint8_t.
+fn primitive_go_helper(prefix: &str, name: &Ident) -> Result<String> {
+    if name == "char" {
+        sbail!(name, "char is not supported in struct conversion yet");
+    }
+    Ok(format!("{prefix}C_{}", primitive_to_c(name)?))
+}
+
+impl ParamType {
+    // Element type of Vec<T>, None for other types.
+    pub fn list_elem(&self) -> Option<&ParamType> {
         match &self.inner {
-            ParamTypeInner::Primitive(name) => match name.to_string().as_str() {
-                "u8" => "uint8_t",
-                "u16" => "uint16_t",
-                "u32" => "uint32_t",
-                "u64" => "uint64_t",
-                "i8" => "int8_t",
-                "i16" => "int16_t",
-                "i32" => "int32_t",
-                "i64" => "int64_t",
-                "bool" => "bool",
-                "char" => "uint32_t",
-                "usize" => "uintptr_t",
-                "isize" => "intptr_t",
-                "f32" => "float",
-                "f64" => "double",
-                _ => panic!("unreconigzed rust primitive type {name}"),
+            ParamTypeInner::List(_, elem) => Some(elem),
+            _ => None,
+        }
+    }
+
+    // Element types of tuple, None for other types.
+    pub fn tuple_elems(&self) -> Option<&[ParamType]> {
+        match &self.inner {
+            ParamTypeInner::Tuple(elems) => Some(elems),
+            _ => None,
+        }
+    }
+
+    // Name of the go struct of tuple, like Tuple_DemoUser_u32 for (DemoUser, u32).
+    pub fn tuple_name(&self) -> Option<Ident> {
+        fn mangle(ty: &ParamType) -> String {
+            match &ty.inner {
+                ParamTypeInner::Primitive(name) | ParamTypeInner::Custom(name) => name.to_string(),
+                ParamTypeInner::List(_, elem) => format!("Vec{}", mangle(elem)),
+                ParamTypeInner::Tuple(elems) => elems.iter().map(mangle).collect(),
+                ParamTypeInner::Handle(_, name) => format!("Handle{name}"),
             }
-            .to_string(),
-            ParamTypeInner::Custom(c) => format!("{struct_}{c}Ref"),
-            ParamTypeInner::List(_) => format!("{struct_}ListRef"),
         }
+        let elems = self.tuple_elems()?;
+        let names: Vec<_> = elems.iter().map(mangle).collect();
+        Some(format_ident!("Tuple_{}", names.join("_")))
+    }
+
+    // Layout hash with the struct hashes from `RawRsFile::convert_structs_hashes`.
+    pub fn layout_hash(&self, hashes: &HashMap<Ident, u64>) -> Result<u64> {
+        Ok(match &self.inner {
+            ParamTypeInner::Primitive(name) => rust2go_convert::layout_hash(&name.to_string(), &[]),
+            ParamTypeInner::Custom(name) => *hashes
+                .get(name)
+                .ok_or_else(|| serr!(name, format!("struct {name} not found")))?,
+            ParamTypeInner::List(_, elem) => {
+                rust2go_convert::layout_hash("Vec", &[elem.layout_hash(hashes)?])
+            }
+            ParamTypeInner::Handle(..) => rust2go_convert::layout_hash("Handle", &[]),
+            ParamTypeInner::Tuple(elems) => rust2go_convert::layout_hash(
+                "Tuple",
+                &elems
+                    .iter()
+                    .map(|e| e.layout_hash(hashes))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+        })
     }
 
-    pub fn to_go(&self) -> String {
+    // Type tokens without the reference mark.
+    pub fn to_owned_tokens(&self) -> TokenStream {
         match &self.inner {
+            ParamTypeInner::Primitive(ty) => ty.to_token_stream(),
+            ParamTypeInner::Custom(ty) => ty.to_token_stream(),
+            ParamTypeInner::List(ty, _) | ParamTypeInner::Handle(ty, _) => ty.to_token_stream(),
+            ParamTypeInner::Tuple(elems) => {
+                let elems = elems.iter().map(|e| e.to_owned_tokens());
+                quote!((#(#elems,)*))
+            }
+        }
+    }
+
+    pub fn to_c(&self, with_struct: bool) -> Result<String> {
+        let struct_ = if with_struct { "struct " } else { "" };
+        Ok(match &self.inner {
+            ParamTypeInner::Primitive(name) => primitive_to_c(name)?.to_string(),
+            ParamTypeInner::Custom(c) => format!("{struct_}{c}Ref"),
+            ParamTypeInner::List(..) => format!("{struct_}ListRef"),
+            ParamTypeInner::Tuple(_) => format!("{struct_}{}Ref", self.tuple_name().unwrap()),
+            ParamTypeInner::Handle(..) => "uintptr_t".to_string(),
+        })
+    }
+
+    pub fn to_go(&self) -> Result<String> {
+        Ok(match &self.inner {
             ParamTypeInner::Primitive(name) => match name.to_string().as_str() {
                 "u8" => "uint8",
                 "u16" => "uint16",
@@ -717,7 +1458,7 @@ impl ParamType {
                 "isize" => "int",
                 "f32" => "float32",
                 "f64" => "float64",
-                _ => panic!("unreconigzed rust primitive type {name}"),
+                _ => sbail!(name, format!("unrecognized rust primitive type {name}")),
             }
             .to_string(),
             ParamTypeInner::Custom(c) => {
@@ -727,217 +1468,172 @@ impl ParamType {
                     _ => s,
                 }
             }
-            ParamTypeInner::List(inner) => {
-                let seg = type_to_segment(inner).unwrap();
-                let inside = match &seg.arguments {
-                    syn::PathArguments::AngleBracketed(ga) => match ga.args.last().unwrap() {
-                        syn::GenericArgument::Type(ty) => ty,
-                        _ => panic!("list generic must be a type"),
-                    },
-                    _ => panic!("list type must have angle bracketed arguments"),
-                };
+            ParamTypeInner::List(_, elem) => format!("[]{}", elem.to_go()?),
+            ParamTypeInner::Tuple(_) => self.tuple_name().unwrap().to_string(),
+            ParamTypeInner::Handle(_, name) => name.to_string(),
+        })
+    }
+
+    // Go params of a method param, tuples are flattened to one param per element.
+    // demo(req (DemoUser, u32)) -> demo(req_0 *DemoUser, req_1 *uint32)
+    pub fn to_go_params(&self, name: &Ident, star: impl std::fmt::Display) -> Result<Vec<String>> {
+        match &self.inner {
+            ParamTypeInner::Tuple(elems) => elems
+                .iter()
+                .enumerate()
+                .map(|(i, e)| Ok(format!("{name}_{i} {star}{}", e.to_go()?)))
+                .collect(),
+            _ => Ok(vec![format!("{name} {star}{}", self.to_go()?)]),
+        }
+    }
+
+    // Go args to pass a converted param to the flattened method.
+    pub fn to_go_args(&self, name: &str, ref_mark: impl std::fmt::Display) -> Vec<String> {
+        match &self.inner {
+            ParamTypeInner::Tuple(elems) => (0..elems.len())
+                .map(|i| format!("{ref_mark}{name}.f{i}"))
+                .collect(),
+            _ => vec![format!("{ref_mark}{name}")],
+        }
+    }
+
+    // Go return type of a method, tuples are returned as multiple values.
+    pub fn to_go_ret(&self) -> Result<String> {
+        match &self.inner {
+            ParamTypeInner::Tuple(elems) => Ok(format!(
+                "({})",
+                elems
+                    .iter()
+                    .map(|e| e.to_go())
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", ")
+            )),
+            _ => self.to_go(),
+        }
+    }
+
+    // Call the go method and store the return value to `name`, multiple values are collected to
+    // the tuple struct.
+    pub fn go_call_ret(&self, name: &str, call: &str) -> String {
+        match &self.inner {
+            ParamTypeInner::Tuple(elems) => {
+                let vals: Vec<_> = (0..elems.len()).map(|i| format!("{name}_{i}")).collect();
                 format!(
-                    "[]{}",
-                    ParamType::try_from(inside)
-                        .expect("unable to convert list type")
-                        .to_go()
+                    "{vals} := {call}\n{name} := {tuple}{{{vals}}}\n",
+                    vals = vals.join(", "),
+                    tuple = self.tuple_name().unwrap(),
                 )
             }
+            _ => format!("{name} := {call}\n"),
         }
     }
 
+    fn struct_level(name: &Ident, mapping: &HashMap<Ident, u8>) -> Result<u8> {
+        mapping
+            .get(name)
+            .copied()
+            .ok_or_else(|| serr!(name, format!("struct {name} not found")))
+    }
+
     // f: StructRef -> Struct
-    pub fn c_to_go_field_converter(&self, mapping: &HashMap<Ident, u8>) -> (String, u8) {
-        match &self.inner {
-            ParamTypeInner::Primitive(name) => (
-                match name.to_string().as_str() {
-                    "u8" => "newC_uint8_t",
-                    "u16" => "newC_uint16_t",
-                    "u32" => "newC_uint32_t",
-                    "u64" => "newC_uint64_t",
-                    "i8" => "newC_int8_t",
-                    "i16" => "newC_int16_t",
-                    "i32" => "newC_int32_t",
-                    "i64" => "newC_int64_t",
-                    "bool" => "newC_bool",
-                    "usize" => "newC_uintptr_t",
-                    "isize" => "newC_intptr_t",
-                    "f32" => "newC_float",
-                    "f64" => "newC_double",
-                    _ => panic!("unrecognized rust primitive type {name}"),
-                }
-                .to_string(),
-                0,
-            ),
-            ParamTypeInner::Custom(c) => (
-                format!("new{}", c.to_string().as_str()),
-                *mapping.get(c).unwrap(),
-            ),
-            ParamTypeInner::List(inner) => {
-                let seg = type_to_segment(inner).unwrap();
-                let inside = match &seg.arguments {
-                    syn::PathArguments::AngleBracketed(ga) => match ga.args.last().unwrap() {
-                        syn::GenericArgument::Type(ty) => ty,
-                        _ => panic!("list generic must be a type"),
-                    },
-                    _ => panic!("list type must have angle bracketed arguments"),
-                };
-                let (inner, inner_level) = ParamType::try_from(inside)
-                    .expect("unable to convert list type")
-                    .c_to_go_field_converter(mapping);
+    pub fn c_to_go_field_converter(&self, mapping: &HashMap<Ident, u8>) -> Result<(String, u8)> {
+        Ok(match &self.inner {
+            ParamTypeInner::Primitive(name) => (primitive_go_helper("new", name)?, 0),
+            ParamTypeInner::Handle(_, name) => (format!("new{name}"), 1),
+            ParamTypeInner::Custom(c) => (format!("new{c}"), Self::struct_level(c, mapping)?),
+            ParamTypeInner::Tuple(_) => {
+                let t = self.tuple_name().unwrap();
+                (format!("new{t}"), Self::struct_level(&t, mapping)?)
+            }
+            ParamTypeInner::List(_, elem) => {
+                let (inner, inner_level) = elem.c_to_go_field_converter(mapping)?;
                 if inner_level == 0 {
                     (format!("new_list_mapper_primitive({inner})"), 1)
                 } else {
                     (format!("new_list_mapper({inner})"), 2.min(inner_level + 1))
                 }
             }
-        }
+        })
     }
 
     // f: StructRef -> Struct with fully ownership
-    pub fn c_to_go_field_converter_owned(&self) -> String {
-        match &self.inner {
-            ParamTypeInner::Primitive(name) => match name.to_string().as_str() {
-                "u8" => "newC_uint8_t",
-                "u16" => "newC_uint16_t",
-                "u32" => "newC_uint32_t",
-                "u64" => "newC_uint64_t",
-                "i8" => "newC_int8_t",
-                "i16" => "newC_int16_t",
-                "i32" => "newC_int32_t",
-                "i64" => "newC_int64_t",
-                "bool" => "newC_bool",
-                "usize" => "newC_uintptr_t",
-                "isize" => "newC_intptr_t",
-                "f32" => "newC_float",
-                "f64" => "newC_double",
-                _ => panic!("unrecognized rust primitive type {name}"),
-            }
-            .to_string(),
-            ParamTypeInner::Custom(c) => format!("own{}", c.to_string().as_str()),
-            ParamTypeInner::List(inner) => {
-                let seg = type_to_segment(inner).unwrap();
-                let inside = match &seg.arguments {
-                    syn::PathArguments::AngleBracketed(ga) => match ga.args.last().unwrap() {
-                        syn::GenericArgument::Type(ty) => ty,
-                        _ => panic!("list generic must be a type"),
-                    },
-                    _ => panic!("list type must have angle bracketed arguments"),
-                };
-                let inner = ParamType::try_from(inside)
-                    .expect("unable to convert list type")
-                    .c_to_go_field_converter_owned();
-                format!("new_list_mapper({inner})")
+    pub fn c_to_go_field_converter_owned(&self) -> Result<String> {
+        Ok(match &self.inner {
+            ParamTypeInner::Primitive(name) => primitive_go_helper("new", name)?,
+            // Each go value holds its own reference of the rust object.
+            ParamTypeInner::Handle(_, name) => format!("new{name}"),
+            ParamTypeInner::Custom(c) => format!("own{c}"),
+            ParamTypeInner::Tuple(_) => format!("own{}", self.tuple_name().unwrap()),
+            ParamTypeInner::List(_, elem) => {
+                format!("new_list_mapper({})", elem.c_to_go_field_converter_owned()?)
             }
-        }
+        })
     }
 
-    pub fn go_to_c_field_counter(&self, mapping: &HashMap<Ident, u8>) -> (String, u8) {
-        match &self.inner {
-            ParamTypeInner::Primitive(name) => (
-                match name.to_string().as_str() {
-                    "u8" => "cntC_uint8_t",
-                    "u16" => "cntC_uint16_t",
-                    "u32" => "cntC_uint32_t",
-                    "u64" => "cntC_uint64_t",
-                    "i8" => "cntC_int8_t",
-                    "i16" => "cntC_int16_t",
-                    "i32" => "cntC_int32_t",
-                    "i64" => "cntC_int64_t",
-                    "bool" => "cntC_bool",
-                    "usize" => "cntC_uintptr_t",
-                    "isize" => "cntC_intptr_t",
-                    "f32" => "cntC_float",
-                    "f64" => "cntC_double",
-                    _ => panic!("unrecognized rust primitive type {name}"),
-                }
-                .to_string(),
-                0,
-            ),
-            ParamTypeInner::Custom(c) => (
-                format!("cnt{}", c.to_string().as_str()),
-                *mapping.get(c).unwrap(),
-            ),
-            ParamTypeInner::List(inner) => {
-                let seg = type_to_segment(inner).unwrap();
-                let inside = match &seg.arguments {
-                    syn::PathArguments::AngleBracketed(ga) => match ga.args.last().unwrap() {
-                        syn::GenericArgument::Type(ty) => ty,
-                        _ => panic!("list generic must be a type"),
-                    },
-                    _ => panic!("list type must have angle bracketed arguments"),
-                };
-                let (inner, inner_level) = ParamType::try_from(inside)
-                    .expect("unable to convert list type")
-                    .go_to_c_field_counter(mapping);
+    pub fn go_to_c_field_counter(&self, mapping: &HashMap<Ident, u8>) -> Result<(String, u8)> {
+        Ok(match &self.inner {
+            ParamTypeInner::Primitive(name) => (primitive_go_helper("cnt", name)?, 0),
+            ParamTypeInner::Handle(_, name) => (format!("cnt{name}"), 1),
+            ParamTypeInner::Custom(c) => (format!("cnt{c}"), Self::struct_level(c, mapping)?),
+            ParamTypeInner::Tuple(_) => {
+                let t = self.tuple_name().unwrap();
+                (format!("cnt{t}"), Self::struct_level(&t, mapping)?)
+            }
+            ParamTypeInner::List(_, elem) => {
+                let (inner, inner_level) = elem.go_to_c_field_counter(mapping)?;
                 if inner_level == 0 {
                     (format!("cnt_list_mapper_primitive({inner})"), 1)
                 } else {
                     (format!("cnt_list_mapper({inner})"), 2.min(inner_level + 1))
                 }
             }
-        }
+        })
     }
 
     // f: Struct -> StructRef
-    pub fn go_to_c_field_converter(&self, mapping: &HashMap<Ident, u8>) -> (String, u8) {
-        match &self.inner {
-            ParamTypeInner::Primitive(name) => (
-                match name.to_string().as_str() {
-                    "u8" => "refC_uint8_t",
-                    "u16" => "refC_uint16_t",
-                    "u32" => "refC_uint32_t",
-                    "u64" => "refC_uint64_t",
-                    "i8" => "refC_int8_t",
-                    "i16" => "refC_int16_t",
-                    "i32" => "refC_int32_t",
-                    "i64" => "refC_int64_t",
-                    "bool" => "refC_bool",
-                    "usize" => "refC_uintptr_t",
-                    "isize" => "refC_intptr_t",
-                    "f32" => "refC_float",
-                    "f64" => "refC_double",
-                    _ => panic!("unreconigzed rust primitive type {name}"),
-                }
-                .to_string(),
-                0,
-            ),
-            ParamTypeInner::Custom(c) => (
-                format!("ref{}", c.to_string().as_str()),
-                *mapping.get(c).unwrap(),
-            ),
-            ParamTypeInner::List(inner) => {
-                let seg = type_to_segment(inner).unwrap();
-                let inside = match &seg.arguments {
-                    syn::PathArguments::AngleBracketed(ga) => match ga.args.last().unwrap() {
-                        syn::GenericArgument::Type(ty) => ty,
-                        _ => panic!("list generic must be a type"),
-                    },
-                    _ => panic!("list type must have angle bracketed arguments"),
-                };
-                let (inner, inner_level) = ParamType::try_from(inside)
-                    .expect("unable to convert list type")
-                    .go_to_c_field_converter(mapping);
+    pub fn go_to_c_field_converter(&self, mapping: &HashMap<Ident, u8>) -> Result<(String, u8)> {
+        Ok(match &self.inner {
+            ParamTypeInner::Primitive(name) => (primitive_go_helper("ref", name)?, 0),
+            ParamTypeInner::Handle(_, name) => (format!("ref{name}"), 1),
+            ParamTypeInner::Custom(c) => (format!("ref{c}"), Self::struct_level(c, mapping)?),
+            ParamTypeInner::Tuple(_) => {
+                let t = self.tuple_name().unwrap();
+                (format!("ref{t}"), Self::struct_level(&t, mapping)?)
+            }
+            ParamTypeInner::List(_, elem) => {
+                let (inner, inner_level) = elem.go_to_c_field_converter(mapping)?;
                 if inner_level == 0 {
                     (format!("ref_list_mapper_primitive({inner})"), 1)
                 } else {
                     (format!("ref_list_mapper({inner})"), 2.min(inner_level + 1))
                 }
             }
-        }
+        })
     }
 
     pub fn to_rust_ref(&self, prefix: Option<&TokenStream>) -> TokenStream {
         match &self.inner {
             ParamTypeInner::Primitive(name) => quote!(#name),
+            ParamTypeInner::Handle(..) => quote!(usize),
             ParamTypeInner::Custom(name) => {
                 let ident = format_ident!("{}Ref", name);
                 quote!(#prefix #ident)
             }
-            ParamTypeInner::List(_) => {
+            ParamTypeInner::List(..) => {
                 let ident = format_ident!("ListRef");
                 quote!(#prefix #ident)
             }
+            // Ref of tuples is defined by rust2go, only the C declaration is named.
+            ParamTypeInner::Tuple(_) => match prefix {
+                Some(prefix) => {
+                    let ident = format_ident!("{}Ref", self.tuple_name().unwrap());
+                    quote!(#prefix #ident)
+                }
+                None => {
+                    let owned = self.to_owned_tokens();
+                    quote!(<#owned as ::rust2go::FromRef>::Ref)
+                }
+            },
         }
     }
 }
@@ -945,16 +1641,16 @@ impl ParamType {
 pub(crate) fn type_to_segment(ty: &Type) -> Result<&PathSegment> {
     let field_type = match ty {
         Type::Path(p) => p,
-        _ => sbail!("only path types are supported"),
+        _ => sbail!(ty, "only path types are supported"),
     };
     let path = &field_type.path;
     // Leading colon is not allow
     if path.leading_colon.is_some() {
-        sbail!("types with leading colons are not supported");
+        sbail!(path, "types with leading colons are not supported");
     }
     // We only accept single-segment path
     if path.segments.len() != 1 {
-        sbail!("types with multiple segments are not supported");
+        sbail!(path, "types with multiple segments are not supported");
     }
     Ok(path.segments.first().unwrap())
 }
@@ -976,7 +1672,7 @@ mod tests {
             fn demo_check_async(req: DemoRequest) -> impl std::future::Future<Output = DemoResponse>;
         }
         "#;
-        let raw_file = super::RawRsFile::new(raw);
+        let raw_file = super::RawRsFile::new(raw).unwrap();
         let traits = raw_file.convert_r2g_trait().unwrap();
         let levels = raw_file.convert_structs_levels().unwrap();
 
@@ -985,10 +1681,824 @@ mod tests {
             raw_file.convert_structs_to_go(&levels, false).unwrap()
         );
         for trait_ in traits {
-            println!("if gen: {}", trait_.generate_go_interface());
-            println!("go export gen: {}", trait_.generate_go_exports(&levels));
+            println!("if gen: {}", trait_.generate_go_interface().unwrap());
+            println!(
+                "go export gen: {}",
+                trait_.generate_go_exports(&levels).unwrap()
+            );
         }
         let levels = raw_file.convert_structs_levels().unwrap();
         levels.iter().for_each(|f| println!("{}: {}", f.0, f.1));
     }
+    #[test]
+    fn c_structs_order() {
+        let raw = r#"
+        pub struct DemoUser {
+            pub name: String,
+            pub age: u8,
+        }
+        pub struct A {
+            pub user: DemoUser,
+            pub ids: Vec<u32>,
+        }
+        "#;
+        let c = super::RawRsFile::new(raw)
+            .unwrap()
+            .convert_structs_to_c()
+            .unwrap();
+        let pos = |name: &str| c.find(&format!("typedef struct {name} ")).unwrap();
+        assert!(pos("StringRef") < pos("DemoUserRef"));
+        assert!(pos("DemoUserRef") < pos("ARef"));
+        assert!(pos("ListRef") < pos("ARef"));
+        assert!(c.contains(
+            "typedef struct ARef {\n  struct DemoUserRef user;\n  struct ListRef ids;\n} ARef;\n"
+        ));
+    }
+    #[test]
+    fn go_docs() {
+        let raw = r#"
+        /// A user.
+        pub struct DemoUser {
+            /// Name of the user.
+            /// Not empty.
+            pub name: String,
+        }
+        /** Demo calls. */
+        #[r2g]
+        pub trait DemoCall {
+            /// Check the user.
+            fn demo_check(req: DemoUser);
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let structs = raw_file.convert_structs_to_go(&levels, false).unwrap();
+        assert!(structs.contains(
+            "// A user.\ntype DemoUser struct {\n    // Name of the user.\n    // Not empty.\n    name string\n}\n"
+        ));
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        assert_eq!(
+            traits[0].generate_go_interface().unwrap(),
+            "var DemoCallImpl DemoCall\n// Demo calls.\ntype DemoCall interface {\n// Check the user.\ndemo_check(req *DemoUser) \n}\n"
+        );
+    }
+
+    #[test]
+    fn trait_defaults() {
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[rust2go::r2g(binding, mem, send, drop_safe)]
+        pub trait DemoCall {
+            async fn demo_mem(req: User) -> User;
+            #[no_mem]
+            #[no_send]
+            async fn demo_plain(req: User) -> User;
+            #[no_drop_safe]
+            async fn demo_ref(req: &User) -> User;
+        }
+        "#;
+        let traits = super::RawRsFile::new(raw)
+            .unwrap()
+            .convert_r2g_trait()
+            .unwrap();
+        let fns = traits[0].fns();
+        assert_eq!(
+            fns.iter().map(|f| f.mem_call_id()).collect::<Vec<_>>(),
+            [Some(0), None, Some(1)]
+        );
+        assert_eq!(
+            fns.iter().map(|f| f.ret_send()).collect::<Vec<_>>(),
+            [true, false, true]
+        );
+        assert_eq!(
+            fns.iter().map(|f| f.is_safe()).collect::<Vec<_>>(),
+            [true, true, false]
+        );
+
+        let raw = raw.replace("#[no_drop_safe]", "");
+        let Err(e) = super::RawRsFile::new(raw).unwrap().convert_r2g_trait() else {
+            panic!("expect drop_safe error");
+        };
+        assert_eq!(
+            e.to_string(),
+            "drop_safe function cannot have reference parameters, opt out with #[no_drop_safe]"
+        );
+
+        let parse = |args: &str| crate::r2g::R2GTraitArgs::parse(args.parse().unwrap());
+        for (args, binding) in [
+            ("binding, mem", "binding"),
+            ("crate::ffi", "crate :: ffi"),
+            ("binding = ffi", "ffi"),
+        ] {
+            let binding_args = parse(args).unwrap().binding;
+            assert_eq!(quote::quote!(#binding_args).to_string(), binding);
+        }
+        let Err(e) = parse("binding, mme") else {
+            panic!("expect unknown flag error");
+        };
+        assert!(e
+            .to_string()
+            .starts_with("unknown r2g flag `mme`, expect one of drop_safe, drop_safe_ret, send,"));
+    }
+
+    #[test]
+    fn shm_rings() {
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[r2g(queue_size = 1024, go_pools = 4)]
+        pub trait DemoCall {
+            #[mem]
+            fn demo_oneway(req: &User);
+            #[mem(queue_size = 65536, go_pool_size = 16)]
+            fn demo_hot(req: &User);
+            #[mem]
+            #[drop_safe]
+            async fn demo_check(req: User) -> User;
+            #[mem(dedicated)]
+            #[drop_safe]
+            async fn demo_large(req: User) -> User;
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        let rings = traits[0].shm_rings();
+        let summary = rings
+            .iter()
+            .map(|r| {
+                let fns = r.fns.iter().map(|f| f.name().to_string()).collect();
+                (r.init_fn.as_str(), r.config.queue_size(), fns)
+            })
+            .collect::<Vec<(_, _, Vec<String>)>>();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "RingsInitDemoCall",
+                    1024,
+                    vec!["demo_oneway".to_string(), "demo_check".to_string()]
+                ),
+                (
+                    "RingsInitDemoCall_demo_hot",
+                    65536,
+                    vec!["demo_hot".to_string()]
+                ),
+                (
+                    "RingsInitDemoCall_demo_large",
+                    1024,
+                    vec!["demo_large".to_string()]
+                ),
+            ]
+        );
+
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let exports = traits[0].generate_go_exports(&levels).unwrap();
+        assert!(exports.contains("func RingsInitDemoCall(crr, crw C.QueueMeta, goPools, goPoolSize C.int64_t) {\nringsInit(crr, crw, []func(unsafe.Pointer, *ants.MultiPool, func(interface{}, []byte, uint)){ringHandleDemoCall0,ringHandleDemoCall2}, int(goPools), int(goPoolSize))\n}"));
+        assert!(exports.contains("func RingsInitDemoCall_demo_hot(crr, crw C.QueueMeta, goPools, goPoolSize C.int64_t) {\nringsInit(crr, crw, []func(unsafe.Pointer, *ants.MultiPool, func(interface{}, []byte, uint)){ringHandleDemoCall1}"));
+        assert_eq!(
+            (
+                rings[1].name.as_str(),
+                rings[1].config.go_pools(),
+                rings[1].config.go_pool_size()
+            ),
+            ("DemoCall::demo_hot", 4, 16)
+        );
+        let rs = traits[0].generate_rs(None).unwrap().to_string();
+        assert!(rs.contains(
+            ":: rust2go :: Rust2goShmConfig :: resolve (\"DemoCall::demo_hot\") . unwrap_or_else (| e | panic ! (\"unable to init shm ring {}: {e}\" , \"DemoCall::demo_hot\"))"
+        ));
+        assert!(rs.contains("config . queue_size . unwrap_or (65536usize)"));
+
+        for (from, to) in [
+            ("queue_size = 1024,", "queue_size = 0,"),
+            ("go_pools = 4", "go_pools = 0"),
+            ("go_pool_size = 16", "go_pool_size = 0"),
+        ] {
+            let raw_file = super::RawRsFile::new(raw.replacen(from, to, 1)).unwrap();
+            let Err(e) = raw_file.convert_r2g_trait() else {
+                panic!("expect error of {to}");
+            };
+            assert_eq!(e.to_string(), "shm settings must be positive");
+        }
+    }
+
+    #[test]
+    fn abi_checks() {
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[r2g]
+        pub trait DemoCall {
+            #[mem]
+            fn demo_oneway(req: &User);
+            #[mem]
+            #[drop_safe]
+            async fn demo_check(req: User) -> User;
+            fn demo_plain(req: &User);
+            async fn demo_async(req: User) -> User;
+        }
+        "#;
+        let traits = super::RawRsFile::new(raw)
+            .unwrap()
+            .convert_r2g_trait()
+            .unwrap();
+        let rs = traits[0].generate_rs(None).unwrap().to_string();
+        // Shm calls are checked once per ring, out of the hot path.
+        assert_eq!(rs.matches("Self :: verify ()").count(), 2);
+        assert_eq!(rs.matches("DemoCallImpl :: verify ()").count(), 1);
+        assert!(
+            rs.contains("if Self :: ABI_HASH == :: rust2go :: UNKNOWN_LAYOUT { return Ok (()) ; }")
+        );
+    }
+
+    #[test]
+    fn tuples() {
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[r2g]
+        pub trait DemoCall {
+            fn demo_pair(req: (User, u32)) -> (User, Vec<u8>);
+        }
+        #[g2r]
+        pub trait DemoBack {
+            fn back(pair: (User, u32)) -> (u32, String);
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let tuples = raw_file.tuples().unwrap();
+        let names: Vec<_> = tuples
+            .iter()
+            .map(|t| t.tuple_name().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            ["Tuple_User_Vecu8", "Tuple_User_u32", "Tuple_u32_String"]
+        );
+        let c = raw_file.convert_structs_to_c().unwrap();
+        assert!(c.contains(
+            "typedef struct Tuple_User_u32Ref {\n  struct UserRef f0;\n  uint32_t f1;\n}"
+        ));
+
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        let interface = traits[0].generate_go_interface().unwrap();
+        assert!(interface.contains("demo_pair(req_0 *User, req_1 *uint32) (User, []uint8)"));
+        let exports = traits[0].generate_go_exports(&levels).unwrap();
+        assert!(exports.contains("resp_0, resp_1 := DemoCallImpl.demo_pair(&_new_req.f0, &_new_req.f1)\nresp := Tuple_User_Vecu8{resp_0, resp_1}\n"));
+
+        let g2r = raw_file.convert_g2r_trait().unwrap();
+        let go = g2r[0].to_go(&levels).unwrap();
+        assert!(go.contains("back(pair_0 *User,pair_1 *uint32) (uint32, string)"));
+        assert!(go.contains("pair := &Tuple_User_u32{*pair_0, *pair_1}\n"));
+        assert!(go.contains("return val.f0, val.f1"));
+
+        // tuples are not allowed in struct fields
+        let raw = "pub struct A { pub pair: (u8, u8) }";
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        assert!(raw_file.convert_structs_levels().is_err());
+    }
+
+    #[test]
+    fn supertraits() {
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[r2g]
+        pub trait Users: Send {
+            fn get(req: User) -> User;
+        }
+        #[r2g]
+        pub trait Audit {
+            fn log(req: User);
+        }
+        #[r2g]
+        pub trait Admin: Users + Audit {
+            fn ban(req: User);
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        assert_eq!(traits[2].parents(), ["Users", "Audit"]);
+
+        let admin = traits[2].generate_go_interface().unwrap();
+        assert!(admin.contains("type Admin interface {\nUsers\nAudit\nban(req *User) \n}"));
+        let users = traits[0].generate_go_interface().unwrap();
+        assert!(users.contains("func implUsers() Users {\nif UsersImpl != nil {\nreturn UsersImpl\n}\nif AdminImpl != nil {\nreturn AdminImpl\n}\nreturn UsersImpl\n}"));
+        let exports = traits[0].generate_go_exports(&levels).unwrap();
+        assert!(exports.contains("resp := implUsers().get(&_new_req)"));
+
+        let rs = traits[2].generate_rs(None).unwrap().to_string();
+        assert!(rs.contains("__rust2go_r2g_Users ! (AdminImpl)"));
+        assert!(rs.contains("__rust2go_r2g_Audit ! ($ impl_struct)"));
+
+        let raw = "#[r2g] pub trait Admin: Users {}";
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        assert!(raw_file.convert_r2g_trait().is_err());
+    }
+
+    #[test]
+    fn cancel() {
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[r2g(cancel)]
+        pub trait DemoCall {
+            #[drop_safe]
+            async fn demo_check(req: User) -> User;
+            fn demo_sync(req: User) -> User;
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        assert!(traits[0].fns()[0].cancel() && !traits[0].fns()[1].cancel());
+
+        let interface = traits[0].generate_go_interface().unwrap();
+        assert!(interface.contains(
+            "demo_check(ctx context.Context, req *User) User\ndemo_sync(req *User) User\n"
+        ));
+        let exports = traits[0].generate_go_exports(&levels).unwrap();
+        assert!(exports.contains("r2gCancels.Store(uintptr(unsafe.Pointer(slot)), _cancel)\n    go func() {\nresp := DemoCallImpl.demo_check(_ctx, &_new_req)\nr2gCancels.Delete(uintptr(unsafe.Pointer(slot)))\n_cancel()\n"));
+        assert!(exports.contains("func CDemoCall_demo_check_cancel(slot *C.void) {"));
+
+        let rs = traits[0].generate_rs(None).unwrap().to_string();
+        assert!(rs.contains("with_cancel"));
+        assert!(rs.contains("binding :: CDemoCall_demo_check_cancel"));
+
+        let raw =
+            "#[r2g(mem, cancel)] pub trait DemoCall { async fn demo_check(req: u32) -> u32; }";
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        assert!(raw_file.convert_r2g_trait().is_err());
+    }
+
+    #[test]
+    fn timeouts() {
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[r2g(timeout_ms = 500)]
+        pub trait DemoCall {
+            #[drop_safe]
+            async fn demo_check(req: User) -> User;
+            #[timeout_ms = 20]
+            async fn demo_fast(req: &User) -> User;
+            #[no_timeout_ms]
+            async fn demo_slow(req: &User) -> User;
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        let timeouts: Vec<_> = traits[0].fns().iter().map(|f| f.timeout_ms()).collect();
+        assert_eq!(timeouts, [Some(500), Some(20), None]);
+
+        let interface = traits[0].generate_go_interface().unwrap();
+        assert!(interface.contains("demo_check(ctx context.Context, req *User) User\n"));
+        assert!(interface.contains("demo_slow(req *User) User\n"));
+        let exports = traits[0].generate_go_exports(&levels).unwrap();
+        assert!(exports.contains("func CDemoCall_demo_check(req C.UserRef, _timeout C.int64_t, slot *C.void, cb *C.void) {\n_new_req := ownUser(req)\n_ctx, _cancel := context.WithTimeout(context.Background(), time.Duration(_timeout))\n"));
+        assert!(exports.contains("case <-_ctx.Done():\n_cancel()\nasmcall.CallFuncG0P2(unsafe.Pointer(cb), nil, unsafe.Pointer(slot))\n"));
+
+        let rs = traits[0].generate_rs(None).unwrap().to_string();
+        assert!(rs.contains("Self :: demo_check_with_timeout (req , :: std :: time :: Duration :: from_millis (500u64))"));
+        assert!(rs.contains("pub unsafe fn demo_fast_with_timeout < 'a > (req : & 'a User , timeout : :: std :: time :: Duration)"));
+        assert!(!rs.contains("fn demo_slow_with_timeout"));
+        assert!(rs.contains("Err (:: rust2go :: DeadlineExceeded)"));
+
+        let raw = "#[r2g(mem)] pub trait DemoCall { #[timeout_ms = 1] async fn demo_check(req: u32) -> u32; }";
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        assert!(raw_file.convert_r2g_trait().is_err());
+    }
+
+    #[test]
+    fn go_panics() {
+        use crate::r2g::GoPanicMode::Result;
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[r2g(go_panic = "result")]
+        pub trait DemoCall {
+            fn demo_check(req: User) -> User;
+            fn demo_oneway(req: User);
+            #[mem]
+            async fn demo_shm(req: &User) -> User;
+            #[mem]
+            fn demo_shm_oneway(req: &User);
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        let modes: Vec<_> = traits[0].fns().iter().map(|f| f.go_panic()).collect();
+        assert_eq!(modes, [Some(Result), Some(Result), Some(Result), None]);
+
+        let exports = traits[0].generate_go_exports(&levels).unwrap();
+        assert!(exports.contains("resp, _panic := func() (_resp User, _panic string) {\ndefer r2gRecover(&_panic)\nresp := DemoCallImpl.demo_check(&_new_req)\nreturn resp, \"\"\n}()\n"));
+        assert!(exports
+            .contains("func CDemoCall_demo_oneway(req C.UserRef, slot *C.void, cb *C.void) {\n"));
+        assert!(exports.contains("post_func(_panic, buffer, 0)\n"));
+
+        let rs = traits[0].generate_rs(None).unwrap().to_string();
+        assert!(rs.contains("fn demo_check (req : User) -> :: std :: result :: Result < User , :: rust2go :: GoPanic >"));
+        assert!(rs.contains("fn demo_oneway (req : User) -> :: std :: result :: Result < () , :: rust2go :: GoPanic >"));
+        assert!(rs.contains(":: rust2go :: RecoveredRef < UserRef >"));
+
+        let raw =
+            r#"#[r2g(go_panic = "resume")] pub trait DemoCall { fn demo_check(req: u32) -> u32; }"#;
+        let traits = super::RawRsFile::new(raw)
+            .unwrap()
+            .convert_r2g_trait()
+            .unwrap();
+        let rs = traits[0].generate_rs(None).unwrap().to_string();
+        assert!(rs.contains("fn demo_check (req : u32) -> u32"));
+        assert!(rs.contains(":: rust2go :: ResumeGoPanic :: resume (slot . take () . unwrap ())"));
+
+        let raw =
+            r#"#[r2g(go_panic = "abort")] pub trait DemoCall { fn demo_check(req: u32) -> u32; }"#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        assert!(raw_file.convert_r2g_trait().is_err());
+    }
+
+    #[test]
+    fn go_objects() {
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[r2g]
+        pub trait DemoClient {
+            fn new(cfg: User) -> Self;
+            fn get(&self, req: User) -> User;
+        }
+        #[r2g]
+        pub trait DemoChild: DemoClient {}
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        assert!(raw_file.convert_r2g_trait().is_err());
+
+        let raw = raw.replace("#[r2g]\n        pub trait DemoChild: DemoClient {}", "");
+        let raw_file = super::RawRsFile::new(&raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        assert!(traits[0].has_object());
+
+        let interface = traits[0].generate_go_interface().unwrap();
+        assert!(interface.contains("var DemoClientImpl DemoClientStatic\n"));
+        assert!(interface.contains("new(cfg *User) DemoClient\n"));
+        let exports = traits[0].generate_go_exports(&levels).unwrap();
+        assert!(
+            exports.contains("return C.uintptr_t(cgo.NewHandle(DemoClientImpl.new(&_new_cfg)))\n")
+        );
+        assert!(exports.contains("func CDemoClient_get(_handle C.uintptr_t, req C.UserRef, slot *C.void, cb *C.void) {\n_self := cgo.Handle(_handle).Value().(DemoClient)\n"));
+        assert!(exports.contains("cgo.Handle(_handle).Delete()\n"));
+
+        let rs = traits[0].generate_rs(None).unwrap().to_string();
+        assert!(rs.contains("pub struct DemoClientImpl { handle : :: rust2go :: GoHandle }"));
+        assert!(
+            rs.contains(":: rust2go :: GoHandle :: new (handle , binding :: CDemoClient_release)")
+        );
+        assert!(rs.contains("fn get (& self , req : User) -> User"));
+        assert!(rs.contains("binding :: CDemoClient_get (self . handle . raw () ,"));
+
+        let raw = "#[r2g] pub trait DemoClient { async fn new(cfg: u32) -> Self; }";
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        assert!(raw_file.convert_r2g_trait().is_err());
+    }
+
+    #[test]
+    fn handles() {
+        let raw = r#"
+        pub struct Session {
+            pub id: u64,
+        }
+        #[g2r]
+        pub trait DemoSession {
+            fn id(session: Handle<Session>) -> u64;
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        assert!(raw_file.handles().is_err());
+
+        let raw = raw.replace("Session {\n            pub id: u64,\n        }", "User {}");
+        let raw = raw.replace(
+            "-> u64",
+            "-> u64;\n            fn open(user: User) -> Handle<Session>",
+        );
+        let raw_file = super::RawRsFile::new(&raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        assert_eq!(raw_file.handles().unwrap()[0], "Session");
+        let go = raw_file.convert_structs_to_go(&levels, false).unwrap();
+        assert!(go.contains("type Session struct {\n*rustHandle\n}\n"));
+        assert!(go.contains("func newSession(p C.uintptr_t) Session {\n"));
+        assert!(go.contains("func refSession(p *Session, _ *[]byte) C.uintptr_t {\n"));
+
+        let g2r = raw_file.convert_g2r_trait().unwrap();
+        let go = g2r[0].to_go(&levels).unwrap();
+        assert!(go.contains(
+            "func (_self Session) id() uint64 {\nreturn DemoSessionImpl{}.id(&_self)\n}\n"
+        ));
+        assert!(go.contains("runtime.KeepAlive(session)\n"));
+        assert!(go.contains("open(user *User) Session {"));
+    }
+
+    // Error of converting the r2g traits with the first `from` replaced by `to`.
+    fn r2g_error(raw: &str, from: &str, to: &str) -> String {
+        let raw_file = super::RawRsFile::new(raw.replacen(from, to, 1)).unwrap();
+        match raw_file.convert_r2g_trait() {
+            Ok(_) => panic!("expect error of {to}"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn callbacks() {
+        let raw = r#"
+        pub struct Event {
+            pub name: String,
+        }
+        #[r2g]
+        pub trait DemoScan {
+            fn scan(dir: String, progress: impl FnMut(Event) + Send) -> u32;
+            #[drop_safe]
+            async fn scan_async(dir: String, progress: Box<dyn FnMut(Event) + Send>) -> u32;
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        assert_eq!(traits[0].fns()[0].callback().unwrap().name, "progress");
+
+        let interface = traits[0].generate_go_interface().unwrap();
+        assert!(interface.contains("scan(dir *string, progress func(Event) bool) uint32\n"));
+        let exports = traits[0].generate_go_exports(&levels).unwrap();
+        assert!(exports.contains(
+            "func CDemoScan_scan(dir C.StringRef, progress *C.void, slot *C.void, cb *C.void) {\n"
+        ));
+        // Sync calls borrow the closure, and async calls pass it to go.
+        assert!(exports.contains(
+            "_cb_progress := &r2gCallback{ptr: unsafe.Pointer(progress), owned: false}\n"
+        ));
+        assert!(exports.contains(
+            "_cb_progress := &r2gCallback{ptr: unsafe.Pointer(progress), owned: true}\n"
+        ));
+        assert!(exports.contains("_ok := _cb_progress.call(asmcall.CallFuncG0P3, asmcall.CallFuncG0P1, unsafe.Pointer(&_arg_ref))\n"));
+        assert!(exports.contains(
+            "resp := DemoScanImpl.scan(&_new_dir, _new_progress)\n_cb_progress.done()\n"
+        ));
+        // Late calls are skipped, and panics of the closure are resumed.
+        let callbacks = super::RawRsFile::go_callbacks();
+        assert!(callbacks.contains("if c.ptr == nil {\n        return false\n    }"));
+        assert!(callbacks.contains("panic(\"rust panicked: \" + msg)"));
+
+        let rs = traits[0].generate_rs(None).unwrap().to_string();
+        assert!(rs.contains("fn scan (dir : String , progress : impl FnMut (Event) + Send) -> u32"));
+        assert!(rs.contains(
+            "let mut progress = :: rust2go :: Callback :: < Event , _ > :: new (progress) ;"
+        ));
+        assert!(rs.contains(
+            "let progress = :: rust2go :: Callback :: < Event , _ > :: boxed (progress) ;"
+        ));
+        assert!(rs.contains("progress . into_raw () as * mut _ ,"));
+
+        for (from, to, err) in [
+            (
+                "Box<dyn FnMut(Event) + Send>",
+                "impl FnMut(Event) + Send",
+                "callback of async method must be Send + 'static",
+            ),
+            (
+                "progress: impl FnMut(Event) + Send) -> u32;",
+                "progress: impl FnMut(Event) + Send, n: u32) -> u32;",
+                "callback must be the last param",
+            ),
+            (
+                "impl FnMut(Event) + Send)",
+                "impl FnOnce(Event) + Send)",
+                "FnOnce callbacks are not supported, use Fn or FnMut",
+            ),
+            (
+                "impl FnMut(Event) + Send)",
+                "impl FnMut(&Event) + Send)",
+                "callback argument must be an owned type other than tuple",
+            ),
+            (
+                "impl FnMut(Event) + Send)",
+                "impl FnMut(Event))",
+                "callback must be Send since go may call it on another thread",
+            ),
+        ] {
+            assert_eq!(r2g_error(raw, from, to), err);
+        }
+    }
+
+    #[test]
+    fn streams() {
+        let raw = r#"
+        pub struct Event {
+            pub name: String,
+        }
+        #[r2g]
+        pub trait DemoTail {
+            fn tail(file: String) -> impl Stream<Item = Event> + Send;
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        assert!(traits[0].fns()[0].stream().is_some());
+        assert!(traits[0].fns()[0].ret().is_none());
+
+        let interface = traits[0].generate_go_interface().unwrap();
+        assert!(interface
+            .contains("tail(ctx context.Context, file *string, yield func(Event) bool) \n"));
+        let exports = traits[0].generate_go_exports(&levels).unwrap();
+        assert!(exports.contains("func CDemoTail_tail(file C.StringRef, _stream *C.void) {\n"));
+        // The stream is registered to be woken and cancelled by rust side.
+        assert!(exports.contains("r2gStreams.Store(uintptr(unsafe.Pointer(_stream)), _s)\n"));
+        assert!(exports
+            .contains("_ok := _s.send(_ctx, asmcall.CallFuncG0P2, unsafe.Pointer(&_item_ref))\n"));
+        assert!(exports.contains("r2gStreams.Delete(uintptr(unsafe.Pointer(_stream)))\n_cancel()\nC.c_rust2go_stream_close(unsafe.Pointer(_stream))\n"));
+        assert!(super::RawRsFile::go_streams().contains("var r2gStreams sync.Map\n"));
+
+        let rs = traits[0].generate_rs(None).unwrap().to_string();
+        assert!(rs.contains("fn tail (file : String) -> impl Stream < Item = Event > + Send"));
+        assert!(rs.contains(":: rust2go :: GoStream :: < Event > :: new (binding :: rust2go_stream_wake , binding :: rust2go_stream_cancel)"));
+
+        for (from, to, err) in [
+            (
+                "fn tail(",
+                "async fn tail(",
+                "async cannot be used with impl Stream",
+            ),
+            (
+                "Item = Event",
+                "Item = (Event, u32)",
+                "stream item must be an owned type other than tuple",
+            ),
+            (
+                "Item = Event",
+                "Item = &'static Event",
+                "stream item must be an owned type other than tuple",
+            ),
+            (
+                "file: String",
+                "ctx: String",
+                "ctx and yield are reserved for the context and item sender of stream method",
+            ),
+        ] {
+            assert_eq!(r2g_error(raw, from, to), err);
+        }
+    }
+
+    #[test]
+    fn stream_params() {
+        let raw = r#"
+        pub struct Chunk {
+            pub data: Vec<u8>,
+        }
+        #[r2g]
+        pub trait DemoUpload {
+            fn put(name: String, chunks: impl Stream<Item = Chunk> + Send) -> u32;
+            #[drop_safe]
+            async fn put_async(name: String, chunks: impl Stream<Item = Chunk> + Send + 'static) -> u32;
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let traits = raw_file.convert_r2g_trait().unwrap();
+        assert_eq!(traits[0].fns()[0].stream_param().unwrap().name, "chunks");
+
+        let interface = traits[0].generate_go_interface().unwrap();
+        assert!(interface.contains("put(name *string, chunks <-chan Chunk) uint32\n"));
+        let exports = traits[0].generate_go_exports(&levels).unwrap();
+        assert!(exports.contains(
+            "func CDemoUpload_put(name C.StringRef, chunks *C.void, slot *C.void, cb *C.void) {\n"
+        ));
+        // Sync calls borrow the stream, and async calls pass it to go.
+        assert!(exports.contains(
+            "_rs_chunks := newR2gRustStream(unsafe.Pointer(chunks), false)\n_new_chunks := make(chan Chunk)\n"
+        ));
+        assert!(exports.contains("newR2gRustStream(unsafe.Pointer(chunks), true)\n"));
+        assert!(exports.contains("case _new_chunks <- ownChunk(*(*C.ChunkRef)(_item)):\n"));
+        assert!(exports
+            .contains("resp := DemoUploadImpl.put(&_new_name, _new_chunks)\n_rs_chunks.stop()\n"));
+        let streams = super::RawRsFile::go_rust_streams();
+        assert!(streams.contains("r2gRustStreams.Store(uintptr(ptr), s)"));
+        assert!(streams.contains("r2gRustStreams.Delete(uintptr(s.ptr))"));
+
+        let rs = traits[0].generate_rs(None).unwrap().to_string();
+        assert!(rs.contains(
+            "let mut chunks = :: rust2go :: RustStream :: < Chunk , _ > :: new (chunks , binding :: rust2go_rust_stream_wake) ;"
+        ));
+        assert!(rs.contains(
+            "let chunks = :: rust2go :: RustStream :: < Chunk , _ > :: boxed (chunks , binding :: rust2go_rust_stream_wake) ;"
+        ));
+
+        for (from, to, err) in [
+            (
+                "Item = Chunk> + Send) -> u32;",
+                "Item = Chunk>) -> u32;",
+                "stream param must be Send since it is polled by go side",
+            ),
+            (
+                "Item = Chunk> + Send + 'static)",
+                "Item = Chunk> + Send)",
+                "stream param of async method must be 'static",
+            ),
+            (
+                "Item = Chunk> + Send) -> u32;",
+                "Item = (Chunk, u32)> + Send) -> u32;",
+                "stream item must be an owned type other than tuple",
+            ),
+            (
+                "chunks: impl Stream<Item = Chunk> + Send) -> u32;",
+                "chunks: impl Stream<Item = Chunk> + Send, n: u32) -> u32;",
+                "stream param must be the last param",
+            ),
+        ] {
+            assert_eq!(r2g_error(raw, from, to), err);
+        }
+    }
+
+    #[test]
+    fn rust_panics() {
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[g2r]
+        pub trait DemoBack {
+            fn log(user: User);
+        }
+        #[g2r(rust_panic = "result")]
+        pub trait DemoResult {
+            fn ping();
+            fn back(pair: (User, u32)) -> (u32, String);
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let g2r = raw_file.convert_g2r_trait().unwrap();
+        assert_eq!(
+            g2r[0].to_importc(),
+            "const void c_DemoBack_log(const void*, const void*);\n"
+        );
+        let go = g2r[0].to_go(&levels).unwrap();
+        assert!(go.contains("panic(\"rust panicked: \" + _internal_panic)"));
+
+        let go = g2r[1].to_go(&levels).unwrap();
+        assert!(go.contains("ping() error {"));
+        assert!(go.contains("asmcall.CallFuncG0P1(unsafe.Pointer(C.c_DemoResult_ping), unsafe.Pointer(&_internal_slot))\n"));
+        assert!(go.contains("return errors.New(\"rust panicked: \" + _internal_panic)"));
+        assert!(go.contains("back(pair_0 *User,pair_1 *uint32) (uint32, string, error)"));
+        assert!(go.contains("var _internal_zero Tuple_u32_String\nreturn _internal_zero.f0, _internal_zero.f1, errors.New("));
+        assert!(go.contains("return val.f0, val.f1, nil"));
+
+        let rs = g2r[0].generate_rs().unwrap().to_string();
+        assert!(rs.contains(":: std :: panic :: catch_unwind"));
+        assert!(rs.contains(":: rust2go :: set_rust_panic (_internal_slot , e)"));
+
+        let raw = r#"#[g2r(rust_panic = "abort")] pub trait DemoBack { fn log(user: u32); }"#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        assert!(raw_file.convert_g2r_trait().is_err());
+    }
+
+    #[test]
+    fn g2r_async() {
+        let raw = r#"
+        pub struct User {
+            pub name: String,
+        }
+        #[g2r]
+        pub trait DemoBack {
+            async fn fetch(user: User) -> User;
+            async fn tick();
+        }
+        "#;
+        let raw_file = super::RawRsFile::new(raw).unwrap();
+        let levels = raw_file.convert_structs_levels().unwrap();
+        let g2r = raw_file.convert_g2r_trait().unwrap();
+        assert!(g2r[0].has_async());
+        assert!(g2r[0]
+            .to_importc()
+            .contains("const void c_DemoBack_fetch(const void*, const void*, const void*);\n"));
+        let go = g2r[0].to_go(&levels).unwrap();
+        assert!(go.contains("asmcall.CallFuncG0P2(unsafe.Pointer(C.c_DemoBack_tick), unsafe.Pointer(&_internal_slot), unsafe.Pointer(&_internal_waker))\n"));
+        assert!(go.contains("if _internal_slot[2] == nil {\n_internal_slot = <-_internal_done\n}"));
+
+        let rs = g2r[0].generate_rs().unwrap().to_string();
+        assert!(rs.contains("_internal_waker : * const :: rust2go :: G2RWaker"));
+        assert!(rs.contains(":: rust2go :: spawn_g2r (_internal_waker . read () , < Self as DemoBack > :: fetch (user) , :: rust2go :: set_g2r_ret)"));
+    }
 }
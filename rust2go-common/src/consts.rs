@@ -0,0 +1,237 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::collections::HashMap;
+
+use syn::{BinOp, Expr, Lit, Result, Type, UnOp};
+
+use crate::common::{Docs, ParamType, ParamTypeInner, RawRsFile};
+
+impl RawRsFile {
+    // Typed go constants of the const items, for example, convert
+    // /// Max requests of a batch.
+    // pub const MAX_BATCH: usize = 512;
+    // to
+    // // Max requests of a batch.
+    // const MAX_BATCH uint = 512
+    pub fn convert_consts_to_go(&self) -> Result<String> {
+        let mut types = HashMap::new();
+        for c in self.consts() {
+            if let Some(ty) = const_go_type(&c.ty)? {
+                types.insert(c.ident.to_string(), ty);
+            }
+        }
+        let mut out = String::new();
+        for c in self.consts() {
+            let Some(ty) = types.get(&c.ident.to_string()) else {
+                continue;
+            };
+            out.push_str(&Docs::new(&c.attrs).to_go(""));
+            out.push_str(&format!(
+                "const {} {ty} = {}\n",
+                c.ident,
+                const_expr_to_go(&c.expr, Some(ty), &types)?
+            ));
+        }
+        Ok(out)
+    }
+}
+
+// Go type of the const, None if the type can not be a go constant.
+pub(crate) fn const_go_type(ty: &Type) -> Result<Option<String>> {
+    if let Type::Reference(r) = ty {
+        return Ok(match r.elem.as_ref() {
+            Type::Path(p) if p.path.is_ident("str") => Some("string".to_string()),
+            _ => None,
+        });
+    }
+    let Ok(ty) = ParamType::try_from(ty) else {
+        return Ok(None);
+    };
+    match &ty.inner {
+        ParamTypeInner::Primitive(_) => Ok(Some(ty.to_go()?)),
+        _ => Ok(None),
+    }
+}
+
+// Go type of the const expression, None if it is an untyped constant.
+fn const_expr_type(expr: &Expr, types: &HashMap<String, String>) -> Result<Option<String>> {
+    Ok(match expr {
+        Expr::Lit(l) => match &l.lit {
+            Lit::Bool(_) => Some("bool".to_string()),
+            _ => None,
+        },
+        Expr::Unary(u) => const_expr_type(&u.expr, types)?,
+        Expr::Binary(b) => match b.op {
+            BinOp::And(_)
+            | BinOp::Or(_)
+            | BinOp::Eq(_)
+            | BinOp::Lt(_)
+            | BinOp::Le(_)
+            | BinOp::Ne(_)
+            | BinOp::Ge(_)
+            | BinOp::Gt(_) => Some("bool".to_string()),
+            BinOp::Shl(_) | BinOp::Shr(_) => const_expr_type(&b.left, types)?,
+            _ => match const_expr_type(&b.left, types)? {
+                Some(ty) => Some(ty),
+                None => const_expr_type(&b.right, types)?,
+            },
+        },
+        Expr::Paren(p) => const_expr_type(&p.expr, types)?,
+        Expr::Group(g) => const_expr_type(&g.expr, types)?,
+        Expr::Path(p) => p
+            .path
+            .get_ident()
+            .and_then(|i| types.get(&i.to_string()).cloned()),
+        Expr::Cast(c) => const_go_type(&c.ty)?,
+        _ => None,
+    })
+}
+
+// Convert the const expression, ty is the go type it evaluates to if known.
+fn const_expr_to_go(
+    expr: &Expr,
+    ty: Option<&str>,
+    types: &HashMap<String, String>,
+) -> Result<String> {
+    Ok(match expr {
+        Expr::Lit(l) => match &l.lit {
+            Lit::Int(i) => i.base10_digits().to_string(),
+            Lit::Float(f) => f.base10_digits().to_string(),
+            Lit::Bool(b) => b.value.to_string(),
+            Lit::Char(c) => (c.value() as u32).to_string(),
+            Lit::Str(s) => go_string(&s.value()),
+            lit => sbail!(lit, "unsupported literal in const"),
+        },
+        Expr::Unary(u) => match u.op {
+            UnOp::Neg(_) => format!("-{}", const_expr_to_go(&u.expr, ty, types)?),
+            UnOp::Not(_) => {
+                let ty = match ty {
+                    Some(ty) => Some(ty.to_string()),
+                    None => const_expr_type(&u.expr, types)?,
+                };
+                match ty.as_deref() {
+                    Some("bool") => {
+                        format!("!{}", const_expr_to_go(&u.expr, ty.as_deref(), types)?)
+                    }
+                    // `!` on integers is bitwise not, the untyped `^0` of go is -1 so the operand
+                    // of unsigned types must be converted.
+                    Some(ty) if ty.starts_with("uint") => {
+                        let mut operand = u.expr.as_ref();
+                        while let Expr::Paren(syn::ExprParen { expr, .. })
+                        | Expr::Group(syn::ExprGroup { expr, .. }) = operand
+                        {
+                            operand = expr;
+                        }
+                        format!("^{ty}({})", const_expr_to_go(operand, Some(ty), types)?)
+                    }
+                    ty => format!("^{}", const_expr_to_go(&u.expr, ty, types)?),
+                }
+            }
+            _ => sbail!(u, "unsupported unary operator in const"),
+        },
+        Expr::Binary(b) => {
+            let (op, left_ty, right_ty) = match b.op {
+                BinOp::Add(_) => ("+", ty, ty),
+                BinOp::Sub(_) => ("-", ty, ty),
+                BinOp::Mul(_) => ("*", ty, ty),
+                BinOp::Div(_) => ("/", ty, ty),
+                BinOp::Rem(_) => ("%", ty, ty),
+                BinOp::And(_) => ("&&", Some("bool"), Some("bool")),
+                BinOp::Or(_) => ("||", Some("bool"), Some("bool")),
+                BinOp::BitXor(_) => ("^", ty, ty),
+                BinOp::BitAnd(_) => ("&", ty, ty),
+                BinOp::BitOr(_) => ("|", ty, ty),
+                BinOp::Shl(_) => ("<<", ty, None),
+                BinOp::Shr(_) => (">>", ty, None),
+                BinOp::Eq(_) => ("==", None, None),
+                BinOp::Lt(_) => ("<", None, None),
+                BinOp::Le(_) => ("<=", None, None),
+                BinOp::Ne(_) => ("!=", None, None),
+                BinOp::Ge(_) => (">=", None, None),
+                BinOp::Gt(_) => (">", None, None),
+                _ => sbail!(b, "unsupported binary operator in const"),
+            };
+            format!(
+                "{} {op} {}",
+                const_expr_to_go(&b.left, left_ty, types)?,
+                const_expr_to_go(&b.right, right_ty, types)?
+            )
+        }
+        Expr::Paren(p) => format!("({})", const_expr_to_go(&p.expr, ty, types)?),
+        Expr::Group(g) => const_expr_to_go(&g.expr, ty, types)?,
+        // other consts
+        Expr::Path(p) if p.qself.is_none() && p.path.get_ident().is_some() => {
+            p.path.get_ident().unwrap().to_string()
+        }
+        Expr::Cast(c) => {
+            let Some(ty) = const_go_type(&c.ty)? else {
+                sbail!(&c.ty, "unsupported cast type in const")
+            };
+            format!("{ty}({})", const_expr_to_go(&c.expr, None, types)?)
+        }
+        e => sbail!(
+            e,
+            "only literals and simple expressions are supported in const"
+        ),
+    })
+}
+
+// Go interpreted string literal.
+fn go_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\U{:08x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consts_to_go() {
+        let raw = r#"
+        /// Max requests of a batch.
+        pub const MAX_BATCH: usize = 512;
+        pub const MAX_BYTES: u64 = (MAX_BATCH as u64) << 10;
+        pub const MIN_DELTA: i32 = -0x10 | !3;
+        pub const RATIO: f64 = 1.5e3;
+        pub const ENABLED: bool = !false && MAX_BATCH > 0;
+        pub const SEP: char = 'a';
+        pub const ALL: u32 = !0;
+        pub const MASK: u8 = !(0x0f | 0x30);
+        pub const LOW_CLEAR: bool = (!MASK & 1) == 0;
+        pub const VERSION: &str = "v1 \"beta\"\n";
+        pub const SKIPPED: Vec<u8> = Vec::new();
+        "#;
+        let go = RawRsFile::new(raw).unwrap().convert_consts_to_go().unwrap();
+        assert_eq!(
+            go,
+            "// Max requests of a batch.\n\
+            const MAX_BATCH uint = 512\n\
+            const MAX_BYTES uint64 = (uint64(MAX_BATCH)) << 10\n\
+            const MIN_DELTA int32 = -16 | ^3\n\
+            const RATIO float64 = 1.5e3\n\
+            const ENABLED bool = !false && MAX_BATCH > 0\n\
+            const SEP rune = 97\n\
+            const ALL uint32 = ^uint32(0)\n\
+            const MASK uint8 = ^uint8(15 | 48)\n\
+            const LOW_CLEAR bool = (^uint8(MASK) & 1) == 0\n\
+            const VERSION string = \"v1 \\\"beta\\\"\\n\"\n"
+        );
+
+        let raw = "pub const N: usize = [1, 2].len();";
+        assert!(RawRsFile::new(raw).unwrap().convert_consts_to_go().is_err());
+    }
+}
@@ -4,17 +4,82 @@ use std::collections::HashMap;
 
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
-use syn::{Error, FnArg, Ident, ItemTrait, Meta, Pat, Result, ReturnType, TraitItem, Type};
+use syn::{
+    parse::Parser, punctuated::Punctuated, Attribute, Error, Expr, ExprLit, FnArg, Ident,
+    ItemTrait, Lit, Meta, Pat, Result, ReturnType, Token, TraitItem, Type,
+};
 
-use crate::common::{Param, ParamType};
+use crate::common::{Docs, Param, ParamType, ParamTypeInner};
+
+/// Arguments of `#[rust2go::g2r(...)]`.
+#[derive(Default, Clone)]
+pub struct G2RTraitArgs {
+    pub rust_panic: RustPanicMode,
+}
+
+/// How rust panics caught in the entry points are surfaced to go, set with
+/// `#[g2r(rust_panic = "result")]`.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RustPanicMode {
+    /// Panic on the calling goroutine with the rust message.
+    #[default]
+    Resume,
+    /// Return an `error` as the last go return value.
+    Result,
+}
+
+impl G2RTraitArgs {
+    pub fn parse(tokens: TokenStream) -> Result<Self> {
+        type AttributeArgs = Punctuated<Meta, Token![,]>;
+        let mut out = G2RTraitArgs::default();
+        for meta in AttributeArgs::parse_terminated.parse2(tokens)? {
+            match meta {
+                Meta::NameValue(nv) if nv.path.is_ident("rust_panic") => {
+                    out.rust_panic = match &nv.value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(s), ..
+                        }) if s.value() == "resume" => RustPanicMode::Resume,
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(s), ..
+                        }) if s.value() == "result" => RustPanicMode::Result,
+                        v => sbail!(v, "rust_panic must be \"resume\" or \"result\""),
+                    };
+                }
+                m => sbail!(m, "unknown g2r argument"),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Find and parse the `#[g2r(...)]` attribute of the trait.
+    pub fn from_attrs(attrs: &[Attribute]) -> Result<Self> {
+        let Some(attr) = attrs.iter().find(|attr| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|s| s.ident == "g2r")
+        }) else {
+            return Ok(Self::default());
+        };
+        match &attr.meta {
+            Meta::List(l) => Self::parse(l.tokens.clone()),
+            _ => Ok(Self::default()),
+        }
+    }
+}
 
 pub struct G2RTraitRepr {
     name: Ident,
+    docs: Docs,
+    rust_panic: RustPanicMode,
     fns: Vec<G2RFnRepr>,
 }
 
 pub struct G2RFnRepr {
     name: Ident,
+    docs: Docs,
+    // The future runs with the registered spawner, and the go caller waits for the output.
+    is_async: bool,
     params: Vec<Param>,
     ret: Option<ParamType>,
     cgo_call: bool,
@@ -24,41 +89,44 @@ impl TryFrom<&ItemTrait> for G2RTraitRepr {
     type Error = Error;
 
     fn try_from(trat: &ItemTrait) -> Result<Self> {
+        Self::new(trat, &G2RTraitArgs::from_attrs(&trat.attrs)?)
+    }
+}
+
+impl G2RTraitRepr {
+    pub fn new(trat: &ItemTrait, args: &G2RTraitArgs) -> Result<Self> {
         let trait_name = trat.ident.clone();
         let mut fns = Vec::new();
 
         for item in trat.items.iter() {
             let TraitItem::Fn(fn_item) = item else {
-                sbail!("only fn items are supported");
+                sbail!(item, "only fn items are supported");
             };
             let fn_name = fn_item.sig.ident.clone();
             let mut params = Vec::new();
             for param in fn_item.sig.inputs.iter() {
                 let FnArg::Typed(param) = param else {
-                    sbail!("only typed fn args are supported")
+                    sbail!(param, "only typed fn args are supported")
                 };
                 // param name
                 let Pat::Ident(param_name) = param.pat.as_ref() else {
-                    sbail!("only ident fn args are supported");
+                    sbail!(&param.pat, "only ident fn args are supported");
                 };
                 // param type
-                let param_type = ParamType::try_from(param.ty.as_ref())?;
+                let param_type = ParamType::try_from_fn(param.ty.as_ref())?;
                 params.push(Param {
                     name: param_name.ident.clone(),
                     ty: param_type,
                 });
             }
-            if fn_item.sig.asyncness.is_some() {
-                sbail!("async is not supported yet when go call rust, manually spawn by your own!");
-            }
             let param_type = match &fn_item.sig.output {
                 ReturnType::Default => None,
                 ReturnType::Type(_, t) => match t.as_ref() {
-                    Type::Path(_) => {
-                        let param_type = ParamType::try_from(t.as_ref())?;
+                    Type::Path(_) | Type::Tuple(_) => {
+                        let param_type = ParamType::try_from_fn(t.as_ref())?;
                         Some(param_type)
                     }
-                    _ => sbail!("only path type returns are supported"),
+                    _ => sbail!(t, "only path type or tuple returns are supported"),
                 },
             };
             let ret = param_type;
@@ -70,6 +138,8 @@ impl TryFrom<&ItemTrait> for G2RTraitRepr {
                 );
             fns.push(G2RFnRepr {
                 name: fn_name,
+                docs: Docs::new(&fn_item.attrs),
+                is_async: fn_item.sig.asyncness.is_some(),
                 params,
                 ret,
                 cgo_call,
@@ -78,6 +148,8 @@ impl TryFrom<&ItemTrait> for G2RTraitRepr {
 
         Ok(G2RTraitRepr {
             name: trait_name,
+            docs: Docs::new(&trat.attrs),
+            rust_panic: args.rust_panic,
             fns,
         })
     }
@@ -94,6 +166,10 @@ macro_rules! or_empty {
 }
 
 impl G2RTraitRepr {
+    pub const fn name(&self) -> &Ident {
+        &self.name
+    }
+
     pub fn fns(&self) -> &[G2RFnRepr] {
         &self.fns
     }
@@ -102,38 +178,59 @@ impl G2RTraitRepr {
         self.fns.iter().any(|f| f.ret.is_some())
     }
 
+    pub const fn rust_panic(&self) -> RustPanicMode {
+        self.rust_panic
+    }
+
+    pub fn has_async(&self) -> bool {
+        self.fns.iter().any(|f| f.is_async)
+    }
+
     pub fn to_importc(&self) -> String {
         let prefix = format!("const void c_{}_", self.name);
         let decs = self
             .fns
             .iter()
-            .map(|f| match f.ffi_param_cnt() {
-                0 => format!("{prefix}{}();\n", f.name),
-                1 => format!("{prefix}{}(const void*);\n", f.name),
-                _ => format!("{prefix}{}(const void*, const void*);\n", f.name),
+            .map(|f| {
+                let params = vec!["const void*"; f.ffi_param_cnt() as usize].join(", ");
+                format!("{prefix}{}({params});\n", f.name)
             })
             .collect::<Vec<String>>();
         decs.join("")
     }
 
-    pub fn to_go(&self, levels: &HashMap<Ident, u8>) -> String {
+    pub fn to_go(&self, levels: &HashMap<Ident, u8>) -> Result<String> {
         let trait_name = &self.name;
         let struct_name = format!("{trait_name}Impl");
-        let mut out = format!("type {struct_name} struct{{}}\n");
+        let mut out = self.docs.to_go("");
+        out.push_str(&format!("type {struct_name} struct{{}}\n"));
 
         for f in &self.fns {
             let call_type = if f.cgo_call { "cgocall" } else { "asmcall" };
             let ffi_param_cnt = f.ffi_param_cnt();
             let f_name = &f.name;
 
-            let params = f
-                .params
-                .iter()
-                .map(|p| format!("{} *{}", p.name, p.ty.to_go()))
-                .collect::<Vec<_>>()
-                .join(",");
-            let ret = f.ret.as_ref().map_or(String::new(), |ret| ret.to_go());
-            let init_slot = or_empty!(f.ret.is_some(), "_internal_slot := [2]unsafe.Pointer{}\n");
+            let mut params = Vec::with_capacity(f.params.len());
+            for p in f.params.iter() {
+                params.extend(p.ty.to_go_params(&p.name, "*")?);
+            }
+            let params = params.join(",");
+            let ret = match (&f.ret, self.rust_panic) {
+                (Some(ret), RustPanicMode::Resume) => ret.to_go_ret()?,
+                (None, RustPanicMode::Resume) => String::new(),
+                // string -> (string, error)
+                (Some(ret), RustPanicMode::Result) => {
+                    let mut rets = match ret.tuple_elems() {
+                        Some(elems) => elems.iter().map(|e| e.to_go()).collect::<Result<_>>()?,
+                        None => vec![ret.to_go()?],
+                    };
+                    rets.push("error".to_string());
+                    format!("({})", rets.join(", "))
+                }
+                (None, RustPanicMode::Result) => "error".to_string(),
+            };
+            // [return value ref, drop ptr, rust panic message ref]
+            let init_slot = "_internal_slot := [3]unsafe.Pointer{}\n";
             let mut init_params = String::new();
             if !f.params.is_empty() {
                 init_params = format!(
@@ -143,17 +240,33 @@ impl G2RTraitRepr {
             }
 
             // write function header
+            out.push_str(&f.docs.to_go(""));
             out.push_str(&format!(
                 "func ({struct_name}) {f_name}({params}) {ret} {{
                     {init_slot}{init_params}"
             ));
+            // The output of async method is sent to the channel by rust side.
+            // _internal_done := make(chan [3]unsafe.Pointer, 1)
+            // _internal_waker := [2]uintptr{uintptr(unsafe.Pointer(C.rust2go_g2r_wake)), uintptr(cgo.NewHandle(_internal_done))}
+            if f.is_async {
+                out.push_str("_internal_done := make(chan [3]unsafe.Pointer, 1)\n_internal_waker := [2]uintptr{uintptr(unsafe.Pointer(C.rust2go_g2r_wake)), uintptr(cgo.NewHandle(_internal_done))}\n");
+            }
 
             // convert params
             for (i, p) in f.params.iter().enumerate() {
+                // tuple params are flattened, collect them back
+                // pair := &Tuple_DemoUser_u32{*pair_0, *pair_1}
+                if let (Some(elems), Some(tuple)) = (p.ty.tuple_elems(), p.ty.tuple_name()) {
+                    let vals = (0..elems.len())
+                        .map(|i| format!("*{}_{i}", p.name))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    out.push_str(&format!("{} := &{tuple}{{{vals}}}\n", p.name));
+                }
                 // user_ref, user_buffer := cvt_ref(cntDemoUser, refDemoUser)(user)
                 // _internal_params[0] = unsafe.Pointer(&user_ref)
-                let cnt = p.ty.go_to_c_field_counter(levels).0;
-                let ref_ = p.ty.go_to_c_field_converter(levels).0;
+                let cnt = p.ty.go_to_c_field_counter(levels)?.0;
+                let ref_ = p.ty.go_to_c_field_converter(levels)?.0;
                 out.push_str(&format!(
                     "{pname}_ref, {pname}_buffer := cvt_ref({cnt}, {ref_})({pname})
                     _internal_params[{i}] = unsafe.Pointer(&{pname}_ref)
@@ -165,43 +278,106 @@ impl G2RTraitRepr {
             // call
             let mut call_params = String::new();
             // unsafe.Pointer(&_internal_slot), unsafe.Pointer(&_internal_params)
-            if f.ret.is_some() {
-                call_params.push_str(", unsafe.Pointer(&_internal_slot)");
-            }
+            call_params.push_str(", unsafe.Pointer(&_internal_slot)");
             if !f.params.is_empty() {
                 call_params.push_str(", unsafe.Pointer(&_internal_params)");
             }
+            if f.is_async {
+                call_params.push_str(", unsafe.Pointer(&_internal_waker)");
+            }
             out.push_str(&format!(
                 "{call_type}.CallFuncG0P{ffi_param_cnt}(unsafe.Pointer(C.c_{trait_name}_{f_name}){call_params})\n"
             ));
 
             // keepalive
-            if f.ret.is_some() {
-                out.push_str("runtime.KeepAlive(_internal_slot)\n");
-            }
+            out.push_str("runtime.KeepAlive(_internal_slot)\n");
             if !f.params.is_empty() {
                 out.push_str("runtime.KeepAlive(_internal_params)\n");
             }
+            // Rust objects of the handles in params may be released by go GC, which must wait
+            // until rust side takes its references.
             for p in f.params.iter() {
-                out.push_str(&format!("runtime.KeepAlive({}_buffer)\n", p.name));
+                out.push_str(&format!(
+                    "runtime.KeepAlive({pname}_buffer)\nruntime.KeepAlive({pname})\n",
+                    pname = p.name
+                ));
             }
+            // The waker is not used if rust side panics before the future is spawned.
+            if f.is_async {
+                out.push_str("if _internal_slot[2] == nil {\n_internal_slot = <-_internal_done\n} else {\ncgo.Handle(_internal_waker[1]).Delete()\n}\n");
+            }
+
+            // rust panic
+            // if _internal_slot[2] != nil {
+            //     _internal_panic := ownString(*(*C.StringRef)(_internal_slot[2]))
+            //     asmcall.CallFuncG0P1(unsafe.Pointer(C.c_rust2go_internal_drop), unsafe.Pointer(_internal_slot[1]))
+            //     panic("rust panicked: " + _internal_panic)
+            // }
+            let on_panic = match (&f.ret, self.rust_panic) {
+                (_, RustPanicMode::Resume) => "panic(\"rust panicked: \" + _internal_panic)".to_string(),
+                (Some(r), RustPanicMode::Result) => format!(
+                    "var _internal_zero {}\nreturn {}, errors.New(\"rust panicked: \" + _internal_panic)",
+                    r.to_go()?,
+                    r.to_go_args("_internal_zero", "").join(", ")
+                ),
+                (None, RustPanicMode::Result) => {
+                    "return errors.New(\"rust panicked: \" + _internal_panic)".to_string()
+                }
+            };
+            out.push_str(&format!("if _internal_slot[2] != nil {{
+                _internal_panic := ownString(*(*C.StringRef)(_internal_slot[2]))
+                {call_type}.CallFuncG0P1(unsafe.Pointer(C.c_rust2go_internal_drop), unsafe.Pointer(_internal_slot[1]))
+                {on_panic}
+                }}
+                "));
 
+            let ret_err = or_empty!(self.rust_panic == RustPanicMode::Result, ", nil");
             if let Some(r) = &f.ret {
                 // val := ownString(*(*C.StringRef)(_internal_slot[0]))
                 // asmcall.CallFuncG0P1(unsafe.Pointer(C.c_rust2go_internal_drop), unsafe.Pointer(_internal_slot[1]))
                 // return val
-                let cvt = r.c_to_go_field_converter_owned();
-                let cty = r.to_c(false);
+                let cvt = r.c_to_go_field_converter_owned()?;
+                let cty = r.to_c(false)?;
+                let vals = r.to_go_args("val", "").join(", ");
                 out.push_str(&format!("val := {cvt}(*(*C.{cty})(_internal_slot[0]))
                 {call_type}.CallFuncG0P1(unsafe.Pointer(C.c_rust2go_internal_drop), unsafe.Pointer(_internal_slot[1]))
-                return val
+                return {vals}{ret_err}
                 "));
+            } else if self.rust_panic == RustPanicMode::Result {
+                out.push_str("return nil\n");
             }
 
             out.push_str("}\n");
+
+            // Methods taking a handle first are also methods of the go type of it:
+            // func (_self Session) name() string {
+            //     return SessionOpsImpl{}.name(&_self)
+            // }
+            if let Some((first, rest)) = f.params.split_first() {
+                if let ParamTypeInner::Handle(_, handle) = &first.ty.inner {
+                    let mut params = Vec::with_capacity(rest.len());
+                    let mut args = vec!["&_self".to_string()];
+                    for p in rest.iter() {
+                        params.extend(p.ty.to_go_params(&p.name, "*")?);
+                        args.extend(match p.ty.tuple_elems() {
+                            Some(elems) => (0..elems.len())
+                                .map(|i| format!("{}_{i}", p.name))
+                                .collect(),
+                            None => vec![p.name.to_string()],
+                        });
+                    }
+                    let return_ = or_empty!(!ret.is_empty(), "return ");
+                    out.push_str(&f.docs.to_go(""));
+                    out.push_str(&format!(
+                        "func (_self {handle}) {f_name}({}) {ret} {{\n{return_}{struct_name}{{}}.{f_name}({})\n}}\n",
+                        params.join(","),
+                        args.join(", ")
+                    ));
+                }
+            }
         }
 
-        out
+        Ok(out)
     }
 
     // Generate rust impl.
@@ -211,13 +387,10 @@ impl G2RTraitRepr {
         for f in self.fns.iter() {
             let f_name = &f.name;
             let cf_name = format_ident!("c_{}_{}", &self.name, &f.name);
-            let slot_expr = f
-                .ret
-                .as_ref()
-                .map(|_| quote! {_internal_slot: *mut [*const (); 2],});
+            let slot_expr = quote! {_internal_slot: *mut [*const (); 3],};
             let mut params_expr = None;
             if !f.params.is_empty() {
-                params_expr = Some(quote! {_internal_params: *const *const ()});
+                params_expr = Some(quote! {_internal_params: *const *const (),});
             }
             let mut params = Vec::new();
             let mut param_names = Vec::new();
@@ -231,35 +404,45 @@ impl G2RTraitRepr {
                 param_names.push(p.name.clone());
             }
 
-            let bottom = if f.ret.is_some() {
-                quote! {
+            let bottom = match (f.is_async, f.ret.is_some()) {
+                (false, true) => quote! {
                     let _internal_out = <Self as #trait_name>::#f_name(#(#param_names),*);
-                    let (_internal_buf, _internal_out_ref) = ::rust2go::ToRef::calc_ref(&_internal_out);
-
-                    let _internal_boxed_storage = ::std::boxed::Box::new((_internal_out, _internal_out_ref, _internal_buf));
-                    let ret_ptr = &_internal_boxed_storage.as_ref().1 as *const _ as *const ();
-                    let drop_ptr = ::std::boxed::Box::leak(_internal_boxed_storage as ::std::boxed::Box<dyn ::std::any::Any>) as *mut dyn ::std::any::Any as *mut ();
-
-                    *_internal_slot = [ret_ptr, drop_ptr];
-                }
-            } else {
-                quote! {
+                    ::rust2go::set_g2r_ret(_internal_slot, _internal_out);
+                },
+                (false, false) => quote! {
                     <Self as #trait_name>::#f_name(#(#param_names),*);
-                }
+                },
+                (true, true) => quote! {
+                    ::rust2go::spawn_g2r(_internal_waker.read(), <Self as #trait_name>::#f_name(#(#param_names),*), ::rust2go::set_g2r_ret);
+                },
+                (true, false) => quote! {
+                    ::rust2go::spawn_g2r(_internal_waker.read(), <Self as #trait_name>::#f_name(#(#param_names),*), |_, _| {});
+                },
             };
+            let waker_expr = f
+                .is_async
+                .then(|| quote! {_internal_waker: *const ::rust2go::G2RWaker});
 
+            // Unwinding into go is undefined behavior, the panic is reported with the slot.
             fn_entries.push(quote! {
                 #[no_mangle]
-                unsafe extern "C" fn #cf_name(#slot_expr #params_expr) {
-                    #(#params)*
-                    #bottom
+                unsafe extern "C" fn #cf_name(#slot_expr #params_expr #waker_expr) {
+                    let _internal_res = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                        #(#params)*
+                        #bottom
+                    }));
+                    if let Err(e) = _internal_res {
+                        ::rust2go::set_rust_panic(_internal_slot, e);
+                    }
                 }
             });
         }
 
         let impl_struct_name = format_ident!("{}Impl", trait_name);
+        let docs = &self.docs;
 
         Ok(quote! {
+            #docs
             pub struct #impl_struct_name;
             impl #impl_struct_name {
                 #(#fn_entries)*
@@ -269,14 +452,28 @@ impl G2RTraitRepr {
 }
 
 impl G2RFnRepr {
+    // The slot is always passed for the rust panic, and the waker for async method.
     fn ffi_param_cnt(&self) -> u8 {
-        [self.params.is_empty(), self.ret.is_none()]
-            .into_iter()
-            .filter(|x| !*x)
-            .count() as u8
+        1 + !self.params.is_empty() as u8 + self.is_async as u8
+    }
+
+    pub const fn name(&self) -> &Ident {
+        &self.name
+    }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    pub fn ret(&self) -> Option<&ParamType> {
+        self.ret.as_ref()
     }
 
     pub const fn cgo_call(&self) -> bool {
         self.cgo_call
     }
+
+    pub const fn is_async(&self) -> bool {
+        self.is_async
+    }
 }
@@ -5,6 +5,9 @@ macro_rules! serr {
     ($msg:expr) => {
         ::syn::Error::new(::proc_macro2::Span::call_site(), $msg)
     };
+    ($tokens:expr, $msg:expr) => {
+        ::syn::Error::new_spanned($tokens, $msg)
+    };
 }
 
 #[macro_export]
@@ -12,8 +15,13 @@ macro_rules! sbail {
     ($msg:expr) => {
         return Err(::syn::Error::new(::proc_macro2::Span::call_site(), $msg))
     };
+    ($tokens:expr, $msg:expr) => {
+        return Err(::syn::Error::new_spanned($tokens, $msg))
+    };
 }
 
 pub mod common;
+pub mod consts;
 pub mod g2r;
+pub mod lint;
 pub mod r2g;
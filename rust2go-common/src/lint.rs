@@ -0,0 +1,311 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::collections::{HashMap, HashSet};
+
+use proc_macro2::Span;
+use syn::{spanned::Spanned, Attribute, Ident, ItemTrait, Result, TraitItem, TraitItemFn};
+
+use crate::{
+    common::{ParamType, ParamTypeInner, RawRsFile},
+    g2r::G2RTraitRepr,
+    r2g::{R2GFnRepr, R2GTraitRepr},
+};
+
+// Structs passed by value to go larger than it are reported.
+pub const LARGE_STRUCT_SIZE: usize = 256;
+
+// Attributes only recognized on r2g or g2r methods.
+const R2G_ATTRS: &[&str] = &[
+    "drop_safe",
+    "drop_safe_ret",
+    "send",
+    "go_pass_struct",
+    "mem",
+    "shm",
+    "cgo_callback",
+    "cancel",
+    "timeout_ms",
+];
+const G2R_ATTRS: &[&str] = &["cgo_call"];
+
+/// A risky declaration which compiles but is likely a mistake.
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Warning {
+    fn new(span: Span, message: impl Into<String>) -> Self {
+        Warning {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+fn find_attr<'a>(f: &'a TraitItemFn, name: &str) -> Option<&'a Attribute> {
+    f.attrs.iter().find(|attr| attr.path().is_ident(name))
+}
+
+fn trait_fns(trat: &ItemTrait) -> impl Iterator<Item = &TraitItemFn> {
+    trat.items.iter().filter_map(|item| match item {
+        TraitItem::Fn(f) => Some(f),
+        _ => None,
+    })
+}
+
+/// Lint methods of a r2g trait. It only looks at the trait itself, so it can be used by the macro.
+pub fn lint_r2g_trait(trat: &ItemTrait, repr: &R2GTraitRepr) -> Vec<Warning> {
+    let mut out = Vec::new();
+    for (f, f_repr) in trait_fns(trat).zip(repr.fns()) {
+        lint_r2g_fn(f, f_repr, repr.defaults(), &mut out);
+    }
+    out
+}
+
+fn lint_r2g_fn(f: &TraitItemFn, repr: &R2GFnRepr, defaults: &[Ident], out: &mut Vec<Warning>) {
+    let name = &f.sig.ident;
+
+    // unsafe by default
+    if repr.is_async() && !repr.is_safe() {
+        let hint = if repr.params().iter().any(|p| p.ty().is_reference) {
+            "take the parameters by value and mark it #[drop_safe] or #[drop_safe_ret] to make it safe"
+        } else {
+            "mark it #[drop_safe] or #[drop_safe_ret] to make it safe"
+        };
+        out.push(Warning::new(
+            f.sig.span(),
+            format!("`{name}` is generated as unsafe fn and its future must not be dropped before completion, {hint}"),
+        ));
+    }
+    if !repr.is_async() && repr.mem_call_id().is_some() {
+        out.push(Warning::new(
+            f.sig.span(),
+            format!("`{name}` is generated as unsafe fn since the params of oneway shm call must outlive the go side, make it async to make it safe"),
+        ));
+    }
+
+    // ineffective or conflicting attributes
+    let mut ineffective = |attr: &str, reason: &str| {
+        if let Some(attr) = find_attr(f, attr) {
+            out.push(Warning::new(
+                attr.meta.span(),
+                format!(
+                    "#[{}] has no effect {reason}",
+                    attr.path().get_ident().unwrap()
+                ),
+            ));
+        }
+    };
+    for attr in G2R_ATTRS {
+        ineffective(attr, "on r2g methods");
+    }
+    if !repr.is_async() {
+        for attr in ["drop_safe", "drop_safe_ret", "send", "cancel", "timeout_ms"] {
+            ineffective(attr, "on sync methods");
+        }
+    } else if repr.drop_safe_ret_params() {
+        ineffective("drop_safe", "together with #[drop_safe_ret]");
+    }
+    if repr.mem_call_id().is_some() {
+        for attr in ["cgo_callback", "cgo"] {
+            ineffective(attr, "on shm calls");
+        }
+    } else if repr.ret().is_none() && repr.callback().is_none() && repr.stream().is_none() {
+        for attr in ["cgo_callback", "cgo"] {
+            ineffective(attr, "on methods without return value or callback");
+        }
+    }
+    if repr.params().is_empty() {
+        ineffective("go_pass_struct", "on methods without params");
+    }
+    for name in R2G_ATTRS {
+        let aliases = match *name {
+            "mem" | "shm" => &["mem", "shm"][..],
+            _ => std::slice::from_ref(name),
+        };
+        if !defaults.iter().any(|d| aliases.iter().any(|a| d == a)) {
+            ineffective(&format!("no_{name}"), &format!("without #[r2g({name})]"));
+        }
+    }
+}
+
+/// Lint methods of a g2r trait.
+pub fn lint_g2r_trait(trat: &ItemTrait) -> Result<Vec<Warning>> {
+    G2RTraitRepr::try_from(trat)?;
+    let mut out = Vec::new();
+    for f in trait_fns(trat) {
+        for name in R2G_ATTRS.iter().filter(|a| **a != "cgo_callback") {
+            if let Some(attr) = find_attr(f, name) {
+                out.push(Warning::new(
+                    attr.meta.span(),
+                    format!("#[{name}] has no effect on g2r methods"),
+                ));
+            }
+        }
+        if let Some(attr) = find_attr(f, "cgo_callback") {
+            out.push(Warning::new(
+                attr.meta.span(),
+                "#[cgo_callback] has no effect on g2r methods, use #[cgo_call] instead",
+            ));
+        }
+    }
+    Ok(out)
+}
+
+impl RawRsFile {
+    /// Lint traits and structs of the file.
+    pub fn lint(&self) -> Result<Vec<Warning>> {
+        let mut fields = HashMap::<Ident, Vec<ParamType>>::new();
+        for s in self.structs() {
+            let tys = s
+                .fields
+                .iter()
+                .map(|field| ParamType::try_from(&field.ty))
+                .collect::<Result<_>>()?;
+            fields.insert(s.ident.clone(), tys);
+        }
+
+        let mut out = Vec::new();
+        let mut used = HashSet::new();
+        for trat in self.r2g_traits() {
+            let repr = R2GTraitRepr::try_from(trat)?;
+            out.extend(lint_r2g_trait(trat, &repr));
+            for (f, f_repr) in trait_fns(trat).zip(repr.fns()) {
+                for (p, param) in f_repr.params().iter().zip(f.sig.inputs.iter()) {
+                    mark_used(p.ty(), &fields, &mut used);
+                    let size = go_size(p.ty(), &fields);
+                    if f_repr.go_pass_struct() && size > LARGE_STRUCT_SIZE {
+                        out.push(Warning::new(
+                            param.span(),
+                            format!("`{}` is about {size} bytes and copied on every call since #[go_pass_struct] passes it to go by value, remove #[go_pass_struct] to pass it by pointer", p.name),
+                        ));
+                    }
+                }
+                if let Some(ret) = f_repr.ret() {
+                    mark_used(ret, &fields, &mut used);
+                }
+            }
+        }
+        for trat in self.g2r_traits() {
+            out.extend(lint_g2r_trait(trat)?);
+            let repr = G2RTraitRepr::try_from(trat)?;
+            for f in repr.fns() {
+                for p in f.params() {
+                    mark_used(p.ty(), &fields, &mut used);
+                }
+                if let Some(ret) = f.ret() {
+                    mark_used(ret, &fields, &mut used);
+                }
+            }
+        }
+
+        for s in self.structs() {
+            if !used.contains(&s.ident) {
+                out.push(Warning::new(
+                    s.ident.span(),
+                    format!("struct `{}` is not used by any r2g or g2r trait", s.ident),
+                ));
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn mark_used(ty: &ParamType, fields: &HashMap<Ident, Vec<ParamType>>, used: &mut HashSet<Ident>) {
+    match &ty.inner {
+        ParamTypeInner::List(_, elem) => mark_used(elem, fields, used),
+        ParamTypeInner::Tuple(elems) => elems.iter().for_each(|e| mark_used(e, fields, used)),
+        ParamTypeInner::Custom(name) => {
+            if used.insert(name.clone()) {
+                for field in fields.get(name).into_iter().flatten() {
+                    mark_used(field, fields, used);
+                }
+            }
+        }
+        ParamTypeInner::Primitive(_) | ParamTypeInner::Handle(..) => (),
+    }
+}
+
+// Approximate size of the go type, padding is ignored.
+fn go_size(ty: &ParamType, fields: &HashMap<Ident, Vec<ParamType>>) -> usize {
+    match &ty.inner {
+        ParamTypeInner::Primitive(name) => match name.to_string().as_str() {
+            "u8" | "i8" | "bool" => 1,
+            "u16" | "i16" => 2,
+            "u32" | "i32" | "f32" | "char" => 4,
+            _ => 8,
+        },
+        ParamTypeInner::List(..) => 24,
+        ParamTypeInner::Handle(..) => 8,
+        ParamTypeInner::Tuple(elems) => elems.iter().map(|e| go_size(e, fields)).sum(),
+        ParamTypeInner::Custom(name) if name == "String" => 16,
+        ParamTypeInner::Custom(name) => fields
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|field| go_size(field, fields))
+            .sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lint_file() {
+        let big_fields: String = (0..11).map(|i| format!("pub f{i}: Vec<u64>,")).collect();
+        let raw = format!(
+            r#"
+        pub struct Unused {{
+            pub id: u32,
+        }}
+        pub struct Big {{
+            {big_fields}
+        }}
+        pub struct User {{
+            pub name: String,
+        }}
+        #[r2g]
+        pub trait DemoCall {{
+            async fn demo_async(req: &User) -> User;
+            #[drop_safe]
+            #[drop_safe_ret]
+            async fn demo_safe(req: User) -> User;
+            #[cgo_callback]
+            #[go_pass_struct]
+            fn demo_oneway(req: Big);
+            #[send]
+            fn demo_sync() -> User;
+        }}
+        #[g2r]
+        pub trait DemoBack {{
+            #[mem]
+            fn demo_back(req: User);
+        }}
+        "#
+        );
+        let warnings = RawRsFile::new(raw).unwrap().lint().unwrap();
+        let messages: Vec<_> = warnings.iter().map(|w| w.message.as_str()).collect();
+        assert_eq!(messages.len(), 7, "{messages:#?}");
+        assert!(messages[0].starts_with("`demo_async` is generated as unsafe fn"));
+        assert!(messages[0].contains("take the parameters by value"));
+        assert_eq!(
+            messages[1],
+            "#[drop_safe] has no effect together with #[drop_safe_ret]"
+        );
+        assert_eq!(
+            messages[2],
+            "#[cgo_callback] has no effect on methods without return value or callback"
+        );
+        assert_eq!(messages[3], "#[send] has no effect on sync methods");
+        assert!(messages[4].starts_with("`req` is about 264 bytes"));
+        assert_eq!(messages[5], "#[mem] has no effect on g2r methods");
+        assert_eq!(
+            messages[6],
+            "struct `Unused` is not used by any r2g or g2r trait"
+        );
+    }
+}
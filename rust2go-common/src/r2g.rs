@@ -5,101 +5,644 @@ use std::collections::HashMap;
 use proc_macro2::{Span, TokenStream};
 use quote::{format_ident, quote};
 use syn::{
-    Error, FnArg, Ident, ItemTrait, Meta, Pat, Path, Result, ReturnType, Token, TraitItem, Type,
+    parse::Parser, punctuated::Punctuated, Attribute, Error, Expr, ExprLit, FnArg, GenericArgument,
+    Ident, ItemTrait, Lit, Meta, MetaList, MetaNameValue, Pat, Path, PathArguments, Result,
+    ReturnType, Token, TraitItem, TraitItemFn, Type, TypeImplTrait, TypeParamBound,
 };
 
-use crate::common::{Param, ParamType};
+use crate::common::{Docs, Param, ParamType};
 
 pub struct R2GTraitRepr {
     name: Ident,
+    docs: Docs,
+    // r2g supertraits, which are embedded in the go interface.
+    parents: Vec<Ident>,
+    // Traits extending this one in the same file, set by `RawRsFile::convert_r2g_trait`.
+    descendants: Vec<Ident>,
+    defaults: Vec<Ident>,
+    shm: ShmConfig,
     fns: Vec<R2GFnRepr>,
 }
 
+/// Arguments of `#[r2g(...)]`.
+/// Besides the binding path and shm settings, method attributes listed here apply to all methods
+/// of the trait, e.g. `#[r2g(binding, mem, send, drop_safe)]`. The binding path is `binding`,
+/// a multi-segment path like `crate::ffi`, or `binding = ffi`. Methods opt out with
+/// `#[no_mem]`, `#[no_send]`, `#[no_drop_safe]` and so on.
+/// `timeout_ms = 500` sets the default deadline of async methods, which is also listed in
+/// `defaults` so methods can opt out with `#[no_timeout_ms]`.
+#[derive(Default, Clone)]
+pub struct R2GTraitArgs {
+    pub binding: Option<Path>,
+    pub shm: ShmConfig,
+    pub defaults: Vec<Ident>,
+    pub timeout_ms: Option<u64>,
+    pub go_panic: Option<GoPanicMode>,
+}
+
+/// How panics of the go implementation are surfaced, set with `#[r2g(go_panic = "resume")]`.
+/// Without it, a go panic crashes the process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GoPanicMode {
+    /// Panic on the calling rust task.
+    Resume,
+    /// Return `Err(rust2go::GoPanic)`.
+    Result,
+}
+
+/// Shared memory settings of a ring.
+/// Set on the trait with `#[r2g(queue_size = 4096, go_pools = 8, go_pool_size = 1024)]` for the
+/// ring shared by its methods. A method with `#[mem(...)]` arguments gets a dedicated ring, e.g.
+/// `#[mem(queue_size = 65536)]`, or `#[mem(dedicated)]` to keep the trait settings.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShmConfig {
+    /// Capacity of the rings, 4096 by default.
+    pub queue_size: Option<usize>,
+    /// Number of go worker pools, 8 by default.
+    pub go_pools: Option<usize>,
+    /// Workers of each go pool, unlimited by default.
+    pub go_pool_size: Option<usize>,
+}
+
+impl ShmConfig {
+    pub const DEFAULT_QUEUE_SIZE: usize = 4096;
+    pub const DEFAULT_GO_POOLS: usize = 8;
+
+    // Parse `name = value`, returns false if it is not a shm setting.
+    fn parse_arg(&mut self, nv: &MetaNameValue) -> Result<bool> {
+        let slot = match nv.path.get_ident() {
+            Some(i) if i == "queue_size" => &mut self.queue_size,
+            Some(i) if i == "go_pools" => &mut self.go_pools,
+            Some(i) if i == "go_pool_size" => &mut self.go_pool_size,
+            _ => return Ok(false),
+        };
+        match &nv.value {
+            Expr::Lit(ExprLit {
+                lit: Lit::Int(i), ..
+            }) => match i.base10_parse()? {
+                0 => sbail!(i, "shm settings must be positive"),
+                v => *slot = Some(v),
+            },
+            v => sbail!(v, "shm settings must be integers"),
+        }
+        Ok(true)
+    }
+
+    // Unset fields fall back to the other config.
+    const fn or(self, other: ShmConfig) -> ShmConfig {
+        ShmConfig {
+            queue_size: match self.queue_size {
+                Some(v) => Some(v),
+                None => other.queue_size,
+            },
+            go_pools: match self.go_pools {
+                Some(v) => Some(v),
+                None => other.go_pools,
+            },
+            go_pool_size: match self.go_pool_size {
+                Some(v) => Some(v),
+                None => other.go_pool_size,
+            },
+        }
+    }
+
+    pub fn queue_size(&self) -> usize {
+        self.queue_size.unwrap_or(Self::DEFAULT_QUEUE_SIZE)
+    }
+
+    pub fn go_pools(&self) -> usize {
+        self.go_pools.unwrap_or(Self::DEFAULT_GO_POOLS)
+    }
+
+    // -1 means unlimited in ants.
+    pub fn go_pool_size(&self) -> isize {
+        self.go_pool_size.map(|s| s as isize).unwrap_or(-1)
+    }
+}
+
+/// A ring pair and the shm methods using it.
+pub struct ShmRing<'a> {
+    pub id: usize,
+    /// Name of the ring to set its config at runtime, the trait name for the shared ring and
+    /// `Trait::method` for dedicated rings.
+    pub name: String,
+    /// Name of the go export which initializes the ring.
+    pub init_fn: String,
+    pub config: ShmConfig,
+    pub fns: Vec<&'a R2GFnRepr>,
+}
+
+impl ShmRing<'_> {
+    // Thread local holding the ring of current thread.
+    fn rs_ident(&self) -> Ident {
+        ring_ident(self.id)
+    }
+}
+
+fn ring_ident(id: usize) -> Ident {
+    match id {
+        0 => format_ident!("WS"),
+        id => format_ident!("WS{id}"),
+    }
+}
+
+// Supertraits which are not r2g traits.
+const MARKER_TRAITS: &[&str] = &["Send", "Sync", "Sized", "Unpin", "Copy", "Clone", "Default"];
+
+// Single ident supertraits except the marker traits.
+fn trait_parents(trat: &ItemTrait) -> Vec<Ident> {
+    trat.supertraits
+        .iter()
+        .filter_map(|bound| match bound {
+            TypeParamBound::Trait(t) => t.path.get_ident(),
+            _ => None,
+        })
+        .filter(|i| !MARKER_TRAITS.iter().any(|m| *i == m))
+        .cloned()
+        .collect()
+}
+
+// Check of the go abi hash by the generated `verify`, panicking on mismatch. All the call paths
+// must use it.
+fn rs_abi_check(impl_struct: TokenStream) -> TokenStream {
+    quote! {
+        if let Err(e) = #impl_struct::verify() {
+            panic!("{e}");
+        }
+    }
+}
+
+// Hidden macro implementing the trait for other impl struct by delegating to the impl of it.
+fn delegate_macro(trait_name: &Ident) -> Ident {
+    format_ident!("__rust2go_r2g_{trait_name}")
+}
+
+// Rust closure or stream taken by the last param, which is called by go with one argument or
+// polled by go for items.
+struct Callback {
+    // Name of the param and type of the argument or item.
+    param: Param,
+    ty: Type,
+}
+
+// Stream returned by r2g method.
+struct Stream {
+    item: ParamType,
+    // Declared return type.
+    ty: Type,
+}
+
+// Type of the associated type like `Output` of `impl Future<Output = T>`.
+fn impl_assoc_type<'a>(i: &'a TypeImplTrait, trait_name: &str, assoc: &str) -> Option<&'a Type> {
+    i.bounds.iter().find_map(|b| match b {
+        TypeParamBound::Trait(t) => {
+            let last_seg = t.path.segments.last()?;
+            if last_seg.ident != trait_name {
+                return None;
+            }
+            match &last_seg.arguments {
+                PathArguments::AngleBracketed(a) if a.args.len() == 1 => match a.args.first() {
+                    Some(GenericArgument::AssocType(t)) if t.ident == assoc => Some(&t.ty),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        _ => None,
+    })
+}
+
+// Argument type of callback param like `impl FnMut(T) + Send` or `Box<dyn Fn(T) + Send>`, and
+// whether it is bounded by `'static`. None for other types.
+fn callback_arg(ty: &Type) -> Result<Option<(ParamType, bool)>> {
+    let (bounds, mut is_static) = match ty {
+        Type::ImplTrait(i) => (&i.bounds, false),
+        Type::Path(p) => {
+            let Some(seg) = p.path.segments.last().filter(|s| s.ident == "Box") else {
+                return Ok(None);
+            };
+            match &seg.arguments {
+                PathArguments::AngleBracketed(a) if a.args.len() == 1 => match a.args.first() {
+                    Some(GenericArgument::Type(Type::TraitObject(t))) => (&t.bounds, true),
+                    _ => return Ok(None),
+                },
+                _ => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    let (mut arg, mut is_send) = (None, false);
+    for bound in bounds.iter() {
+        let t = match bound {
+            TypeParamBound::Trait(t) => t,
+            TypeParamBound::Lifetime(l) => {
+                is_static |= l.ident == "static";
+                continue;
+            }
+            _ => continue,
+        };
+        let Some(seg) = t.path.segments.last() else {
+            continue;
+        };
+        match seg.ident.to_string().as_str() {
+            "Send" => is_send = true,
+            "FnOnce" => sbail!(seg, "FnOnce callbacks are not supported, use Fn or FnMut"),
+            "Fn" | "FnMut" => {
+                let PathArguments::Parenthesized(args) = &seg.arguments else {
+                    sbail!(seg, "callback must be like Fn(T)")
+                };
+                if args.inputs.len() != 1 || !matches!(args.output, ReturnType::Default) {
+                    sbail!(args, "callback must take one argument and return nothing")
+                }
+                let input = &args.inputs[0];
+                let ty = ParamType::try_from_fn(input)?;
+                if ty.is_reference || ty.tuple_elems().is_some() {
+                    sbail!(
+                        input,
+                        "callback argument must be an owned type other than tuple"
+                    )
+                }
+                arg = Some(ty);
+            }
+            _ => (),
+        }
+    }
+    let Some(arg) = arg else {
+        return Ok(None);
+    };
+    // Go side may call it from any goroutine, which runs on any thread.
+    if !is_send {
+        sbail!(
+            ty,
+            "callback must be Send since go may call it on another thread"
+        )
+    }
+    Ok(Some((arg, is_static)))
+}
+
+// Item type of stream param like `impl Stream<Item = T> + Send`, and whether it is bounded by
+// `'static`. None for other types.
+fn stream_param_item(ty: &Type) -> Result<Option<(ParamType, bool)>> {
+    let Type::ImplTrait(i) = ty else {
+        return Ok(None);
+    };
+    let Some(item) = impl_assoc_type(i, "Stream", "Item") else {
+        return Ok(None);
+    };
+    let item_ty = ParamType::try_from_fn(item)?;
+    if item_ty.is_reference || item_ty.tuple_elems().is_some() {
+        sbail!(item, "stream item must be an owned type other than tuple")
+    }
+    let (mut is_send, mut is_static) = (false, false);
+    for bound in i.bounds.iter() {
+        match bound {
+            TypeParamBound::Trait(t) => {
+                is_send |= t.path.segments.last().is_some_and(|s| s.ident == "Send")
+            }
+            TypeParamBound::Lifetime(l) => is_static |= l.ident == "static",
+            _ => (),
+        }
+    }
+    if !is_send {
+        sbail!(i, "stream param must be Send since it is polled by go side")
+    }
+    Ok(Some((item_ty, is_static)))
+}
+
+// Method attributes which can be set as trait defaults.
+const R2G_FN_ATTRS: &[&str] = &[
+    "drop_safe",
+    "drop_safe_ret",
+    "send",
+    "go_pass_struct",
+    "mem",
+    "shm",
+    "cgo_callback",
+    "cgo",
+    "cancel",
+];
+
+impl R2GTraitArgs {
+    pub fn parse(tokens: TokenStream) -> Result<Self> {
+        type AttributeArgs = Punctuated<Meta, Token![,]>;
+        let mut out = R2GTraitArgs::default();
+        for meta in AttributeArgs::parse_terminated.parse2(tokens)? {
+            match meta {
+                Meta::NameValue(nv) if nv.path.is_ident("binding") => match nv.value {
+                    Expr::Path(p) => out.binding = Some(p.path),
+                    v => sbail!(v, "binding must be a path"),
+                },
+                Meta::NameValue(nv) if nv.path.is_ident("go_panic") => {
+                    out.go_panic = Some(match &nv.value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(s), ..
+                        }) if s.value() == "resume" => GoPanicMode::Resume,
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(s), ..
+                        }) if s.value() == "result" => GoPanicMode::Result,
+                        v => sbail!(v, "go_panic must be \"resume\" or \"result\""),
+                    });
+                }
+                Meta::NameValue(nv) if nv.path.is_ident("timeout_ms") => {
+                    out.timeout_ms = Some(parse_timeout(&nv)?);
+                    out.defaults.push(format_ident!("timeout_ms"));
+                }
+                Meta::NameValue(nv) => {
+                    if !out.shm.parse_arg(&nv)? {
+                        sbail!(nv, "unknown r2g argument")
+                    }
+                }
+                // A bare ident is a flag, except the conventional `binding` module. Other
+                // binding modules are given as `binding = ffi` or a multi-segment path.
+                Meta::Path(p) => match p.get_ident() {
+                    Some(i) if R2G_FN_ATTRS.iter().any(|a| i == a) => out.defaults.push(i.clone()),
+                    Some(i) if i != "binding" => sbail!(
+                        i,
+                        format!(
+                            "unknown r2g flag `{i}`, expect one of {}, or a binding path like `binding = {i}`",
+                            R2G_FN_ATTRS.join(", ")
+                        )
+                    ),
+                    _ => out.binding = Some(p),
+                },
+                m => sbail!(m, "unknown r2g argument"),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Find and parse the `#[r2g(...)]` attribute of the trait.
+    pub fn from_attrs(attrs: &[Attribute]) -> Result<Self> {
+        let Some(attr) = attrs.iter().find(|attr| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|s| s.ident == "r2g")
+        }) else {
+            return Ok(Self::default());
+        };
+        match &attr.meta {
+            Meta::List(l) => Self::parse(l.tokens.clone()),
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+// Whether the method has one of the attributes, and whether it is inherited from the trait.
+fn method_attr(fn_item: &TraitItemFn, defaults: &[Ident], names: &[&str]) -> (bool, bool) {
+    let has = |name: &str| {
+        fn_item
+            .attrs
+            .iter()
+            .any(|attr| matches!(&attr.meta, Meta::Path(p) | Meta::List(MetaList { path: p, .. }) if p.is_ident(name)))
+    };
+    if names.iter().any(|n| has(n)) {
+        return (true, false);
+    }
+    let inherited = names.iter().any(|n| defaults.iter().any(|d| d == n))
+        && !names.iter().any(|n| has(&format!("no_{n}")));
+    (inherited, inherited)
+}
+
+fn parse_timeout(nv: &MetaNameValue) -> Result<u64> {
+    match &nv.value {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(i), ..
+        }) => i.base10_parse(),
+        v => sbail!(v, "timeout_ms must be an integer"),
+    }
+}
+
+// Deadline of the method from `#[timeout_ms = 500]` or the trait default, and whether it is
+// inherited from the trait.
+fn method_timeout(fn_item: &TraitItemFn, default: Option<u64>) -> Result<(Option<u64>, bool)> {
+    for attr in fn_item.attrs.iter() {
+        match &attr.meta {
+            Meta::NameValue(nv) if nv.path.is_ident("timeout_ms") => {
+                return Ok((Some(parse_timeout(nv)?), false))
+            }
+            Meta::Path(p) if p.is_ident("no_timeout_ms") => return Ok((None, false)),
+            _ => (),
+        }
+    }
+    Ok((default, default.is_some()))
+}
+
+// Settings of `#[mem(...)]`, some if the method has a dedicated ring.
+fn method_shm(fn_item: &TraitItemFn) -> Result<Option<ShmConfig>> {
+    let mut out = None;
+    for attr in fn_item.attrs.iter() {
+        let Meta::List(l) = &attr.meta else {
+            continue;
+        };
+        if !l.path.is_ident("mem") && !l.path.is_ident("shm") {
+            continue;
+        }
+        let config = out.get_or_insert_with(ShmConfig::default);
+        for meta in l.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)? {
+            match meta {
+                Meta::Path(p) if p.is_ident("dedicated") => (),
+                Meta::NameValue(nv) if config.parse_arg(&nv)? => (),
+                m => sbail!(m, "unknown shm argument"),
+            }
+        }
+    }
+    Ok(out)
+}
+
 impl TryFrom<&ItemTrait> for R2GTraitRepr {
     type Error = Error;
 
     fn try_from(trat: &ItemTrait) -> Result<Self> {
+        Self::new(trat, &R2GTraitArgs::from_attrs(&trat.attrs)?)
+    }
+}
+
+impl R2GTraitRepr {
+    /// The macro gets the arguments separately since the attribute is removed from the trait.
+    pub fn new(trat: &ItemTrait, args: &R2GTraitArgs) -> Result<Self> {
         let trait_name = trat.ident.clone();
         let mut fns = Vec::new();
 
         let mut mem_cnt = 0;
         for item in trat.items.iter() {
             let TraitItem::Fn(fn_item) = item else {
-                sbail!("only fn items are supported");
+                sbail!(item, "only fn items are supported");
             };
             let fn_name = fn_item.sig.ident.clone();
             let mut params = Vec::new();
-            for param in fn_item.sig.inputs.iter() {
-                let FnArg::Typed(param) = param else {
-                    sbail!("only typed fn args are supported")
+            let mut receiver = false;
+            let mut callback = None;
+            let mut stream_param = None;
+            let inputs_len = fn_item.sig.inputs.len();
+            for (idx, param) in fn_item.sig.inputs.iter().enumerate() {
+                let param = match param {
+                    FnArg::Typed(param) => param,
+                    // Methods of the go object held by the impl.
+                    FnArg::Receiver(r)
+                        if r.reference.is_some()
+                            && r.mutability.is_none()
+                            && r.colon_token.is_none() =>
+                    {
+                        receiver = true;
+                        continue;
+                    }
+                    FnArg::Receiver(r) => sbail!(r, "only &self receiver is supported"),
                 };
                 // param name
                 let Pat::Ident(param_name) = param.pat.as_ref() else {
-                    sbail!("only ident fn args are supported");
+                    sbail!(&param.pat, "only ident fn args are supported");
                 };
+                // rust closure called by go side
+                if let Some((arg, bounded)) = callback_arg(param.ty.as_ref())? {
+                    if idx + 1 != inputs_len {
+                        sbail!(param, "callback must be the last param")
+                    }
+                    callback = Some((
+                        Callback {
+                            param: Param {
+                                name: param_name.ident.clone(),
+                                ty: arg,
+                            },
+                            ty: param.ty.as_ref().clone(),
+                        },
+                        bounded,
+                    ));
+                    continue;
+                }
+                // rust stream polled by go side
+                if let Some((item, bounded)) = stream_param_item(param.ty.as_ref())? {
+                    if idx + 1 != inputs_len {
+                        sbail!(param, "stream param must be the last param")
+                    }
+                    stream_param = Some((
+                        Callback {
+                            param: Param {
+                                name: param_name.ident.clone(),
+                                ty: item,
+                            },
+                            ty: param.ty.as_ref().clone(),
+                        },
+                        bounded,
+                    ));
+                    continue;
+                }
                 // param type
-                let param_type = ParamType::try_from(param.ty.as_ref())?;
+                let param_type = ParamType::try_from_fn(param.ty.as_ref())?;
                 params.push(Param {
                     name: param_name.ident.clone(),
                     ty: param_type,
                 });
             }
             let mut is_async = fn_item.sig.asyncness.is_some();
+            // Constructors return the impl holding a new go object.
+            let constructor = match &fn_item.sig.output {
+                ReturnType::Type(_, t) => {
+                    matches!(t.as_ref(), Type::Path(p) if p.path.is_ident("Self"))
+                }
+                ReturnType::Default => false,
+            };
+            if constructor && (is_async || receiver) {
+                sbail!(
+                    &fn_item.sig,
+                    "constructor must be a sync function without receiver"
+                )
+            }
+            let mut stream = None;
             let ret = match &fn_item.sig.output {
                 ReturnType::Default => None,
+                ReturnType::Type(..) if constructor => None,
                 ReturnType::Type(_, t) => match t.as_ref() {
-                    Type::Path(_) => {
-                        let param_type = ParamType::try_from(t.as_ref())?;
+                    Type::Path(_) | Type::Tuple(_) => {
+                        let param_type = ParamType::try_from_fn(t.as_ref())?;
                         Some(param_type)
                     }
+                    // Items are pushed by go side.
+                    Type::ImplTrait(i) if impl_assoc_type(i, "Stream", "Item").is_some() => {
+                        if is_async {
+                            sbail!(i, "async cannot be used with impl Stream");
+                        }
+                        let item = impl_assoc_type(i, "Stream", "Item").unwrap();
+                        let item_ty = ParamType::try_from_fn(item)?;
+                        if item_ty.is_reference || item_ty.tuple_elems().is_some() {
+                            sbail!(item, "stream item must be an owned type other than tuple")
+                        }
+                        stream = Some(Stream {
+                            item: item_ty,
+                            ty: t.as_ref().clone(),
+                        });
+                        None
+                    }
                     // Check if it's a future.
                     Type::ImplTrait(i) => {
-                        let t_str = i
-                            .bounds
-                            .iter()
-                            .find_map(|b| match b {
-                                syn::TypeParamBound::Trait(t) => {
-                                    let last_seg = t.path.segments.last().unwrap();
-                                    if last_seg.ident != "Future" {
-                                        return None;
-                                    }
-                                    // extract the Output type of the future.
-                                    let arg = match &last_seg.arguments {
-                                        syn::PathArguments::AngleBracketed(a)
-                                            if a.args.len() == 1 =>
-                                        {
-                                            a.args.first().unwrap()
-                                        }
-                                        _ => return None,
-                                    };
-                                    match arg {
-                                        syn::GenericArgument::AssocType(t)
-                                            if t.ident == "Output" =>
-                                        {
-                                            // extract the type of the Output.
-                                            let ret = Some(ParamType::try_from(&t.ty).unwrap());
-                                            if is_async {
-                                                panic!("async cannot be used with impl Future");
-                                            }
-                                            is_async = true;
-                                            ret
-                                        }
-                                        _ => None,
-                                    }
-                                }
-                                _ => None,
-                            })
-                            .ok_or_else(|| serr!("only future types are supported"))?;
-                        Some(t_str)
+                        // extract the Output type of the future.
+                        let Some(output) = impl_assoc_type(i, "Future", "Output") else {
+                            sbail!(i, "only future and stream types are supported")
+                        };
+                        if is_async {
+                            sbail!(i, "async cannot be used with impl Future");
+                        }
+                        is_async = true;
+                        Some(ParamType::try_from_fn(output)?)
                     }
-                    _ => sbail!("only path type or impl trait returns are supported"),
+                    _ => sbail!(
+                        t,
+                        "only path type, tuple or impl trait returns are supported"
+                    ),
                 },
             };
             if is_async && ret.is_none() {
-                sbail!("async function must have a return value")
+                sbail!(&fn_item.sig, "async function must have a return value")
             }
+            if let Some(stream) = &stream {
+                if callback.is_some() || stream_param.is_some() {
+                    sbail!(
+                        &stream.ty,
+                        "stream method cannot take a callback or stream param"
+                    )
+                }
+                if let Some(p) = params.iter().find(|p| p.name == "ctx" || p.name == "yield") {
+                    sbail!(
+                        &p.name,
+                        "ctx and yield are reserved for the context and item sender of stream method"
+                    )
+                }
+            }
+            // The closure of async method is moved to go side, and dropped when the go method
+            // returns.
+            let callback = match callback {
+                Some((c, false)) if is_async => {
+                    sbail!(&c.ty, "callback of async method must be Send + 'static")
+                }
+                Some((c, _)) if constructor => {
+                    sbail!(&c.ty, "callback is not supported on constructors")
+                }
+                c => c.map(|(c, _)| c),
+            };
+            // The stream of async method is moved to go side, and dropped when the go method
+            // returns.
+            let stream_param = match stream_param {
+                Some((s, false)) if is_async => {
+                    sbail!(&s.ty, "stream param of async method must be 'static")
+                }
+                Some((s, _)) if constructor => {
+                    sbail!(&s.ty, "stream param is not supported on constructors")
+                }
+                s => s.map(|(s, _)| s),
+            };
+
+            // Method attributes, with defaults from the trait.
+            let attr = |names: &[&str]| method_attr(fn_item, &args.defaults, names);
+            let opt_out = |inherited: bool, name: &str| {
+                if inherited {
+                    format!(", opt out with #[no_{name}]")
+                } else {
+                    String::new()
+                }
+            };
 
             // on async mode, parse attributes to check it's drop safe setting.
+            let mut drop_safe = false;
             let mut drop_safe_ret_params = false;
             let mut ret_send = false;
 
@@ -107,55 +650,115 @@ impl TryFrom<&ItemTrait> for R2GTraitRepr {
             let has_reference = params.iter().any(|param| param.ty.is_reference);
 
             if is_async {
-                let drop_safe = fn_item
-                .attrs
-                .iter()
-                .any(|attr|
-                    matches!(&attr.meta, Meta::Path(p) if p.get_ident() == Some(&format_ident!("drop_safe")))
-                );
-                drop_safe_ret_params = fn_item
-                .attrs
-                .iter()
-                .any(|attr|
-                    matches!(&attr.meta, Meta::Path(p) if p.get_ident() == Some(&format_ident!("drop_safe_ret")))
-                );
-                ret_send = fn_item
-                .attrs
-                .iter()
-                .any(|attr|
-                    matches!(&attr.meta, Meta::Path(p) if p.get_ident() == Some(&format_ident!("send")))
-                );
+                let (drop_safe_inherited, drop_safe_ret_inherited);
+                (drop_safe, drop_safe_inherited) = attr(&["drop_safe"]);
+                (drop_safe_ret_params, drop_safe_ret_inherited) = attr(&["drop_safe_ret"]);
+                (ret_send, _) = attr(&["send"]);
 
                 if !drop_safe && !drop_safe_ret_params {
                     is_safe = false;
                 }
                 if (drop_safe || drop_safe_ret_params) && has_reference {
-                    sbail!("drop_safe function cannot have reference parameters")
+                    let hint = match drop_safe_ret_params {
+                        true => opt_out(drop_safe_ret_inherited, "drop_safe_ret"),
+                        false => opt_out(drop_safe_inherited, "drop_safe"),
+                    };
+                    sbail!(
+                        &fn_item.sig,
+                        format!("drop_safe function cannot have reference parameters{hint}")
+                    )
                 }
             }
 
-            let go_ptr = fn_item
-                .attrs
-                .iter()
-                .all(|attr|
-                    matches!(&attr.meta, Meta::Path(p) if p.get_ident() != Some(&format_ident!("go_pass_struct")))
-                );
+            let go_ptr = !attr(&["go_pass_struct"]).0;
 
-            let using_mem = fn_item
-                .attrs
-                .iter()
-                .any(|attr|
-                    matches!(&attr.meta, Meta::Path(p) if p.get_ident() == Some(&format_ident!("mem")) || p.get_ident() == Some(&format_ident!("shm")))
-                );
-            let cgo_cb = fn_item
-                .attrs
-                .iter()
-                .any(|attr|
-                    matches!(&attr.meta, Meta::Path(p) if p.get_ident() == Some(&format_ident!("cgo_callback")) || p.get_ident() == Some(&format_ident!("cgo")))
-                );
+            let (using_mem, mem_inherited) = attr(&["mem", "shm"]);
+            let (cgo_cb, _) = attr(&["cgo_callback", "cgo"]);
+            let (mut cancel, cancel_inherited) = attr(&["cancel"]);
+            cancel &= is_async;
+            if cancel && using_mem {
+                sbail!(
+                    &fn_item.sig,
+                    format!(
+                        "cancel is not supported on shm calls{}",
+                        opt_out(cancel_inherited, "cancel")
+                    )
+                )
+            }
+            let (mut timeout_ms, timeout_inherited) = method_timeout(fn_item, args.timeout_ms)?;
+            if !is_async {
+                timeout_ms = None;
+            }
+            if timeout_ms.is_some() && using_mem {
+                sbail!(
+                    &fn_item.sig,
+                    format!(
+                        "timeout is not supported on shm calls{}",
+                        opt_out(timeout_inherited, "timeout_ms")
+                    )
+                )
+            }
+            if cancel || timeout_ms.is_some() {
+                if let Some(p) = params.iter().find(|p| p.name == "ctx") {
+                    sbail!(
+                        &p.name,
+                        "ctx is reserved for the context of cancellable method"
+                    )
+                }
+            }
+            if timeout_ms.is_some() {
+                if let Some(p) = params.iter().find(|p| p.name == "timeout") {
+                    sbail!(
+                        &p.name,
+                        "timeout is reserved for the deadline of the method"
+                    )
+                }
+            }
+            if using_mem && stream.is_some() {
+                sbail!(
+                    &fn_item.sig,
+                    format!(
+                        "stream is not supported on shm calls{}",
+                        opt_out(mem_inherited, "mem")
+                    )
+                )
+            }
+            if using_mem && callback.is_some() {
+                sbail!(
+                    &fn_item.sig,
+                    format!(
+                        "callback is not supported on shm calls{}",
+                        opt_out(mem_inherited, "mem")
+                    )
+                )
+            }
+            if using_mem && stream_param.is_some() {
+                sbail!(
+                    &fn_item.sig,
+                    format!(
+                        "stream param is not supported on shm calls{}",
+                        opt_out(mem_inherited, "mem")
+                    )
+                )
+            }
+            if using_mem && (receiver || constructor) {
+                sbail!(
+                    &fn_item.sig,
+                    format!(
+                        "constructors and &self methods are not supported on shm calls{}",
+                        opt_out(mem_inherited, "mem")
+                    )
+                )
+            }
             if using_mem && !is_async {
                 if ret.is_some() {
-                    sbail!("function based on shm must be async or without return value")
+                    sbail!(
+                        &fn_item.sig,
+                        format!(
+                            "function based on shm must be async or without return value{}",
+                            opt_out(mem_inherited, "mem")
+                        )
+                    )
                 } else {
                     is_safe = false;
                 }
@@ -167,23 +770,72 @@ impl TryFrom<&ItemTrait> for R2GTraitRepr {
             } else {
                 None
             };
+            let dedicated_shm = match using_mem {
+                true => method_shm(fn_item)?,
+                false => None,
+            };
+            // Oneway shm calls respond before calling go implementation, constructors return
+            // the handle directly, and streams end with the go method.
+            let go_panic = args
+                .go_panic
+                .filter(|_| (!using_mem || ret.is_some()) && !constructor && stream.is_none());
 
             fns.push(R2GFnRepr {
                 name: fn_name,
+                docs: Docs::new(&fn_item.attrs),
                 is_async,
                 params,
                 ret,
                 is_safe,
+                drop_safe,
                 drop_safe_ret_params,
                 ret_send,
-                ret_static: !has_reference,
+                // The future of go object methods borrows the impl.
+                ret_static: !has_reference && !receiver,
                 cgo_cb,
+                cancel,
+                timeout_ms,
+                go_panic,
+                receiver,
+                constructor,
+                callback,
+                stream_param,
+                stream,
                 go_ptr,
                 mem_call_id,
+                dedicated_shm,
+                mem_ring: 0,
+                mem_ring_call_id: 0,
             });
         }
+
+        // Methods with dedicated rings are numbered from 1, others share the ring 0.
+        let (mut shared_cnt, mut dedicated_cnt) = (0, 0);
+        for f in fns.iter_mut().filter(|f| f.mem_call_id.is_some()) {
+            if f.dedicated_shm.is_some() {
+                dedicated_cnt += 1;
+                f.mem_ring = dedicated_cnt;
+            } else {
+                f.mem_ring_call_id = shared_cnt;
+                shared_cnt += 1;
+            }
+        }
+
+        let parents = trait_parents(trat);
+        if !parents.is_empty() && fns.iter().any(|f| f.receiver || f.constructor) {
+            sbail!(
+                trait_name,
+                "trait with constructors or &self methods cannot have supertraits"
+            )
+        }
+
         Ok(R2GTraitRepr {
             name: trait_name,
+            docs: Docs::new(&trat.attrs),
+            parents,
+            descendants: Vec::new(),
+            defaults: args.defaults.clone(),
+            shm: args.shm,
             fns,
         })
     }
@@ -191,69 +843,256 @@ impl TryFrom<&ItemTrait> for R2GTraitRepr {
 
 pub struct R2GFnRepr {
     name: Ident,
+    docs: Docs,
     is_async: bool,
     params: Vec<Param>,
     ret: Option<ParamType>,
     is_safe: bool,
+    drop_safe: bool,
     drop_safe_ret_params: bool,
     ret_send: bool,
     ret_static: bool,
     go_ptr: bool,
     cgo_cb: bool,
+    // Go method takes a context which is cancelled when the future is dropped.
+    cancel: bool,
+    // Default deadline, the go method takes a context with it and the future resolves to an
+    // error when it is exceeded.
+    timeout_ms: Option<u64>,
+    // Go panics are recovered and sent back to rust side.
+    go_panic: Option<GoPanicMode>,
+    // Method of the go object referred by the handle of the impl.
+    receiver: bool,
+    // Returns the impl with a handle of the go object created by the static go impl.
+    constructor: bool,
+    // Rust closure of the last param.
+    callback: Option<Callback>,
+    // Rust stream of the last param.
+    stream_param: Option<Callback>,
+    // Returns a stream of items pushed by the go method.
+    stream: Option<Stream>,
     mem_call_id: Option<usize>,
+    dedicated_shm: Option<ShmConfig>,
+    // The ring used by shm call and the call id in it.
+    mem_ring: usize,
+    mem_ring_call_id: usize,
 }
 
 impl R2GTraitRepr {
+    pub const fn name(&self) -> &Ident {
+        &self.name
+    }
+
     pub fn fns(&self) -> &[R2GFnRepr] {
         &self.fns
     }
 
+    /// R2g supertraits of the trait.
+    pub fn parents(&self) -> &[Ident] {
+        &self.parents
+    }
+
+    /// Whether the impl holds a go object, which is created by constructors and called by
+    /// `&self` methods.
+    pub fn has_object(&self) -> bool {
+        self.fns.iter().any(|f| f.receiver || f.constructor)
+    }
+
+    pub(crate) fn set_descendants(&mut self, descendants: Vec<Ident>) {
+        self.descendants = descendants;
+    }
+
+    // Go expression of the impl. With descendants, the impl of any of them is used if the impl
+    // of the trait itself is not set.
+    fn go_impl(&self) -> String {
+        match self.descendants.is_empty() {
+            true => format!("{}Impl", self.name),
+            false => format!("impl{}()", self.name),
+        }
+    }
+
+    /// Method attributes set on the trait.
+    pub fn defaults(&self) -> &[Ident] {
+        &self.defaults
+    }
+
+    /// Shm settings of the trait.
+    pub const fn shm(&self) -> &ShmConfig {
+        &self.shm
+    }
+
+    /// Rings used by shm calls, the shared one first.
+    pub fn shm_rings(&self) -> Vec<ShmRing<'_>> {
+        let mut rings: Vec<ShmRing> = Vec::new();
+        for f in self.fns.iter().filter(|f| f.mem_call_id.is_some()) {
+            if let Some(ring) = rings.iter_mut().find(|r| r.id == f.mem_ring) {
+                ring.fns.push(f);
+                continue;
+            }
+            let (name, init_fn, config) = match &f.dedicated_shm {
+                None => (
+                    self.name.to_string(),
+                    format!("RingsInit{}", self.name),
+                    self.shm,
+                ),
+                Some(c) => (
+                    format!("{}::{}", self.name, f.name),
+                    format!("RingsInit{}_{}", self.name, f.name),
+                    c.or(self.shm),
+                ),
+            };
+            rings.push(ShmRing {
+                id: f.mem_ring,
+                name,
+                init_fn,
+                config,
+                fns: vec![f],
+            });
+        }
+        rings.sort_by_key(|r| r.id);
+        rings
+    }
+
+    // Hash of the trait ABI: method order, flags, mem call ids and param/return layouts.
+    // The macro computes the same value with `generate_rs_abi_hash` at compile time.
+    pub fn abi_hash(&self, hashes: &HashMap<Ident, u64>) -> Result<u64> {
+        let fns = self
+            .fns
+            .iter()
+            .map(|f| f.abi_hash(hashes))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(rust2go_convert::layout_hash(&self.name.to_string(), &fns))
+    }
+
+    fn generate_rs_abi_hash(&self) -> TokenStream {
+        let name = self.name.to_string();
+        let fns = self.fns.iter().map(|f| f.to_rs_abi_hash());
+        quote! {::rust2go::layout_hash(#name, &[#(#fns),*])}
+    }
+
+    // Generate golang export of the abi hash.
+    pub fn generate_go_abi_hash(&self, hashes: &HashMap<Ident, u64>) -> Result<String> {
+        // //export CDemoCall_abi_hash
+        // func CDemoCall_abi_hash() C.uint64_t {
+        //     return C.uint64_t(0x1234)
+        // }
+        let fn_name = format!("C{}_abi_hash", self.name);
+        Ok(format!(
+            "//export {fn_name}\nfunc {fn_name}() C.uint64_t {{\nreturn C.uint64_t({:#x})\n}}\n",
+            self.abi_hash(hashes)?
+        ))
+    }
+
     // Generate golang exports.
-    pub fn generate_go_exports(&self, levels: &HashMap<Ident, u8>) -> String {
+    pub fn generate_go_exports(&self, levels: &HashMap<Ident, u8>) -> Result<String> {
         let name = self.name.to_string();
+        let go_impl = self.go_impl();
         let mut out: String = self
             .fns
             .iter()
-            .map(|f| f.to_go_export(&name, levels))
-            .collect();
-        let shm_cnt = self.fns.iter().filter(|f| f.mem_call_id.is_some()).count();
-        if shm_cnt != 0 {
-            let mem_ffi_handles = (0..shm_cnt)
-                .map(|id| format!("ringHandle{name}{id}"))
+            .map(|f| f.to_go_export(&name, &go_impl, levels))
+            .collect::<Result<_>>()?;
+        if self.has_object() {
+            // //export CDemoClient_release
+            // func CDemoClient_release(_handle C.uintptr_t) {
+            //     cgo.Handle(_handle).Delete()
+            // }
+            out.push_str(&format!("//export C{name}_release\nfunc C{name}_release(_handle C.uintptr_t) {{\ncgo.Handle(_handle).Delete()\n}}\n"));
+        }
+        for ring in self.shm_rings() {
+            let mem_ffi_handles = ring
+                .fns
+                .iter()
+                .map(|f| format!("ringHandle{name}{}", f.mem_call_id.unwrap()))
                 .collect::<Vec<String>>();
-            out.push_str(&format!("//export RingsInit{name}\nfunc RingsInit{name}(crr, crw C.QueueMeta) {{\nringsInit(crr, crw, []func(unsafe.Pointer, *ants.MultiPool, func(interface{{}}, []byte, uint)){{{}}})\n}}\n", mem_ffi_handles.join(",")));
+            // The pool settings are resolved at runtime by rust side.
+            out.push_str(&format!(
+                "//export {init}\nfunc {init}(crr, crw C.QueueMeta, goPools, goPoolSize C.int64_t) {{\nringsInit(crr, crw, []func(unsafe.Pointer, *ants.MultiPool, func(interface{{}}, []byte, uint)){{{}}}, int(goPools), int(goPoolSize))\n}}\n",
+                mem_ffi_handles.join(","),
+                init = ring.init_fn,
+            ));
         }
-        out
+        Ok(out)
     }
 
     // Generate golang interface.
-    pub fn generate_go_interface(&self) -> String {
+    pub fn generate_go_interface(&self) -> Result<String> {
         // var DemoCallImpl DemoCall
         // type DemoCall interface {
+        //     DemoParent
         //     demo_oneway(req DemoUser)
         //     demo_check(req DemoComplicatedRequest) DemoResponse
         //     demo_check_async(req DemoComplicatedRequest) DemoResponse
         // }
         let name = self.name.to_string();
-        let fns = self.fns.iter().map(|f| f.to_go_interface_method());
-
         let mut out = String::new();
+        if self.has_object() {
+            // Go objects implement the interface with &self methods, and the impl implements
+            // the constructors and the others:
+            // var DemoClientImpl DemoClientStatic
+            // type DemoClientStatic interface {
+            //     new(cfg DemoConfig) DemoClient
+            // }
+            // type DemoClient interface {
+            //     get(req DemoRequest) DemoResponse
+            // }
+            out.push_str(&format!(
+                "var {name}Impl {name}Static\ntype {name}Static interface {{\n"
+            ));
+            for f in self.fns.iter().filter(|f| !f.receiver) {
+                out.push_str(&f.docs.to_go(""));
+                out.push_str(&f.to_go_interface_method(&name)?);
+                out.push('\n');
+            }
+            out.push_str("}\n");
+            out.push_str(&self.docs.to_go(""));
+            out.push_str(&format!("type {name} interface {{\n"));
+            for f in self.fns.iter().filter(|f| f.receiver) {
+                out.push_str(&f.docs.to_go(""));
+                out.push_str(&f.to_go_interface_method(&name)?);
+                out.push('\n');
+            }
+            out.push_str("}\n");
+            return Ok(out);
+        }
         out.push_str(&format!("var {name}Impl {name}\n"));
+        if !self.descendants.is_empty() {
+            // func implDemoParent() DemoParent {
+            //     if DemoParentImpl != nil {
+            //         return DemoParentImpl
+            //     }
+            //     if DemoCallImpl != nil {
+            //         return DemoCallImpl
+            //     }
+            //     return DemoParentImpl
+            // }
+            out.push_str(&format!(
+                "func {}() {name} {{\n",
+                self.go_impl().trim_end_matches("()")
+            ));
+            for impl_ in std::iter::once(&self.name).chain(self.descendants.iter()) {
+                out.push_str(&format!(
+                    "if {impl_}Impl != nil {{\nreturn {impl_}Impl\n}}\n"
+                ));
+            }
+            out.push_str(&format!("return {name}Impl\n}}\n"));
+        }
+        out.push_str(&self.docs.to_go(""));
         out.push_str(&format!("type {name} interface {{\n"));
-        for f in fns {
-            out.push_str(&f);
+        for parent in self.parents.iter() {
+            out.push_str(&format!("{parent}\n"));
+        }
+        for f in self.fns.iter() {
+            out.push_str(&f.docs.to_go(""));
+            out.push_str(&f.to_go_interface_method(&name)?);
             out.push('\n');
         }
         out.push_str("}\n");
-        out
+        Ok(out)
     }
 
     // Generate rust impl, callbacks and binding mod include.
-    pub fn generate_rs(
-        &self,
-        binding_path: Option<&Path>,
-        queue_size: Option<usize>,
-    ) -> Result<TokenStream> {
+    pub fn generate_rs(&self, binding_path: Option<&Path>) -> Result<TokenStream> {
         const DEFAULT_BINDING_MOD: &str = "binding";
         let path_prefix = match binding_path {
             Some(p) => quote! {#p::},
@@ -269,93 +1108,358 @@ impl R2GTraitRepr {
         for f in self.fns.iter() {
             fn_trait_impls.push(f.to_rs_impl(&self.name, &path_prefix)?);
             fn_callbacks.push(f.to_rs_callback(&path_prefix)?);
+            fn_callbacks.push(f.to_rs_with_timeout(&self.name, &path_prefix));
         }
 
         let trait_name = &self.name;
+        let trait_name_str = trait_name.to_string();
         let impl_struct_name = format_ident!("{}Impl", trait_name);
+        let abi_hash = self.generate_rs_abi_hash();
+        let abi_hash_ffi = format_ident!("C{}_abi_hash", trait_name);
+        let docs = &self.docs;
 
-        let mem_init_ffi = format_ident!("RingsInit{}", trait_name);
-        let mut shm_init = None;
-        let mut shm_init_extc = None;
-        let mem_cnt = self.fns.iter().filter(|f| f.mem_call_id.is_some()).count();
-        let queue_size = queue_size.unwrap_or(4096);
-        if mem_cnt != 0 {
-            let mem_ffi_handles = (0..mem_cnt).map(|id| format_ident!("mem_ffi_handle{}", id));
-            shm_init = Some(quote! {
+        // Implement the supertraits by delegating to their impls, and let the traits extending
+        // this one do the same with the macro.
+        let delegate = delegate_macro(trait_name);
+        let delegate_fns = self
+            .fns
+            .iter()
+            .map(|f| f.to_rs_delegate(trait_name, &impl_struct_name));
+        let parent_delegates = self.parents.iter().map(|p| {
+            let m = delegate_macro(p);
+            quote! { #m!(#impl_struct_name); }
+        });
+        let parent_delegates_in_macro = self.parents.iter().map(|p| {
+            let m = delegate_macro(p);
+            quote! { #m!($impl_struct); }
+        });
+
+        let (mut shm_init, mut shm_init_extc) = (Vec::new(), Vec::new());
+        for ring in self.shm_rings() {
+            let ws = ring.rs_ident();
+            let abi_check = rs_abi_check(quote! { #impl_struct_name });
+            let mem_init_ffi = format_ident!("{}", ring.init_fn);
+            let ring_name = &ring.name;
+            let queue_size = ring.config.queue_size();
+            let go_pools = ring.config.go_pools() as i64;
+            let go_pool_size = ring.config.go_pool_size() as i64;
+            let mem_ffi_handles = ring
+                .fns
+                .iter()
+                .map(|f| format_ident!("mem_ffi_handle{}", f.mem_call_id.unwrap()));
+            // Settings in attributes can be overridden at runtime.
+            shm_init.push(quote! {
                 ::std::thread_local! {
-                    static WS: (::rust2go_mem_ffi::WriteQueue<::rust2go_mem_ffi::Payload>, ::rust2go_mem_ffi::SharedSlab) = {
-                        unsafe {::rust2go_mem_ffi::init_mem_ffi(#mem_init_ffi as *const (), #queue_size, [#(#impl_struct_name::#mem_ffi_handles),*])}
+                    static #ws: (::rust2go_mem_ffi::WriteQueue<::rust2go_mem_ffi::Payload>, ::rust2go_mem_ffi::SharedSlab) = {
+                        #abi_check
+                        let config = ::rust2go::Rust2goShmConfig::resolve(#ring_name)
+                            .unwrap_or_else(|e| panic!("unable to init shm ring {}: {e}", #ring_name));
+                        let go_pools = config.go_pools.map_or(#go_pools, |n| n as i64);
+                        let go_pool_size = config.go_pool_size.map_or(#go_pool_size, |n| n as i64);
+                        unsafe {
+                            ::rust2go_mem_ffi::init_mem_ffi_with(
+                                |rr, rw| #mem_init_ffi(rr, rw, go_pools, go_pool_size),
+                                config.queue_size.unwrap_or(#queue_size),
+                                [#(#impl_struct_name::#mem_ffi_handles),*],
+                            )
+                        }
+                        .unwrap_or_else(|e| panic!("unable to init shm ring {}: {e}", #ring_name))
                     };
                 }
             });
-            shm_init_extc = Some(quote! {
+            shm_init_extc.push(quote! {
                 extern "C" {
-                    fn #mem_init_ffi(rr: ::rust2go_mem_ffi::QueueMeta, rw: ::rust2go_mem_ffi::QueueMeta);
+                    fn #mem_init_ffi(rr: ::rust2go_mem_ffi::QueueMeta, rw: ::rust2go_mem_ffi::QueueMeta, go_pools: i64, go_pool_size: i64);
                 }
-            })
+            });
         }
 
+        // The impl of traits with go objects holds the handle, and they can't be extended.
+        let (impl_struct, delegate_body) = match self.has_object() {
+            true => {
+                let msg = format!("{trait_name} holds go objects and cannot be extended");
+                (
+                    quote! { pub struct #impl_struct_name { handle: ::rust2go::GoHandle } },
+                    quote! { compile_error!(#msg); },
+                )
+            }
+            false => (
+                quote! { pub struct #impl_struct_name; },
+                quote! {
+                    impl #trait_name for $impl_struct {
+                        #(#delegate_fns)*
+                    }
+                    #(#parent_delegates_in_macro)*
+                },
+            ),
+        };
+
         Ok(quote! {
-            #shm_init_extc
-            pub struct #impl_struct_name;
+            #[doc(hidden)]
+            #[allow(unused_macros)]
+            macro_rules! #delegate {
+                ($impl_struct: ty) => {
+                    #delegate_body
+                };
+            }
+            #(#shm_init_extc)*
+            #docs
+            #impl_struct
+            #(#parent_delegates)*
             impl #trait_name for #impl_struct_name {
                 #(#fn_trait_impls)*
             }
             impl #impl_struct_name {
-                #shm_init
+                pub const ABI_HASH: u64 = #abi_hash;
+
+                /// Check the linked go library is generated from the same interface, which is
+                /// skipped if the layout of any type is unknown. Calls check it on first use, and
+                /// shm calls check it when the ring of the thread is created.
+                pub fn verify() -> ::std::result::Result<(), ::rust2go::AbiMismatch> {
+                    if Self::ABI_HASH == ::rust2go::UNKNOWN_LAYOUT {
+                        return Ok(());
+                    }
+                    static GO_ABI_HASH: ::std::sync::OnceLock<u64> = ::std::sync::OnceLock::new();
+                    let go = *GO_ABI_HASH.get_or_init(|| unsafe { #path_prefix #abi_hash_ffi() } as u64);
+                    if go != Self::ABI_HASH {
+                        return Err(::rust2go::AbiMismatch {
+                            trait_name: #trait_name_str,
+                            rust: Self::ABI_HASH,
+                            go,
+                        });
+                    }
+                    Ok(())
+                }
+
+                #(#shm_init)*
                 #(#fn_callbacks)*
             }
         })
     }
-}
+}
+
+impl R2GFnRepr {
+    pub const fn name(&self) -> &Ident {
+        &self.name
+    }
+
+    pub const fn is_async(&self) -> bool {
+        self.is_async
+    }
+
+    pub const fn drop_safe(&self) -> bool {
+        self.drop_safe
+    }
+
+    pub const fn drop_safe_ret_params(&self) -> bool {
+        self.drop_safe_ret_params
+    }
+
+    pub const fn is_safe(&self) -> bool {
+        self.is_safe
+    }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    pub fn ret(&self) -> Option<&ParamType> {
+        self.ret.as_ref()
+    }
+
+    pub const fn ret_send(&self) -> bool {
+        self.ret_send
+    }
+
+    pub const fn ret_static(&self) -> bool {
+        self.ret_static
+    }
+
+    pub const fn mem_call_id(&self) -> Option<usize> {
+        self.mem_call_id
+    }
+
+    /// The ring of shm call, 0 for the ring shared by the trait.
+    pub const fn mem_ring(&self) -> usize {
+        self.mem_ring
+    }
+
+    /// Settings of the dedicated ring.
+    pub const fn dedicated_shm(&self) -> Option<&ShmConfig> {
+        self.dedicated_shm.as_ref()
+    }
+
+    pub const fn cgo_callback(&self) -> bool {
+        self.cgo_cb
+    }
+
+    pub const fn go_pass_struct(&self) -> bool {
+        !self.go_ptr
+    }
+
+    pub const fn cancel(&self) -> bool {
+        self.cancel
+    }
+
+    pub const fn timeout_ms(&self) -> Option<u64> {
+        self.timeout_ms
+    }
+
+    // Whether the go method takes a context.
+    const fn go_ctx(&self) -> bool {
+        self.cancel || self.timeout_ms.is_some() || self.stream.is_some()
+    }
+
+    pub const fn go_panic(&self) -> Option<GoPanicMode> {
+        self.go_panic
+    }
+
+    /// Whether it is a `&self` method of the go object.
+    pub const fn receiver(&self) -> bool {
+        self.receiver
+    }
+
+    /// Whether it returns `Self` with a new go object.
+    pub const fn constructor(&self) -> bool {
+        self.constructor
+    }
 
-impl R2GFnRepr {
-    pub const fn name(&self) -> &Ident {
-        &self.name
+    /// Name of the last param taking a rust closure, and the type of its argument.
+    pub fn callback(&self) -> Option<&Param> {
+        self.callback.as_ref().map(|c| &c.param)
     }
 
-    pub const fn is_async(&self) -> bool {
-        self.is_async
+    /// Item type of the returned stream.
+    pub fn stream(&self) -> Option<&ParamType> {
+        self.stream.as_ref().map(|s| &s.item)
     }
 
-    pub const fn drop_safe_ret_params(&self) -> bool {
-        self.drop_safe_ret_params
+    /// Name of the last param taking a rust stream, and the type of its item.
+    pub fn stream_param(&self) -> Option<&Param> {
+        self.stream_param.as_ref().map(|s| &s.param)
     }
 
-    pub const fn is_safe(&self) -> bool {
-        self.is_safe
+    // The last param taking a rust closure or stream.
+    fn last_param(&self) -> Option<&Callback> {
+        self.callback.as_ref().or(self.stream_param.as_ref())
     }
 
-    pub fn params(&self) -> &[Param] {
-        &self.params
+    // Value written to the slot by the callback, with the exceeded deadline and the recovered
+    // go panic as errors.
+    fn rs_slot_ret(&self) -> Option<TokenStream> {
+        let ret = match &self.ret {
+            Some(ret) => quote! { #ret },
+            None if self.go_panic.is_some() => quote! { () },
+            None => return None,
+        };
+        Some(match (self.timeout_ms.is_some(), self.go_panic.is_some()) {
+            (false, false) => ret,
+            (true, false) => quote! { ::std::result::Result<#ret, ::rust2go::DeadlineExceeded> },
+            (false, true) => quote! { ::std::result::Result<#ret, ::rust2go::GoPanic> },
+            (true, true) => quote! { ::std::result::Result<#ret, ::rust2go::CallError> },
+        })
     }
 
-    pub fn ret(&self) -> Option<&ParamType> {
-        self.ret.as_ref()
+    /// Return type of the method, or the output of the future without returned params for the
+    /// async method.
+    pub fn rs_ret(&self) -> Option<TokenStream> {
+        match (self.go_panic, &self.ret) {
+            (Some(GoPanicMode::Resume), None) => None,
+            (Some(GoPanicMode::Resume), Some(ret)) if self.timeout_ms.is_some() => {
+                Some(quote! { ::std::result::Result<#ret, ::rust2go::DeadlineExceeded> })
+            }
+            (Some(GoPanicMode::Resume), Some(ret)) => Some(quote! { #ret }),
+            _ => self.rs_slot_ret(),
+        }
     }
 
-    pub const fn ret_send(&self) -> bool {
-        self.ret_send
+    fn with_ret_params(&self, ret: TokenStream) -> TokenStream {
+        if self.drop_safe_ret_params {
+            let tys = self.params.iter().map(|p| &p.ty);
+            quote! { (#ret, (#(#tys,)*)) }
+        } else {
+            ret
+        }
     }
 
-    pub const fn ret_static(&self) -> bool {
-        self.ret_static
+    /// Output of the future returned by the async method.
+    pub fn rs_future_output(&self) -> TokenStream {
+        self.with_ret_params(self.rs_ret().unwrap_or_else(|| quote! { () }))
     }
 
-    pub const fn mem_call_id(&self) -> Option<usize> {
-        self.mem_call_id
+    // Output of the future before the recovered go panic is resumed.
+    fn rs_slot_output(&self) -> TokenStream {
+        self.with_ret_params(self.rs_slot_ret().unwrap_or_else(|| quote! { () }))
     }
 
-    pub const fn cgo_callback(&self) -> bool {
-        self.cgo_cb
+    // [flags, mem call id], flags: bit0 is async, bit1 has return value, bit2 is cancellable,
+    // bit3 has deadline, bit4 recovers go panics, bit5 has receiver, bit6 is constructor, bit7
+    // takes a rust closure, bit8 returns a stream, bit9 takes a rust stream.
+    // Dedicated rings are in the high bits of call id.
+    fn abi_header(&self) -> [u64; 2] {
+        let flags = self.is_async as u64
+            | (self.ret.is_some() as u64) << 1
+            | (self.cancel as u64) << 2
+            | (self.timeout_ms.is_some() as u64) << 3
+            | (self.go_panic.is_some() as u64) << 4
+            | (self.receiver as u64) << 5
+            | (self.constructor as u64) << 6
+            | (self.callback.is_some() as u64) << 7
+            | (self.stream.is_some() as u64) << 8
+            | (self.stream_param.is_some() as u64) << 9;
+        let call_id = self
+            .mem_call_id
+            .map(|id| id as u64 | (self.mem_ring as u64) << 32)
+            .unwrap_or(u64::MAX);
+        [flags, call_id]
+    }
+
+    fn abi_hash(&self, hashes: &HashMap<Ident, u64>) -> Result<u64> {
+        let mut children = self.abi_header().to_vec();
+        for p in self
+            .params
+            .iter()
+            .chain(self.last_param().map(|c| &c.param))
+        {
+            children.push(p.ty.layout_hash(hashes)?);
+        }
+        if let Some(ret) = self.ret.as_ref().or(self.stream()) {
+            children.push(ret.layout_hash(hashes)?);
+        }
+        Ok(rust2go_convert::layout_hash(
+            &self.name.to_string(),
+            &children,
+        ))
     }
 
-    fn to_go_export(&self, trait_name: &str, levels: &HashMap<Ident, u8>) -> String {
+    fn to_rs_abi_hash(&self) -> TokenStream {
+        let name = self.name.to_string();
+        let [flags, call_id] = self.abi_header();
+        let types = self
+            .params
+            .iter()
+            .chain(self.last_param().map(|c| &c.param))
+            .map(|p| p.ty.to_owned_tokens())
+            .chain(
+                self.ret
+                    .iter()
+                    .chain(self.stream())
+                    .map(|r| r.to_owned_tokens()),
+            );
+        quote! {::rust2go::layout_hash(#name, &[#flags, #call_id, #(<#types as ::rust2go::ToRef>::LAYOUT_HASH),*])}
+    }
+
+    fn to_go_export(
+        &self,
+        trait_name: &str,
+        go_impl: &str,
+        levels: &HashMap<Ident, u8>,
+    ) -> Result<String> {
         let ref_mark = BoolMark::new(self.go_ptr, "&");
         if let Some(mem_call_id) = self.mem_call_id {
             let fn_sig = format!("func ringHandle{trait_name}{mem_call_id}(ptr unsafe.Pointer, pool *ants.MultiPool, post_func func(interface{{}}, []byte, uint)) {{\n");
             let Some(ret) = &self.ret else {
-                return format!("{fn_sig}post_func(nil, nil, 0)\n}}\n");
+                return Ok(format!("{fn_sig}post_func(nil, nil, 0)\n}}\n"));
             };
 
             let mut fn_body = String::new();
@@ -364,7 +1468,7 @@ impl R2GFnRepr {
                 fn_body.push_str(&format!(
                     "{name}:=*(*C.{ref_type})(ptr)\n",
                     name = p.name,
-                    ref_type = p.ty.to_c(false)
+                    ref_type = p.ty.to_c(false)?
                 ));
                 if idx + 1 != params_len {
                     fn_body.push_str(&format!(
@@ -375,64 +1479,234 @@ impl R2GFnRepr {
                 fn_body.push_str(&format!(
                     "{name}_:={cvt}({name})\n",
                     name = p.name,
-                    cvt = p.ty.c_to_go_field_converter(levels).0
+                    cvt = p.ty.c_to_go_field_converter(levels)?.0
                 ));
             }
             fn_body.push_str("pool.Submit(func() {\n");
-            fn_body.push_str(&format!(
-                "resp := {trait_name}Impl.{fn_name}({params})\n",
-                fn_name = self.name,
-                params = self
-                    .params
-                    .iter()
-                    .map(|p| format!("{ref_mark}{}_", p.name))
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            ));
-            fn_body.push_str(&format!(
-                "resp_ref_size := uint(unsafe.Sizeof(C.{}{{}}))\n",
-                ret.to_c(false)
-            ));
+            let params = self
+                .params
+                .iter()
+                .flat_map(|p| p.ty.to_go_args(&format!("{}_", p.name), &ref_mark))
+                .collect::<Vec<_>>()
+                .join(", ");
+            fn_body.push_str(&self.go_call(&format!(
+                "{go_impl}.{fn_name}({params})",
+                fn_name = self.name
+            ))?);
             let (g2c_cnt, g2c_cvt) = (
-                ret.go_to_c_field_counter(levels).0,
-                ret.go_to_c_field_converter(levels).0,
+                ret.go_to_c_field_counter(levels)?.0,
+                ret.go_to_c_field_converter(levels)?.0,
             );
-            fn_body.push_str(&format!("resp_ref, buffer := cvt_ref_cap({g2c_cnt}, {g2c_cvt}, resp_ref_size)(&resp)\noffset := uint(len(buffer))\nbuffer = append(buffer, unsafe.Slice((*byte)(unsafe.Pointer(&resp_ref)), resp_ref_size)...)\n"));
+            if self.go_panic.is_some() {
+                // The response is a `RecoveredRef`, the panic message is held with the buffer.
+                let recovered_ty = Self::go_recovered_ty(ret)?;
+                fn_body.push_str(&format!("if _panic != \"\" {{\n_ret := {recovered_ty}{{panic: refString(&_panic, nil)}}\nbuffer := append([]byte(nil), unsafe.Slice((*byte)(unsafe.Pointer(&_ret)), unsafe.Sizeof(_ret))...)\npost_func(_panic, buffer, 0)\nreturn\n}}\n"));
+                fn_body.push_str(&format!(
+                    "resp_ref_size := uint(unsafe.Sizeof({recovered_ty}{{}}))\n"
+                ));
+                fn_body.push_str(&format!("resp_ref, buffer := cvt_ref_cap({g2c_cnt}, {g2c_cvt}, resp_ref_size)(&resp)\noffset := uint(len(buffer))\n_ret := {recovered_ty}{{resp: resp_ref}}\nbuffer = append(buffer, unsafe.Slice((*byte)(unsafe.Pointer(&_ret)), resp_ref_size)...)\n"));
+            } else {
+                fn_body.push_str(&format!(
+                    "resp_ref_size := uint(unsafe.Sizeof(C.{}{{}}))\n",
+                    ret.to_c(false)?
+                ));
+                fn_body.push_str(&format!("resp_ref, buffer := cvt_ref_cap({g2c_cnt}, {g2c_cvt}, resp_ref_size)(&resp)\noffset := uint(len(buffer))\nbuffer = append(buffer, unsafe.Slice((*byte)(unsafe.Pointer(&resp_ref)), resp_ref_size)...)\n"));
+            }
             fn_body.push_str("post_func(resp, buffer, offset)\n})\n");
             let fn_ending = "}\n";
-            return format!("{fn_sig}{fn_body}{fn_ending}");
+            return Ok(format!("{fn_sig}{fn_body}{fn_ending}"));
         }
 
         let mut out = String::new();
         let fn_name = format!("C{}_{}", trait_name, self.name);
         out.push_str(&format!("//export {fn_name}\nfunc {fn_name}("));
-        self.params
-            .iter()
-            .for_each(|p| out.push_str(&format!("{} C.{}, ", p.name, p.ty.to_c(false))));
+        // Methods of the go object are called on the value of the handle.
+        let go_impl = match self.receiver {
+            true => {
+                out.push_str("_handle C.uintptr_t, ");
+                "_self"
+            }
+            false => go_impl,
+        };
+        for p in self.params.iter() {
+            out.push_str(&format!("{} C.{}, ", p.name, p.ty.to_c(false)?));
+        }
+        if let Some(c) = self.last_param() {
+            out.push_str(&format!("{} *C.void, ", c.param.name));
+        }
+        let call_cb = match self.cgo_cb {
+            true => "cgocall.CallFuncG0P2",
+            false => "asmcall.CallFuncG0P2",
+        };
 
         let mut new_names = Vec::new();
         let mut new_cvt = String::new();
+        if self.receiver {
+            new_cvt.push_str(&format!(
+                "_self := cgo.Handle(_handle).Value().({trait_name})\n"
+            ));
+        }
         for p in self.params.iter() {
             let new_name = format_ident!("_new_{}", p.name);
-            let cvt = p.ty.c_to_go_field_converter(levels).0;
+            // The go method may outlive the call when the deadline is exceeded or it returns a
+            // stream, and the params of constructors are likely to be held by the object.
+            let cvt = match self.timeout_ms.is_some() || self.constructor || self.stream.is_some() {
+                true => p.ty.c_to_go_field_converter_owned()?,
+                false => p.ty.c_to_go_field_converter(levels)?.0,
+            };
             new_cvt.push_str(&format!("{new_name} := {cvt}({})\n", p.name));
-            new_names.push(format!("{ref_mark}{new_name}"));
+            new_names.extend(p.ty.to_go_args(&new_name.to_string(), &ref_mark));
+        }
+        if let Some(cb) = self.callback() {
+            // The closure is borrowed by sync calls, and owned by go side for async calls:
+            // _cb_progress := &r2gCallback{ptr: unsafe.Pointer(progress), owned: true}
+            // _new_progress := func(_arg DemoEvent) bool {
+            //     _arg_ref, buffer := cvt_ref(cntDemoEvent, refDemoEvent)(&_arg)
+            //     _ok := _cb_progress.call(asmcall.CallFuncG0P3, asmcall.CallFuncG0P1, unsafe.Pointer(&_arg_ref))
+            //     runtime.KeepAlive(_arg)
+            //     runtime.KeepAlive(buffer)
+            //     return _ok
+            // }
+            let name = &cb.name;
+            let call_fns = match self.cgo_cb {
+                true => "cgocall.CallFuncG0P3, cgocall.CallFuncG0P1",
+                false => "asmcall.CallFuncG0P3, asmcall.CallFuncG0P1",
+            };
+            new_cvt.push_str(&format!(
+                "_cb_{name} := &r2gCallback{{ptr: unsafe.Pointer({name}), owned: {}}}
+",
+                self.is_async
+            ));
+            new_cvt.push_str(&format!(
+                "_new_{name} := func(_arg {}) bool {{\n_arg_ref, buffer := cvt_ref({}, {})(&_arg)\n_ok := _cb_{name}.call({call_fns}, unsafe.Pointer(&_arg_ref))\nruntime.KeepAlive(_arg)\nruntime.KeepAlive(buffer)\nreturn _ok\n}}\n",
+                cb.ty.to_go()?,
+                cb.ty.go_to_c_field_counter(levels)?.0,
+                cb.ty.go_to_c_field_converter(levels)?.0,
+            ));
+            new_names.push(format!("_new_{name}"));
+        }
+        if let Some(sp) = self.stream_param() {
+            // Items are polled when the previous one is received, and the stream is borrowed by
+            // sync calls and owned by go side for async calls:
+            // _rs_chunks := newR2gRustStream(unsafe.Pointer(chunks), true)
+            // _new_chunks := make(chan DemoChunk)
+            // go func() {
+            //     _rs_chunks.run(func(_item unsafe.Pointer) bool {
+            //         select {
+            //         case _new_chunks <- ownDemoChunk(*(*C.DemoChunkRef)(_item)):
+            //             return true
+            //         case <-_rs_chunks.stopped:
+            //             return false
+            //         }
+            //     })
+            //     close(_new_chunks)
+            // }()
+            let name = &sp.name;
+            new_cvt.push_str(&format!(
+                "_rs_{name} := newR2gRustStream(unsafe.Pointer({name}), {})\n_new_{name} := make(chan {})\n",
+                self.is_async,
+                sp.ty.to_go()?
+            ));
+            new_cvt.push_str(&format!(
+                "go func() {{\n_rs_{name}.run(func(_item unsafe.Pointer) bool {{\nselect {{\ncase _new_{name} <- {}(*(*C.{})(_item)):\nreturn true\ncase <-_rs_{name}.stopped:\nreturn false\n}}\n}})\nclose(_new_{name})\n}}()\n",
+                sp.ty.c_to_go_field_converter_owned()?,
+                sp.ty.to_c(false)?
+            ));
+            new_names.push(format!("_new_{name}"));
+        }
+        if self.constructor {
+            // //export CDemoClient_new
+            // func CDemoClient_new(cfg C.DemoConfigRef) C.uintptr_t {
+            //     _new_cfg := ownDemoConfig(cfg)
+            //     return C.uintptr_t(cgo.NewHandle(DemoClientImpl.new(_new_cfg)))
+            // }
+            out.push_str(") C.uintptr_t {\n");
+            out.push_str(&new_cvt);
+            out.push_str(&format!(
+                "return C.uintptr_t(cgo.NewHandle({go_impl}.{fn_name}({params})))\n}}\n",
+                fn_name = self.name,
+                params = new_names.join(", ")
+            ));
+            return Ok(out);
+        }
+        if let Some(item) = self.stream() {
+            // //export CDemoCall_demo_tail
+            // func CDemoCall_demo_tail(req C.DemoRequestRef, _stream *C.void) {
+            //     _new_req := ownDemoRequest(req)
+            //     _ctx, _cancel := context.WithCancel(context.Background())
+            //     _s := &r2gStream{ptr: unsafe.Pointer(_stream), ready: make(chan struct{}, 1), cancel: _cancel}
+            //     r2gStreams.Store(uintptr(unsafe.Pointer(_stream)), _s)
+            //     go func() {
+            //         DemoCallImpl.demo_tail(_ctx, &_new_req, func(_item DemoEvent) bool {
+            //             _item_ref, buffer := cvt_ref(cntDemoEvent, refDemoEvent)(&_item)
+            //             _ok := _s.send(_ctx, asmcall.CallFuncG0P2, unsafe.Pointer(&_item_ref))
+            //             runtime.KeepAlive(_item)
+            //             runtime.KeepAlive(buffer)
+            //             return _ok
+            //         })
+            //         r2gStreams.Delete(uintptr(unsafe.Pointer(_stream)))
+            //         _cancel()
+            //         C.c_rust2go_stream_close(unsafe.Pointer(_stream))
+            //     }()
+            // }
+            out.push_str("_stream *C.void) {\n");
+            out.push_str(&new_cvt);
+            out.push_str("_ctx, _cancel := context.WithCancel(context.Background())\n_s := &r2gStream{ptr: unsafe.Pointer(_stream), ready: make(chan struct{}, 1), cancel: _cancel}\nr2gStreams.Store(uintptr(unsafe.Pointer(_stream)), _s)\n");
+            new_names.insert(0, "_ctx".to_string());
+            new_names.push(format!(
+                "func(_item {}) bool {{\n_item_ref, buffer := cvt_ref({}, {})(&_item)\n_ok := _s.send(_ctx, {call_cb}, unsafe.Pointer(&_item_ref))\nruntime.KeepAlive(_item)\nruntime.KeepAlive(buffer)\nreturn _ok\n}}",
+                item.to_go()?,
+                item.go_to_c_field_counter(levels)?.0,
+                item.go_to_c_field_converter(levels)?.0,
+            ));
+            out.push_str(&format!(
+                "    go func() {{\n{go_impl}.{fn_name}({params})\n",
+                fn_name = self.name,
+                params = new_names.join(", ")
+            ));
+            out.push_str("r2gStreams.Delete(uintptr(unsafe.Pointer(_stream)))\n_cancel()\nC.c_rust2go_stream_close(unsafe.Pointer(_stream))\n}()\n}\n");
+            return Ok(out);
         }
         match (self.is_async, &self.ret) {
-            (true, None) => panic!("async function must have a return value"),
+            (true, None) => sbail!(&self.name, "async function must have a return value"),
             (false, None) => {
                 // //export CDemoCall_demo_oneway
                 // func CDemoCall_demo_oneway(req C.DemoUserRef) {
                 //     DemoCallImpl.demo_oneway(newDemoUser(req))
                 // }
-                out.push_str(") {\n");
-                out.push_str(&new_cvt);
-                out.push_str(&format!(
-                    "    {trait_name}Impl.{fn_name}({params})\n",
+                //
+                // With recovered panics, the callback is called only if the impl panics:
+                // func CDemoCall_demo_oneway(req C.DemoUserRef, slot *C.void, cb *C.void) {
+                //     _panic := func() (_panic string) {
+                //         defer r2gRecover(&_panic)
+                //         DemoCallImpl.demo_oneway(newDemoUser(req))
+                //         return ""
+                //     }()
+                //     if _panic != "" {
+                //         _ret := refString(&_panic, nil)
+                //         asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+                //         runtime.KeepAlive(_panic)
+                //     }
+                // }
+                let call = format!(
+                    "{go_impl}.{fn_name}({params})",
                     fn_name = self.name,
                     params = new_names.join(", ")
+                );
+                if self.go_panic.is_none() {
+                    out.push_str(") {\n");
+                    out.push_str(&new_cvt);
+                    out.push_str(&format!("    {}", self.go_call(&call)?));
+                    out.push_str("}\n");
+                    return Ok(out);
+                }
+                out.push_str("slot *C.void, cb *C.void) {\n");
+                out.push_str(&new_cvt);
+                out.push_str(&self.go_call(&call)?);
+                out.push_str(&format!(
+                    "if _panic != \"\" {{\n{}}}\n}}\n",
+                    self.go_respond_panic(call_cb)?
                 ));
-                out.push_str("}\n");
             }
             (false, Some(ret)) => {
                 // //export CDemoCall_demo_check
@@ -444,26 +1718,35 @@ impl R2GFnRepr {
                 //     runtime.KeepAlive(resp)
                 //     runtime.KeepAlive(buffer)
                 // }
+                //
+                // With recovered panics, the response is a `RecoveredRef`:
+                //     resp, _panic := func() (_resp DemoResponse, _panic string) {
+                //         ...
+                //     }()
+                //     if _panic != "" {
+                //         _ret := struct{resp C.DemoResponseRef; panic C.StringRef}{panic: refString(&_panic, nil)}
+                //         asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+                //         runtime.KeepAlive(_panic)
+                //         return
+                //     }
+                //     resp_ref, buffer := cvt_ref(cntDemoResponse, refDemoResponse)(&resp)
+                //     _ret := struct{resp C.DemoResponseRef; panic C.StringRef}{resp: resp_ref}
+                //     asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+                //     ...
                 out.push_str("slot *C.void, cb *C.void) {\n");
                 out.push_str(&new_cvt);
-                out.push_str(&format!(
-                    "resp := {trait_name}Impl.{fn_name}({params})\n",
+                out.push_str(&self.go_call(&format!(
+                    "{go_impl}.{fn_name}({params})",
                     fn_name = self.name,
                     params = new_names.join(", ")
-                ));
-                let (g2c_cnt, g2c_cvt) = (
-                    ret.go_to_c_field_counter(levels).0,
-                    ret.go_to_c_field_converter(levels).0,
-                );
-                out.push_str(&format!(
-                    "resp_ref, buffer := cvt_ref({g2c_cnt}, {g2c_cvt})(&resp)\n"
-                ));
-                if self.cgo_cb {
-                    out.push_str("cgocall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&resp_ref), unsafe.Pointer(slot))\n");
-                } else {
-                    out.push_str("asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&resp_ref), unsafe.Pointer(slot))\n");
+                ))?);
+                if self.go_panic.is_some() {
+                    out.push_str(&format!(
+                        "if _panic != \"\" {{\n{}return\n}}\n",
+                        self.go_respond_panic(call_cb)?
+                    ));
                 }
-                out.push_str("runtime.KeepAlive(resp_ref)\nruntime.KeepAlive(resp)\nruntime.KeepAlive(buffer)\n");
+                out.push_str(&self.go_respond(ret, call_cb, levels)?);
                 out.push_str("}\n");
             }
             (true, Some(ret)) => {
@@ -479,47 +1762,288 @@ impl R2GFnRepr {
                 //         runtime.KeepAlive(buffer)
                 //     }()
                 // }
+                //
+                // With #[cancel], the context is cancelled by rust side when the future is dropped:
+                //     _ctx, _cancel := context.WithCancel(context.Background())
+                //     r2gCancels.Store(uintptr(unsafe.Pointer(slot)), _cancel)
+                //     go func() {
+                //         resp := DemoCallImpl.demo_check_async(_ctx, _new_req)
+                //         r2gCancels.Delete(uintptr(unsafe.Pointer(slot)))
+                //         _cancel()
+                //         ...
+                //
+                // With a deadline, the params are copied and the response is abandoned when it
+                // is exceeded, which is reported to rust side with a nil response:
+                // func CDemoCall_demo_check_async(req C.DemoComplicatedRequestRef, _timeout C.int64_t, slot *C.void, cb *C.void) {
+                //     _new_req := ownDemoComplicatedRequest(req)
+                //     _ctx, _cancel := context.WithTimeout(context.Background(), time.Duration(_timeout))
+                //     go func() {
+                //         _done := make(chan DemoResponse, 1)
+                //         go func() {
+                //             resp := DemoCallImpl.demo_check_async(_ctx, &_new_req)
+                //             _done <- resp
+                //         }()
+                //         select {
+                //         case resp := <-_done:
+                //             _cancel()
+                //             ...
+                //         case <-_ctx.Done():
+                //             _cancel()
+                //             asmcall.CallFuncG0P2(unsafe.Pointer(cb), nil, unsafe.Pointer(slot))
+                //         }
+                //     }()
+                // }
+                if self.timeout_ms.is_some() {
+                    out.push_str("_timeout C.int64_t, ");
+                }
                 out.push_str("slot *C.void, cb *C.void) {\n");
                 out.push_str(&new_cvt);
+                if self.timeout_ms.is_some() {
+                    out.push_str("_ctx, _cancel := context.WithTimeout(context.Background(), time.Duration(_timeout))\n");
+                } else if self.cancel {
+                    out.push_str("_ctx, _cancel := context.WithCancel(context.Background())\n");
+                }
+                if self.cancel {
+                    out.push_str("r2gCancels.Store(uintptr(unsafe.Pointer(slot)), _cancel)\n");
+                }
+                if self.go_ctx() {
+                    new_names.insert(0, "_ctx".to_string());
+                }
+                // Unregister before the slot is written, after that it may be reused.
+                let release_ctx = match (self.cancel, self.go_ctx()) {
+                    (true, _) => "r2gCancels.Delete(uintptr(unsafe.Pointer(slot)))\n_cancel()\n",
+                    (false, true) => "_cancel()\n",
+                    (false, false) => "",
+                };
                 out.push_str("    go func() {\n");
-                out.push_str(&format!(
-                    "resp := {trait_name}Impl.{fn_name}({params})\n",
+                let call = self.go_call(&format!(
+                    "{go_impl}.{fn_name}({params})",
                     fn_name = self.name,
                     params = new_names.join(", ")
-                ));
-                let (g2c_cnt, g2c_cvt) = (
-                    ret.go_to_c_field_counter(levels).0,
-                    ret.go_to_c_field_converter(levels).0,
-                );
-                out.push_str(&format!(
-                    "resp_ref, buffer := cvt_ref({g2c_cnt}, {g2c_cvt})(&resp)\n"
-                ));
-                if self.cgo_cb {
-                    out.push_str("cgocall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&resp_ref), unsafe.Pointer(slot))\n");
-                } else {
-                    out.push_str("asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&resp_ref), unsafe.Pointer(slot))\n");
+                ))?;
+                // With recovered panics and a deadline, the panic is sent with another channel:
+                //         _panicked := make(chan string, 1)
+                //         go func() {
+                //             resp, _panic := func() (_resp DemoResponse, _panic string) {
+                //                 ...
+                //             }()
+                //             if _panic != "" {
+                //                 _panicked <- _panic
+                //                 return
+                //             }
+                //             _done <- resp
+                //         }()
+                //         select {
+                //         case resp := <-_done:
+                //             ...
+                //         case _panic := <-_panicked:
+                //             _cancel()
+                //             _ret := struct{resp C.DemoResponseRef; panic C.StringRef}{panic: refString(&_panic, nil)}
+                //             ...
+                match (self.timeout_ms.is_some(), self.go_panic.is_some()) {
+                    (true, false) => {
+                        out.push_str(&format!(
+                            "_done := make(chan {}, 1)\ngo func() {{\n{call}_done <- resp\n}}()\n",
+                            ret.to_go()?
+                        ));
+                        out.push_str("select {\ncase resp := <-_done:\n");
+                        out.push_str(release_ctx);
+                    }
+                    (true, true) => {
+                        out.push_str(&format!(
+                            "_done := make(chan {}, 1)\n_panicked := make(chan string, 1)\ngo func() {{\n{call}if _panic != \"\" {{\n_panicked <- _panic\nreturn\n}}\n_done <- resp\n}}()\n",
+                            ret.to_go()?
+                        ));
+                        out.push_str("select {\ncase resp := <-_done:\n");
+                        out.push_str(release_ctx);
+                    }
+                    (false, false) => {
+                        out.push_str(&call);
+                        out.push_str(release_ctx);
+                    }
+                    (false, true) => {
+                        out.push_str(&call);
+                        out.push_str(release_ctx);
+                        out.push_str(&format!(
+                            "if _panic != \"\" {{\n{}return\n}}\n",
+                            self.go_respond_panic(call_cb)?
+                        ));
+                    }
+                }
+                out.push_str(&self.go_respond(ret, call_cb, levels)?);
+                if self.timeout_ms.is_some() && self.go_panic.is_some() {
+                    out.push_str("case _panic := <-_panicked:\n");
+                    out.push_str(release_ctx);
+                    out.push_str(&self.go_respond_panic(call_cb)?);
+                }
+                if self.timeout_ms.is_some() {
+                    out.push_str("case <-_ctx.Done():\n");
+                    out.push_str(release_ctx);
+                    out.push_str(&format!(
+                        "{call_cb}(unsafe.Pointer(cb), nil, unsafe.Pointer(slot))\n}}\n"
+                    ));
                 }
-                out.push_str("runtime.KeepAlive(resp_ref)\nruntime.KeepAlive(resp)\nruntime.KeepAlive(buffer)\n");
                 out.push_str("}()\n}\n");
+                if self.cancel {
+                    // //export CDemoCall_demo_check_async_cancel
+                    // func CDemoCall_demo_check_async_cancel(slot *C.void) {
+                    //     if cancel, ok := r2gCancels.LoadAndDelete(uintptr(unsafe.Pointer(slot))); ok {
+                    //         cancel.(context.CancelFunc)()
+                    //     }
+                    // }
+                    out.push_str(&format!(
+                        "//export {fn_name}_cancel\nfunc {fn_name}_cancel(slot *C.void) {{\n"
+                    ));
+                    out.push_str("if cancel, ok := r2gCancels.LoadAndDelete(uintptr(unsafe.Pointer(slot))); ok {\ncancel.(context.CancelFunc)()\n}\n}\n");
+                }
             }
         }
-        out
+        Ok(out)
+    }
+
+    // Call of the go impl. With recovered panics, it is wrapped in a func which also returns the
+    // panic message, which is empty if the impl returns:
+    //     resp, _panic := func() (_resp DemoResponse, _panic string) {
+    //         defer r2gRecover(&_panic)
+    //         resp := DemoCallImpl.demo_check(_new_req)
+    //         return resp, ""
+    //     }()
+    //
+    // The closure passed to the method can't be called after it returns, and the stream is
+    // no longer polled.
+    fn go_call(&self, call: &str) -> Result<String> {
+        let mut out = self.go_call_recovered(call)?;
+        if let Some(cb) = self.callback() {
+            out.push_str(&format!("_cb_{}.done()\n", cb.name));
+        }
+        if let Some(sp) = self.stream_param() {
+            out.push_str(&format!("_rs_{}.stop()\n", sp.name));
+        }
+        Ok(out)
+    }
+
+    fn go_call_recovered(&self, call: &str) -> Result<String> {
+        Ok(match (self.go_panic.is_some(), &self.ret) {
+            (false, Some(ret)) => ret.go_call_ret("resp", call),
+            (false, None) => format!("{call}\n"),
+            (true, Some(ret)) => format!(
+                "resp, _panic := func() (_resp {ty}, _panic string) {{\ndefer r2gRecover(&_panic)\n{call}return resp, \"\"\n}}()\n",
+                ty = ret.to_go()?,
+                call = ret.go_call_ret("resp", call),
+            ),
+            (true, None) => format!(
+                "_panic := func() (_panic string) {{\ndefer r2gRecover(&_panic)\n{call}\nreturn \"\"\n}}()\n"
+            ),
+        })
+    }
+
+    // Go struct of the response with recovered panics, which is `RecoveredRef` of rust side.
+    fn go_recovered_ty(ret: &ParamType) -> Result<String> {
+        Ok(format!(
+            "struct {{\nresp C.{}\npanic C.StringRef\n}}",
+            ret.to_c(false)?
+        ))
+    }
+
+    // Respond `resp` to rust side with the callback.
+    fn go_respond(
+        &self,
+        ret: &ParamType,
+        call_cb: &str,
+        levels: &HashMap<Ident, u8>,
+    ) -> Result<String> {
+        let (g2c_cnt, g2c_cvt) = (
+            ret.go_to_c_field_counter(levels)?.0,
+            ret.go_to_c_field_converter(levels)?.0,
+        );
+        let mut out = format!("resp_ref, buffer := cvt_ref({g2c_cnt}, {g2c_cvt})(&resp)\n");
+        match self.go_panic {
+            Some(_) => out.push_str(&format!(
+                "_ret := {}{{resp: resp_ref}}\n{call_cb}(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))\n",
+                Self::go_recovered_ty(ret)?
+            )),
+            None => out.push_str(&format!(
+                "{call_cb}(unsafe.Pointer(cb), unsafe.Pointer(&resp_ref), unsafe.Pointer(slot))\n"
+            )),
+        }
+        out.push_str(
+            "runtime.KeepAlive(resp_ref)\nruntime.KeepAlive(resp)\nruntime.KeepAlive(buffer)\n",
+        );
+        Ok(out)
+    }
+
+    // Respond the recovered `_panic` to rust side with the callback.
+    fn go_respond_panic(&self, call_cb: &str) -> Result<String> {
+        let panic_ref = match &self.ret {
+            Some(ret) => format!(
+                "_ret := {}{{panic: refString(&_panic, nil)}}\n",
+                Self::go_recovered_ty(ret)?
+            ),
+            None => "_ret := refString(&_panic, nil)\n".to_string(),
+        };
+        Ok(format!("{panic_ref}{call_cb}(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))\nruntime.KeepAlive(_panic)\n"))
     }
 
-    fn to_go_interface_method(&self) -> String {
+    fn to_go_interface_method(&self, trait_name: &str) -> Result<String> {
         // demo_oneway(req DemoUser)
         // demo_check(req DemoComplicatedRequest) DemoResponse
+        // new(cfg DemoConfig) DemoClient
         let star_mark = BoolMark::new(self.go_ptr, "*");
-        format!(
-            "{}({}) {}",
-            self.name,
-            self.params
-                .iter()
-                .map(|p| format!("{} {star_mark}{}", p.name, p.ty.to_go()))
-                .collect::<Vec<_>>()
-                .join(", "),
-            self.ret.as_ref().map(|p| p.to_go()).unwrap_or_default()
-        )
+        let mut params = Vec::with_capacity(self.params.len() + 1);
+        if self.go_ctx() {
+            params.push("ctx context.Context".to_string());
+        }
+        for p in self.params.iter() {
+            params.extend(p.ty.to_go_params(&p.name, &star_mark)?);
+        }
+        if let Some(cb) = self.callback() {
+            params.push(format!("{} func({}) bool", cb.name, cb.ty.to_go()?));
+        }
+        if let Some(sp) = self.stream_param() {
+            params.push(format!("{} <-chan {}", sp.name, sp.ty.to_go()?));
+        }
+        if let Some(item) = self.stream() {
+            params.push(format!("yield func({}) bool", item.to_go()?));
+        }
+        let ret = match &self.ret {
+            Some(ret) => ret.to_go_ret()?,
+            None if self.constructor => trait_name.to_string(),
+            None => String::new(),
+        };
+        Ok(format!("{}({}) {ret}", self.name, params.join(", ")))
+    }
+
+    // fn demo_check(req: DemoRequest) -> DemoResponse {
+    //     <DemoCallImpl as DemoCall>::demo_check(req)
+    // }
+    fn to_rs_delegate(&self, trait_name: &Ident, impl_struct_name: &Ident) -> TokenStream {
+        let func_name = &self.name;
+        let func_params = self.rs_params();
+        let func_param_names = self.rs_param_names();
+        let docs = &self.docs;
+        let ret = match (self.rs_ret(), self.is_async) {
+            (None, _) => self.stream.as_ref().map(|s| {
+                let ty = &s.ty;
+                quote! { -> #ty }
+            }),
+            (Some(ret), false) => Some(quote! { -> #ret }),
+            (Some(_), true) => {
+                let output = self.rs_future_output();
+                Some(quote! { -> impl ::std::future::Future<Output = #output> })
+            }
+        };
+        let call =
+            quote! { <#impl_struct_name as #trait_name>::#func_name(#(#func_param_names),*) };
+        match self.is_safe {
+            true => quote! {
+                #docs
+                fn #func_name(#(#func_params),*) #ret { #call }
+            },
+            false => quote! {
+                #docs
+                unsafe fn #func_name(#(#func_params),*) #ret { unsafe { #call } }
+            },
+        }
     }
 
     fn to_rs_impl(&self, trait_name: &Ident, path_prefix: &TokenStream) -> Result<TokenStream> {
@@ -528,11 +2052,16 @@ impl R2GFnRepr {
         let func_name = &self.name;
         let callback_name = format_ident!("{func_name}_cb");
         let func_param_names: Vec<_> = self.params.iter().map(|p| &p.name).collect();
-        let func_param_types: Vec<_> = self.params.iter().map(|p| &p.ty).collect();
+        let func_params = self.rs_params();
         let unsafe_marker = (!self.is_safe).then(syn::token::Unsafe::default);
+        let docs = &self.docs;
+        let receiver = self.receiver.then(|| quote! { &self, });
         out.extend(quote! {
-            #unsafe_marker fn #func_name(#(#func_param_names: #func_param_types),*)
+            #docs
+            #unsafe_marker fn #func_name(#receiver #(#func_params),*)
         });
+        // Methods of the go object take its handle first.
+        let handle_arg = self.receiver.then(|| quote! { self.handle.raw(), });
 
         let ref_marks = self.params.iter().map(|p| {
             if p.ty.is_reference {
@@ -542,10 +2071,61 @@ impl R2GFnRepr {
             }
         });
         let c_func_name = format_ident!("C{trait_name}_{func_name}");
+        // Args of the go function, the closure or stream is passed at last.
+        let callback_init = self.rs_callback_init(path_prefix);
+        let mut c_args: Vec<_> = func_param_names
+            .iter()
+            .map(|name| quote! { ::std::mem::transmute(#name) })
+            .collect();
+        c_args.extend(self.rs_callback_arg());
+        // Check the abi hash of go side on first use, shm calls check it on ring creation.
+        let abi_check = rs_abi_check(quote! { Self });
+        if self.constructor {
+            // fn new(cfg: DemoConfig) -> Self {
+            //     let (_buf, cfg) = ::rust2go::ToRef::calc_ref(&cfg);
+            //     let handle = unsafe { binding::CDemoClient_new(::std::mem::transmute(cfg)) };
+            //     Self { handle: unsafe { ::rust2go::GoHandle::new(handle, binding::CDemoClient_release) } }
+            // }
+            let release = format_ident!("C{trait_name}_release");
+            out.extend(quote! {
+                -> Self {
+                    #abi_check
+                    #(
+                        let (_buf, #func_param_names) = ::rust2go::ToRef::calc_ref(#ref_marks #func_param_names);
+                    )*
+                    #[allow(clippy::useless_transmute)]
+                    let handle = unsafe { #path_prefix #c_func_name(#(::std::mem::transmute(#func_param_names)),*) };
+                    Self { handle: unsafe { ::rust2go::GoHandle::new(handle, #path_prefix #release) } }
+                }
+            });
+            return Ok(out);
+        }
+        if let Some(stream) = &self.stream {
+            // fn demo_tail(req: DemoRequest) -> impl Stream<Item = DemoEvent> {
+            //     let (_buf, req) = ::rust2go::ToRef::calc_ref(&req);
+            //     let _stream = ::rust2go::GoStream::<DemoEvent>::new(binding::rust2go_stream_wake, binding::rust2go_stream_cancel);
+            //     unsafe { binding::CDemoCall_demo_tail(::std::mem::transmute(req), _stream.go_ref() as *mut _) };
+            //     _stream
+            // }
+            let (ty, item) = (&stream.ty, stream.item.to_owned_tokens());
+            out.extend(quote! {
+                -> #ty {
+                    #abi_check
+                    #(
+                        let (_buf, #func_param_names) = ::rust2go::ToRef::calc_ref(#ref_marks #func_param_names);
+                    )*
+                    let _stream = ::rust2go::GoStream::<#item>::new(#path_prefix rust2go_stream_wake, #path_prefix rust2go_stream_cancel);
+                    #[allow(clippy::useless_transmute)]
+                    unsafe { #path_prefix #c_func_name(#handle_arg #(#c_args,)* _stream.go_ref() as *mut _) };
+                    _stream
+                }
+            });
+            return Ok(out);
+        }
         match (self.is_async, &self.ret) {
-            (true, None) => sbail!("async function must have a return value"),
+            (true, None) => sbail!(func_name, "async function must have a return value"),
             (false, None) => {
-                if let Some(mem_call_id) = self.mem_call_id {
+                if self.mem_call_id.is_some() {
                     // fn demo_oneway(req: &DemoUser) {
                     //     const CALL_ID: u32 = 0;
                     //     let (buf, ptr) = ::rust2go::ToRef::calc_ref(&::rust2go::CopyStruct((&req,)));
@@ -563,12 +2143,13 @@ impl R2GFnRepr {
                     //         ));
                     //     });
                     // }
-                    let mem_call_id = mem_call_id as u32;
+                    let mem_call_id = self.mem_ring_call_id as u32;
+                    let ws = ring_ident(self.mem_ring);
                     out.extend(quote! {
                         {
                             const CALL_ID: u32 = #mem_call_id;
                             let (buf, ptr) = ::rust2go::ToRef::calc_ref(&::rust2go::CopyStruct((#(&#func_param_names,)*)));
-                            Self::WS.with(|(wq, sb)| {
+                            Self::#ws.with(|(wq, sb)| {
                                 let sid = ::rust2go_mem_ffi::push_slab(sb, ::rust2go_mem_ffi::TaskDesc {
                                     buf,
                                     params_ptr: 0,
@@ -582,6 +2163,23 @@ impl R2GFnRepr {
                             });
                         }
                     });
+                } else if self.go_panic.is_some() {
+                    // The callback is called with the message if go impl panics.
+                    let ret = self.rs_ret().map(|ret| quote! { -> #ret });
+                    let ret_value = self.rs_resume(quote! { slot.take().map_or(Ok(()), Err) });
+                    out.extend(quote! {
+                        #ret {
+                            #abi_check
+                            let mut slot: Option<::rust2go::GoPanic> = None;
+                            #(
+                                let (_buf, #func_param_names) = ::rust2go::ToRef::calc_ref(#ref_marks #func_param_names);
+                            )*
+                            #callback_init
+                            #[allow(clippy::useless_transmute)]
+                            unsafe { #path_prefix #c_func_name(#handle_arg #(#c_args,)* &slot as *const _ as *const () as *mut _, Self::#callback_name as *const () as *mut _) };
+                            #ret_value
+                        }
+                    });
                 } else {
                     // fn demo_check(r: user::DemoRequest) {
                     //     let (_buf, r) = ::rust2go::ToRef::calc_ref(&r);
@@ -589,18 +2187,23 @@ impl R2GFnRepr {
                     // }
                     out.extend(quote! {
                         {
+                            #abi_check
                             #(
                                 let (_buf, #func_param_names) = ::rust2go::ToRef::calc_ref(#ref_marks #func_param_names);
                             )*
+                            #callback_init
                             #[allow(clippy::useless_transmute)]
-                            unsafe {#path_prefix #c_func_name(#(::std::mem::transmute(#func_param_names)),*)}
+                            unsafe {#path_prefix #c_func_name(#handle_arg #(#c_args),*)}
                         }
                     });
                 }
             }
-            (false, Some(ret)) => {
+            (false, Some(_)) => {
                 if self.mem_call_id.is_some() {
-                    sbail!("sync function with return value cannot be shm call")
+                    sbail!(
+                        func_name,
+                        "sync function with return value cannot be shm call"
+                    )
                 }
                 // fn demo_check(r: user::DemoRequest) -> user::DemoResponse {
                 //     let mut slot = None;
@@ -613,20 +2216,24 @@ impl R2GFnRepr {
                 //     slot.take().unwrap()
                 // }
 
+                let (ret, slot_ret) = (self.rs_ret(), self.rs_slot_ret());
+                let ret_value = self.rs_resume(quote! { slot.take().unwrap() });
                 out.extend(quote!{
                     -> #ret {
-                        let mut slot = None;
+                        #abi_check
+                        let mut slot: Option<#slot_ret> = None;
                         #(
                             let (_buf, #func_param_names) = ::rust2go::ToRef::calc_ref(#ref_marks #func_param_names);
                         )*
+                        #callback_init
                         #[allow(clippy::useless_transmute)]
-                        unsafe { #path_prefix #c_func_name(#(::std::mem::transmute(#func_param_names),)* &slot as *const _ as *const () as *mut _, Self::#callback_name as *const () as *mut _) };
-                        slot.take().unwrap()
+                        unsafe { #path_prefix #c_func_name(#handle_arg #(#c_args,)* &slot as *const _ as *const () as *mut _, Self::#callback_name as *const () as *mut _) };
+                        #ret_value
                     }
                 });
             }
-            (true, Some(ret)) => {
-                if let Some(mem_call_id) = self.mem_call_id {
+            (true, Some(_)) => {
+                if self.mem_call_id.is_some() {
                     // const CALL_ID: u32 = 1;
 
                     // let (buf, ptr) = ::rust2go::ToRef::calc_ref(&::rust2go::CopyStruct((&req,)));
@@ -647,20 +2254,20 @@ impl R2GFnRepr {
                     //     wq.push(payload)
                     // });
                     // ::rust2go::LocalFut { slot }
-                    let mem_call_id = mem_call_id as u32;
-                    let fut_output = if self.drop_safe_ret_params {
-                        quote! { (#ret, (#(#func_param_types,)*)) }
-                    } else {
-                        quote! { #ret }
-                    };
+                    let mem_call_id = self.mem_ring_call_id as u32;
+                    let ws = ring_ident(self.mem_ring);
+                    let (fut_output, slot_output) =
+                        (self.rs_future_output(), self.rs_slot_output());
+                    let fut =
+                        self.rs_resume_future(quote! { ::rust2go_mem_ffi::LocalFut { slot } });
                     out.extend(quote! {
                         -> impl ::std::future::Future<Output = #fut_output> {
                             const CALL_ID: u32 = #mem_call_id;
 
                             let (buf, ptr) = ::rust2go::ToRef::calc_ref(&::rust2go::CopyStruct((#(&#func_param_names,)*)));
-                            let slot = ::rust2go_mem_ffi::new_shared_mut(::rust2go_mem_ffi::SlotInner::<#fut_output>::new());
+                            let slot = ::rust2go_mem_ffi::new_shared_mut(::rust2go_mem_ffi::SlotInner::<#slot_output>::new());
                             let slot_ptr = ::rust2go_mem_ffi::Shared::into_raw(slot.clone()) as usize;
-                            Self::WS.with(|(wq, sb)| {
+                            Self::#ws.with(|(wq, sb)| {
                                 let sid = ::rust2go_mem_ffi::push_slab(sb, ::rust2go_mem_ffi::TaskDesc {
                                     buf,
                                     params_ptr: Box::into_raw(Box::new((#(#func_param_names,)*))) as usize,
@@ -669,7 +2276,7 @@ impl R2GFnRepr {
                                 let payload = ::rust2go_mem_ffi::Payload::new_call(CALL_ID, sid, ptr as usize);
                                 wq.push(payload)
                             });
-                            ::rust2go_mem_ffi::LocalFut { slot }
+                            #fut
                         }
                     });
                 } else {
@@ -690,33 +2297,29 @@ impl R2GFnRepr {
                     //         Self::demo_check_async_cb as *const (),
                     //     )
                     // }
-                    let len = self.params.len();
-                    let tuple_ids = (0..len).map(syn::Index::from);
-                    let new_fn = match self.drop_safe_ret_params {
-                        false => quote! {::rust2go::ResponseFuture::new_without_req},
-                        true => quote! {::rust2go::ResponseFuture::new},
-                    };
-                    let ret = match self.drop_safe_ret_params {
-                        false => quote! { #ret },
-                        true => quote! { (#ret, (#(#func_param_types,)*)) },
-                    };
-                    out.extend(quote! {
-                        -> impl ::std::future::Future<Output = #ret> {
-                            #new_fn(
-                                |r_ref: <(#(#func_param_types,)*) as ::rust2go::ToRef>::Ref, slot: *const (), cb: *const ()| {
-                                    #[allow(clippy::useless_transmute)]
-                                    unsafe {
-                                        #path_prefix #c_func_name(
-                                            #(::std::mem::transmute(r_ref.#tuple_ids),)*
-                                            slot as *const _ as *mut _,
-                                            cb as *const _ as *mut _,
-                                        )
-                                    };
-                                },
-                                (#(#func_param_names,)*),
-                                Self::#callback_name as *const (),
-                            )
+                    let output = self.rs_future_output();
+                    let body = match self.timeout_ms {
+                        // The deadline can be overridden per call with the inherent method.
+                        Some(ms) => {
+                            let with_timeout = format_ident!("{func_name}_with_timeout");
+                            let callee = match self.receiver {
+                                true => quote! { self. },
+                                false => quote! { Self:: },
+                            };
+                            let names = self.rs_param_names();
+                            quote! {
+                                #callee #with_timeout(#(#names,)* ::std::time::Duration::from_millis(#ms))
+                            }
                         }
+                        None => self.to_rs_cgo_future(trait_name, path_prefix),
+                    };
+                    out.extend(match self.is_safe {
+                        true => quote! {
+                            -> impl ::std::future::Future<Output = #output> { #body }
+                        },
+                        false => quote! {
+                            -> impl ::std::future::Future<Output = #output> { unsafe { #body } }
+                        },
                     });
                 }
             }
@@ -724,6 +2327,207 @@ impl R2GFnRepr {
         Ok(out)
     }
 
+    // Params of the rust method, with the closure or stream at last.
+    fn rs_params(&self) -> Vec<TokenStream> {
+        let mut params: Vec<_> = self
+            .params
+            .iter()
+            .map(|p| {
+                let (name, ty) = (&p.name, &p.ty);
+                quote! { #name: #ty }
+            })
+            .collect();
+        if let Some(c) = self.last_param() {
+            let (name, ty) = (&c.param.name, &c.ty);
+            params.push(quote! { #name: #ty });
+        }
+        params
+    }
+
+    // Names of the params of the rust method, with the closure or stream at last.
+    fn rs_param_names(&self) -> Vec<&Ident> {
+        self.params
+            .iter()
+            .chain(self.last_param().map(|c| &c.param))
+            .map(|p| &p.name)
+            .collect()
+    }
+
+    // The closure passed to go, which is borrowed by sync calls:
+    //     let mut progress = ::rust2go::Callback::<DemoEvent, _>::new(progress);
+    // Or owned by go side for async calls:
+    //     let progress = ::rust2go::Callback::<DemoEvent, _>::boxed(progress);
+    //
+    // Streams are passed in the same way:
+    //     let chunks = ::rust2go::RustStream::<DemoChunk, _>::boxed(chunks, binding::rust2go_rust_stream_wake);
+    fn rs_callback_init(&self, path_prefix: &TokenStream) -> Option<TokenStream> {
+        let c = self.last_param()?;
+        let (name, arg) = (&c.param.name, c.param.ty.to_owned_tokens());
+        let (ty, wake) = match self.callback.is_some() {
+            true => (quote! { ::rust2go::Callback }, None),
+            false => (
+                quote! { ::rust2go::RustStream },
+                Some(quote! { , #path_prefix rust2go_rust_stream_wake }),
+            ),
+        };
+        Some(match self.is_async {
+            true => quote! { let #name = #ty::<#arg, _>::boxed(#name #wake); },
+            false => quote! { let mut #name = #ty::<#arg, _>::new(#name #wake); },
+        })
+    }
+
+    // Argument of the closure or stream passed to go.
+    fn rs_callback_arg(&self) -> Option<TokenStream> {
+        let name = &self.last_param()?.param.name;
+        Some(match self.is_async {
+            true => quote! { #name.into_raw() as *mut _ },
+            false => quote! { #name.as_ptr() as *mut _ },
+        })
+    }
+
+    // Resume the recovered go panic of the return value if required.
+    fn rs_resume(&self, value: TokenStream) -> TokenStream {
+        match self.go_panic {
+            Some(GoPanicMode::Resume) => quote! { ::rust2go::ResumeGoPanic::resume(#value) },
+            _ => value,
+        }
+    }
+
+    // Resume the recovered go panic of the future output if required.
+    fn rs_resume_future(&self, fut: TokenStream) -> TokenStream {
+        match self.go_panic {
+            Some(GoPanicMode::Resume) => quote! { ::rust2go::ResumeGoPanicFuture(#fut) },
+            _ => fut,
+        }
+    }
+
+    // Body of the async method based on cgo, which creates the response future.
+    fn to_rs_cgo_future(&self, trait_name: &Ident, path_prefix: &TokenStream) -> TokenStream {
+        let func_name = &self.name;
+        let callback_name = format_ident!("{func_name}_cb");
+        let c_func_name = format_ident!("C{trait_name}_{func_name}");
+        let func_param_names: Vec<_> = self.params.iter().map(|p| &p.name).collect();
+        let func_param_types: Vec<_> = self.params.iter().map(|p| &p.ty).collect();
+        let tuple_ids = (0..self.params.len()).map(syn::Index::from);
+        // The response type can't be inferred when the recovered go panic is resumed.
+        let future_ty = match self.go_panic {
+            Some(_) => {
+                let slot_ret = self.rs_slot_ret();
+                quote! {::rust2go::ResponseFuture::<_, #slot_ret, _>}
+            }
+            None => quote! {::rust2go::ResponseFuture},
+        };
+        let new_fn = match self.drop_safe_ret_params {
+            false => quote! {#future_ty::new_without_req},
+            true => quote! {#future_ty::new},
+        };
+        // .with_cancel(|slot: *const ()| unsafe { binding::CDemoCall_demo_check_async_cancel(slot as *const _ as *mut _) })
+        let with_cancel = self.cancel.then(|| {
+            let cancel_func_name = format_ident!("{c_func_name}_cancel");
+            quote! {
+                .with_cancel(|slot: *const ()| unsafe { #path_prefix #cancel_func_name(slot as *const _ as *mut _) })
+            }
+        });
+        // The deadline is passed to go in nanoseconds.
+        let (timeout_ns, timeout_arg) = match self.timeout_ms {
+            Some(_) => (
+                Some(quote! {
+                    let timeout_ns = i64::try_from(timeout.as_nanos()).unwrap_or(i64::MAX);
+                }),
+                Some(quote! { timeout_ns, }),
+            ),
+            None => (None, None),
+        };
+        // The handle is copied since the future only borrows the impl.
+        let (handle, handle_arg) = match self.receiver {
+            true => (
+                Some(quote! { let _handle = self.handle.raw(); }),
+                Some(quote! { _handle, }),
+            ),
+            false => (None, None),
+        };
+        let callback_init = self.rs_callback_init(path_prefix);
+        let callback_arg = self.rs_callback_arg().map(|arg| quote! { #arg, });
+        let move_marker =
+            (self.timeout_ms.is_some() || self.receiver || self.last_param().is_some())
+                .then(|| Token![move](Span::call_site()));
+        let fut = self.rs_resume_future(quote! {
+            #new_fn(
+                #move_marker |r_ref: <(#(#func_param_types,)*) as ::rust2go::ToRef>::Ref, slot: *const (), cb: *const ()| {
+                    #[allow(clippy::useless_transmute)]
+                    unsafe {
+                        #path_prefix #c_func_name(
+                            #handle_arg
+                            #(::std::mem::transmute(r_ref.#tuple_ids),)*
+                            #callback_arg
+                            #timeout_arg
+                            slot as *const _ as *mut _,
+                            cb as *const _ as *mut _,
+                        )
+                    };
+                },
+                (#(#func_param_names,)*),
+                Self::#callback_name as *const (),
+            )
+            #with_cancel
+        });
+        let abi_check = rs_abi_check(quote! { Self });
+        quote! {
+            #abi_check
+            #timeout_ns
+            #handle
+            #callback_init
+            #fut
+        }
+    }
+
+    // pub fn demo_check_async_with_timeout(
+    //     req: user::DemoRequest,
+    //     timeout: ::std::time::Duration,
+    // ) -> impl std::future::Future<Output = Result<user::DemoResponse, ::rust2go::DeadlineExceeded>>
+    fn to_rs_with_timeout(&self, trait_name: &Ident, path_prefix: &TokenStream) -> TokenStream {
+        if self.timeout_ms.is_none() {
+            return TokenStream::default();
+        }
+        let func_name = &self.name;
+        let with_timeout = format_ident!("{func_name}_with_timeout");
+        let func_param_names: Vec<_> = self.params.iter().map(|p| &p.name).collect();
+        // Unlike the trait method, the future returned by inherent method only captures the
+        // named lifetime of reference params.
+        let func_param_types = self.params.iter().map(|p| {
+            let ty = &p.ty;
+            match ty.is_reference {
+                true => {
+                    let owned = ty.to_owned_tokens();
+                    quote! { &'a #owned }
+                }
+                false => quote! { #ty },
+            }
+        });
+        let callback = self.last_param().map(|c| {
+            let (name, ty) = (&c.param.name, &c.ty);
+            quote! { #name: #ty, }
+        });
+        let receiver = self.receiver.then(|| quote! { &'a self, });
+        let (generics, bound) = match self.ret_static {
+            true => (None, None),
+            false => (Some(quote! { <'a> }), Some(quote! { + 'a })),
+        };
+        let unsafe_marker = (!self.is_safe).then(syn::token::Unsafe::default);
+        let output = self.rs_future_output();
+        let body = self.to_rs_cgo_future(trait_name, path_prefix);
+        let doc =
+            format!(" Call `{func_name}` with the deadline `timeout` instead of the default.");
+        quote! {
+            #[doc = #doc]
+            pub #unsafe_marker fn #with_timeout #generics(#receiver #(#func_param_names: #func_param_types,)* #callback timeout: ::std::time::Duration)
+                -> impl ::std::future::Future<Output = #output> #bound
+            {
+                #body
+            }
+        }
+    }
+
     fn to_rs_callback(&self, path_prefix: &TokenStream) -> Result<TokenStream> {
         if let Some(mem_call_id) = self.mem_call_id {
             let fn_name = format_ident!("mem_ffi_handle{}", mem_call_id);
@@ -746,9 +2550,24 @@ impl R2GFnRepr {
                         ::rust2go_mem_ffi::set_result_for_shared_mut_slot(&slot, value);
                     }
                 };
+                let value = match self.go_panic {
+                    Some(_) => {
+                        let slot_ret = self.rs_slot_ret();
+                        quote! {
+                            let value_ref = unsafe { &*(response_ptr as *const ::rust2go::RecoveredRef<#resp_ref_ty>) };
+                            let value: #slot_ret = match value_ref.panic() {
+                                Some(p) => Err(p),
+                                None => Ok(::rust2go::FromRef::from_ref(&value_ref.resp)),
+                            };
+                        }
+                    }
+                    None => quote! {
+                        let value_ref = unsafe { &*(response_ptr as *const #resp_ref_ty) };
+                        let value: #ret = ::rust2go::FromRef::from_ref(value_ref);
+                    },
+                };
                 body = Some(quote! {
-                    let value_ref = unsafe { &*(response_ptr as *const #resp_ref_ty) };
-                    let value: #ret = ::rust2go::FromRef::from_ref(value_ref);
+                    #value
 
                     let _params = unsafe { Box::from_raw(desc.params_ptr as *mut (#(#reqs_ty,)*)) };
 
@@ -769,7 +2588,19 @@ impl R2GFnRepr {
         let fn_name = format_ident!("{}_cb", self.name);
 
         match (self.is_async, &self.ret) {
-            (true, None) => sbail!("async function must have a return value"),
+            (true, None) => sbail!(&self.name, "async function must have a return value"),
+            (false, None) if self.go_panic.is_some() => {
+                // #[no_mangle]
+                // unsafe extern "C" fn demo_oneway_cb(resp: *const ::rust2go::StringRef, slot: *const ()) {
+                //     *(slot as *mut Option<::rust2go::GoPanic>) = Some(::rust2go::GoPanic { message: ::rust2go::FromRef::from_ref(&*resp) });
+                // }
+                Ok(quote! {
+                    #[no_mangle]
+                    unsafe extern "C" fn #fn_name(resp: *const ::rust2go::StringRef, slot: *const ()) {
+                        *(slot as *mut Option<::rust2go::GoPanic>) = Some(::rust2go::GoPanic { message: ::rust2go::FromRef::from_ref(&*resp) });
+                    }
+                })
+            }
             (false, None) => {
                 // There's no need to generate callback for sync function without callback.
                 Ok(TokenStream::default())
@@ -779,12 +2610,14 @@ impl R2GFnRepr {
                 // unsafe extern "C" fn demo_check_cb(resp: *const binding::DemoResponseRef, slot: *const ()) {
                 //     *(slot as *mut Option<DemoResponse>) = Some(::rust2go::FromRef::from_ref(::std::mem::transmute(resp)));
                 // }
-                let resp_ref_ty = ret.to_rust_ref(Some(path_prefix));
+                let (resp_ty, value) = self.rs_callback_value(ret, path_prefix);
+                let slot_ret = self.rs_slot_ret();
                 Ok(quote! {
                     #[allow(clippy::useless_transmute, clippy::transmute_ptr_to_ref)]
                     #[no_mangle]
-                    unsafe extern "C" fn #fn_name(resp: *const #resp_ref_ty, slot: *const ()) {
-                        *(slot as *mut Option<#ret>) = Some(::rust2go::FromRef::from_ref(::std::mem::transmute(resp)));
+                    unsafe extern "C" fn #fn_name(resp: *const #resp_ty, slot: *const ()) {
+                        let resp: #slot_ret = #value;
+                        *(slot as *mut Option<#slot_ret>) = Some(resp);
                     }
                 })
             }
@@ -796,18 +2629,64 @@ impl R2GFnRepr {
                 // ) {
                 //     ::rust2go::SlotWriter::<DemoResponse>::from_ptr(slot).write(::rust2go::FromRef::from_ref(::std::mem::transmute(resp)));
                 // }
-                let resp_ref_ty = ret.to_rust_ref(Some(path_prefix));
+                let (resp_ty, value) = self.rs_callback_value(ret, path_prefix);
+                let slot_ret = self.rs_slot_ret();
                 let func_param_types = self.params.iter().map(|p| &p.ty);
                 Ok(quote! {
                     #[allow(clippy::useless_transmute, clippy::transmute_ptr_to_ref)]
                     #[no_mangle]
-                    unsafe extern "C" fn #fn_name(resp: *const #resp_ref_ty, slot: *const ()) {
-                        ::rust2go::SlotWriter::<#ret, ((#(#func_param_types,)*), Vec<u8>)>::from_ptr(slot).write(::rust2go::FromRef::from_ref(::std::mem::transmute(resp)));
+                    unsafe extern "C" fn #fn_name(resp: *const #resp_ty, slot: *const ()) {
+                        let resp: #slot_ret = #value;
+                        ::rust2go::SlotWriter::<#slot_ret, ((#(#func_param_types,)*), Vec<u8>)>::from_ptr(slot).write(resp);
                     }
                 })
             }
         }
     }
+
+    // Response type of the callback and the slot value converted from it.
+    fn rs_callback_value(
+        &self,
+        ret: &ParamType,
+        path_prefix: &TokenStream,
+    ) -> (TokenStream, TokenStream) {
+        let resp_ref_ty = ret.to_rust_ref(Some(path_prefix));
+        // With both a deadline and recovered panics, the error is a `CallError`.
+        let into =
+            (self.timeout_ms.is_some() && self.go_panic.is_some()).then(|| quote! { .into() });
+        let (resp_ty, value) = match self.go_panic {
+            Some(_) => (
+                quote! { ::rust2go::RecoveredRef<#resp_ref_ty> },
+                quote! {
+                    match (*resp).panic() {
+                        Some(p) => Err(p #into),
+                        None => Ok(::rust2go::FromRef::from_ref(::std::mem::transmute(&(*resp).resp))),
+                    }
+                },
+            ),
+            None if self.timeout_ms.is_some() => (
+                quote! { #resp_ref_ty },
+                quote! { Ok(::rust2go::FromRef::from_ref(::std::mem::transmute(resp))) },
+            ),
+            None => (
+                quote! { #resp_ref_ty },
+                quote! { ::rust2go::FromRef::from_ref(::std::mem::transmute(resp)) },
+            ),
+        };
+        match self.timeout_ms {
+            // Go side reports the exceeded deadline with a null response.
+            Some(_) => (
+                resp_ty,
+                quote! {
+                    match resp.is_null() {
+                        true => Err(::rust2go::DeadlineExceeded #into),
+                        false => #value,
+                    }
+                },
+            ),
+            None => (resp_ty, value),
+        }
+    }
 }
 
 struct BoolMark {
@@ -34,6 +34,39 @@ macro_rules! max_mem_type {
     };
 }
 
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+const fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    hash
+}
+
+/// Layout hash of types which don't provide one, the ABI check is skipped for the traits
+/// using them. No type is tagged with `?`, so it never collides with a generated hash.
+pub const UNKNOWN_LAYOUT: u64 = fnv1a(FNV_OFFSET, b"?");
+
+/// Hash a layout node with its tag and children hashes.
+/// The generator computes the same hash for the go side, so the algorithm must not change
+/// without bumping all hashes. A node with an unknown child is unknown.
+pub const fn layout_hash(tag: &str, children: &[u64]) -> u64 {
+    let mut hash = fnv1a(FNV_OFFSET, tag.as_bytes());
+    let mut i = 0;
+    while i < children.len() {
+        if children[i] == UNKNOWN_LAYOUT {
+            return UNKNOWN_LAYOUT;
+        }
+        hash = fnv1a(hash, &children[i].to_le_bytes());
+        i += 1;
+    }
+    hash
+}
+
 pub struct Writer {
     ptr: *mut u8,
 }
@@ -64,6 +97,9 @@ impl Writer {
 
 pub trait ToRef {
     const MEM_TYPE: MemType;
+    /// Hash of the memory layout of Ref, used to check rust side and go side are
+    /// generated from the same definition. Manual impls may leave it unknown.
+    const LAYOUT_HASH: u64 = UNKNOWN_LAYOUT;
 
     type Ref;
     fn to_size(&self, acc: &mut usize);
@@ -93,6 +129,7 @@ pub trait ToRef {
 
 impl<T: ToRef> ToRef for &T {
     const MEM_TYPE: MemType = T::MEM_TYPE;
+    const LAYOUT_HASH: u64 = T::LAYOUT_HASH;
     type Ref = T::Ref;
 
     #[inline]
@@ -140,6 +177,7 @@ pub struct ListRef(DataView);
 // Vec<T> -> ListRef
 impl<T: ToRef> ToRef for Vec<T> {
     const MEM_TYPE: MemType = T::MEM_TYPE.next();
+    const LAYOUT_HASH: u64 = layout_hash("Vec", &[T::LAYOUT_HASH]);
     type Ref = ListRef;
 
     fn to_size(&self, acc: &mut usize) {
@@ -183,6 +221,7 @@ pub struct StringRef(DataView);
 
 impl ToRef for String {
     const MEM_TYPE: MemType = MemType::SimpleWrapper;
+    const LAYOUT_HASH: u64 = layout_hash("String", &[]);
     type Ref = StringRef;
 
     #[inline]
@@ -211,6 +250,7 @@ macro_rules! primitive_impl {
         $(
             impl ToRef for $ty {
                 const MEM_TYPE: MemType = MemType::Primitive;
+                const LAYOUT_HASH: u64 = layout_hash(stringify!($ty), &[]);
                 type Ref = $ty;
 
                 #[inline]
@@ -235,61 +275,56 @@ macro_rules! primitive_impl {
 
 primitive_impl!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64, bool, char);
 
+// Tuples are passed to go as structs, so the Ref must be `#[repr(C)]` instead of a tuple.
 macro_rules! tuple_impl {
-    (($ty:ident, $name:tt)) => {
-        tuple_impl!(@# ($ty, $name));
-    };
-    ($(($ty:ident, $name:tt)),+) => {
-        tuple_impl!(@# $(($ty, $name)),*);
-        tuple_impl!(@! [$(($ty, $name))*]);
-    };
-    (@# $(($ty:ident, $name:tt)),*) => {
-        impl<$($ty,)*> ToRef for ($($ty,)*) where $($ty:ToRef,)* {
-            const MEM_TYPE: MemType = MemType::Primitive$(.max($ty::MEM_TYPE))*;
-            type Ref = ($($ty::Ref,)*);
+    ($($name:ident: ($(($ty:ident, $idx:tt)),+);)*) => {
+        $(
+            #[derive(Copy, Clone, Debug)]
+            #[repr(C)]
+            pub struct $name<$($ty,)*>($(pub $ty,)*);
 
-            fn to_size(&self, acc: &mut usize) {
-                $(self.$name.to_size(acc);)*
+            impl<$($ty,)*> ToRef for ($($ty,)*) where $($ty:ToRef,)* {
+                const MEM_TYPE: MemType = MemType::Primitive$(.max($ty::MEM_TYPE))*;
+                const LAYOUT_HASH: u64 = layout_hash("Tuple", &[$($ty::LAYOUT_HASH),*]);
+                type Ref = $name<$($ty::Ref,)*>;
+
+                fn to_size(&self, acc: &mut usize) {
+                    $(self.$idx.to_size(acc);)*
+                }
+
+                fn to_ref(&self, buffer: &mut Writer) -> Self::Ref {
+                    $name($(self.$idx.to_ref(buffer),)*)
+                }
             }
 
-            fn to_ref(&self, buffer: &mut Writer) -> Self::Ref {
-                (
-                    $(self.$name.to_ref(buffer),)*
-                )
+            impl<$($ty,)*> FromRef for ($($ty,)*) where $($ty:FromRef,)* {
+                type Ref = $name<$($ty::Ref,)*>;
+
+                fn from_ref(ref_: &Self::Ref) -> Self {
+                    ($($ty::from_ref(&ref_.$idx),)*)
+                }
             }
-        }
-    };
-    (@! [] ($ty_l:ident, $name_l:tt) $(($ty:ident, $name:tt))*) => {
-        tuple_impl!(@~ [$(($ty, $name))*]);
-    };
-    (@! [($ty_f:ident, $name_f:tt) $(($ty:ident, $name:tt))*] $(($ty_r:ident, $name_r:tt))*) => {
-        tuple_impl!(@! [$(($ty, $name))*] ($ty_f, $name_f) $(($ty_r, $name_r))*);
-    };
-    (@~ [] $(($ty:ident, $name:tt))*) => {
-        tuple_impl!($(($ty, $name)),*);
-    };
-    (@~ [($ty_f:ident, $name_f:tt) $(($ty:ident, $name:tt))*] $(($ty_r:ident, $name_r:tt))*) => {
-        tuple_impl!(@~ [$(($ty, $name))*] ($ty_f, $name_f) $(($ty_r, $name_r))*);
+        )*
     };
 }
 
 tuple_impl!(
-    (T1, 0),
-    (T2, 1),
-    (T3, 2),
-    (T4, 3),
-    (T5, 4),
-    (T6, 5),
-    (T7, 6),
-    (T8, 7),
-    (T9, 8),
-    (T10, 9),
-    (T11, 10),
-    (T12, 11),
-    (T13, 12),
-    (T14, 13),
-    (T15, 14),
-    (T16, 15)
+    TupleRef1: ((T1, 0));
+    TupleRef2: ((T1, 0), (T2, 1));
+    TupleRef3: ((T1, 0), (T2, 1), (T3, 2));
+    TupleRef4: ((T1, 0), (T2, 1), (T3, 2), (T4, 3));
+    TupleRef5: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4));
+    TupleRef6: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5));
+    TupleRef7: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5), (T7, 6));
+    TupleRef8: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5), (T7, 6), (T8, 7));
+    TupleRef9: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5), (T7, 6), (T8, 7), (T9, 8));
+    TupleRef10: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5), (T7, 6), (T8, 7), (T9, 8), (T10, 9));
+    TupleRef11: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5), (T7, 6), (T8, 7), (T9, 8), (T10, 9), (T11, 10));
+    TupleRef12: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5), (T7, 6), (T8, 7), (T9, 8), (T10, 9), (T11, 10), (T12, 11));
+    TupleRef13: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5), (T7, 6), (T8, 7), (T9, 8), (T10, 9), (T11, 10), (T12, 11), (T13, 12));
+    TupleRef14: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5), (T7, 6), (T8, 7), (T9, 8), (T10, 9), (T11, 10), (T12, 11), (T13, 12), (T14, 13));
+    TupleRef15: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5), (T7, 6), (T8, 7), (T9, 8), (T10, 9), (T11, 10), (T12, 11), (T13, 12), (T14, 13), (T15, 14));
+    TupleRef16: ((T1, 0), (T2, 1), (T3, 2), (T4, 3), (T5, 4), (T6, 5), (T7, 6), (T8, 7), (T9, 8), (T10, 9), (T11, 10), (T12, 11), (T13, 12), (T14, 13), (T15, 14), (T16, 15));
 );
 
 #[inline]
@@ -363,6 +398,7 @@ macro_rules! copy_struct_for_tuple {
         impl<$($ty,)*> ToRef for CopyStruct<($($ty,)*)> where $($ty:ToRef,)* {
             // Complex since we need buffer
             const MEM_TYPE: MemType = MemType::Complex;
+            const LAYOUT_HASH: u64 = layout_hash("CopyStruct", &[$($ty::LAYOUT_HASH),*]);
             type Ref = *const u8;
 
             fn to_size(&self, acc: &mut usize) {
@@ -412,3 +448,43 @@ copy_struct_for_tuple!(
     (T15, 14),
     (T16, 15)
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A manual impl written before layout hashes were added.
+    struct Legacy;
+
+    impl ToRef for Legacy {
+        const MEM_TYPE: MemType = MemType::Primitive;
+        type Ref = u8;
+
+        fn to_size(&self, _: &mut usize) {}
+
+        fn to_ref(&self, _: &mut Writer) -> u8 {
+            0
+        }
+    }
+
+    #[test]
+    fn layout_hashes() {
+        assert_eq!(
+            <Vec<(u32, String)>>::LAYOUT_HASH,
+            layout_hash(
+                "Vec",
+                &[layout_hash(
+                    "Tuple",
+                    &[layout_hash("u32", &[]), layout_hash("String", &[])]
+                )]
+            )
+        );
+        assert_ne!(<Vec<u32>>::LAYOUT_HASH, <Vec<i32>>::LAYOUT_HASH);
+        // Flags and call ids are hashed as children, so they must not be mistaken as unknown.
+        assert_ne!(layout_hash("demo", &[0, u64::MAX]), UNKNOWN_LAYOUT);
+
+        assert_eq!(Legacy::LAYOUT_HASH, UNKNOWN_LAYOUT);
+        assert_eq!(<Vec<(u32, Legacy)>>::LAYOUT_HASH, UNKNOWN_LAYOUT);
+        assert_eq!(<&Legacy>::LAYOUT_HASH, UNKNOWN_LAYOUT);
+    }
+}
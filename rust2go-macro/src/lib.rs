@@ -2,8 +2,12 @@
 
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
-use rust2go_common::{g2r::G2RTraitRepr, r2g::R2GTraitRepr, sbail};
-use syn::{parse::Parser, parse_macro_input, DeriveInput, Ident};
+use rust2go_common::{
+    g2r::{G2RTraitArgs, G2RTraitRepr},
+    r2g::{R2GTraitArgs, R2GTraitRepr},
+    sbail,
+};
+use syn::{parse_macro_input, DeriveInput, Ident};
 
 #[proc_macro_derive(R2G)]
 pub fn r2g_derive(input: TokenStream) -> TokenStream {
@@ -38,6 +42,9 @@ pub fn r2g_derive(input: TokenStream) -> TokenStream {
             "String" => {
                 ref_fields.push(quote! {#name: ::rust2go::StringRef});
             }
+            "Handle" => {
+                ref_fields.push(quote! {#name: usize});
+            }
             "i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32" | "u64" | "usize"
             | "f32" | "f64" | "bool" | "char" => {
                 ref_fields.push(quote! {#name: #ty});
@@ -64,6 +71,7 @@ pub fn r2g_derive(input: TokenStream) -> TokenStream {
 
         impl ::rust2go::ToRef for #type_name {
             const MEM_TYPE: ::rust2go::MemType = ::rust2go::max_mem_type!(#(#owned_types),*);
+            const LAYOUT_HASH: u64 = ::rust2go::layout_hash("Struct", &[#(<#owned_types as ::rust2go::ToRef>::LAYOUT_HASH),*]);
             type Ref = #ref_type_name;
 
             fn to_size(&self, acc: &mut usize) {
@@ -92,82 +100,76 @@ pub fn r2g_derive(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
-fn parse_attrs(attrs: TokenStream) -> (Option<syn::Path>, Option<usize>) {
-    let mut binding_path = None;
-    let mut queue_size = None;
-
-    type AttributeArgs = syn::punctuated::Punctuated<syn::Meta, syn::Token![,]>;
-    if let Ok(attrs) = AttributeArgs::parse_terminated.parse(attrs) {
-        for attr in attrs {
-            match attr {
-                syn::Meta::NameValue(nv) => {
-                    if nv.path.is_ident("binding") {
-                        binding_path = Some(nv.path);
-                    } else if nv.path.is_ident("queue_size") {
-                        if let syn::Expr::Lit(syn::ExprLit {
-                            lit: syn::Lit::Int(litint),
-                            ..
-                        }) = nv.value
-                        {
-                            queue_size = Some(litint.base10_parse::<usize>().unwrap());
-                        }
-                    }
-                }
-                syn::Meta::Path(p) => {
-                    binding_path = Some(p);
-                }
-                _ => {}
-            }
-        }
-    }
-    (binding_path, queue_size)
-}
-
 #[proc_macro_attribute]
 pub fn r2g(attrs: TokenStream, item: TokenStream) -> TokenStream {
-    let (binding_path, queue_size) = parse_attrs(attrs);
-    syn::parse::<syn::ItemTrait>(item)
-        .and_then(|trat| r2g_trait(binding_path, queue_size, trat))
+    R2GTraitArgs::parse(attrs.into())
+        .and_then(|args| Ok((args, syn::parse::<syn::ItemTrait>(item)?)))
+        .and_then(|(args, trat)| r2g_trait(args, trat))
+        .map(TokenStream::from)
         .unwrap_or_else(|e| TokenStream::from(e.to_compile_error()))
 }
 
 #[proc_macro_attribute]
-pub fn g2r(_attrs: TokenStream, item: TokenStream) -> TokenStream {
+pub fn g2r(attrs: TokenStream, item: TokenStream) -> TokenStream {
     syn::parse::<syn::ItemTrait>(item)
-        .and_then(g2r_trait)
+        .and_then(|trat| g2r_trait(G2RTraitArgs::parse(attrs.into())?, trat))
+        .map(TokenStream::from)
         .unwrap_or_else(|e| TokenStream::from(e.to_compile_error()))
 }
 
-fn g2r_trait(mut trat: syn::ItemTrait) -> syn::Result<TokenStream> {
-    let trat_repr = G2RTraitRepr::try_from(&trat)?;
+fn g2r_trait(
+    args: G2RTraitArgs,
+    mut trat: syn::ItemTrait,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let trat_repr = G2RTraitRepr::new(&trat, &args)?;
+    // The lint reads method attributes, so it runs before they are removed.
+    #[cfg(feature = "lint")]
+    let warnings = lint_warnings(rust2go_common::lint::lint_g2r_trait(&trat)?);
+    #[cfg(not(feature = "lint"))]
+    let warnings = quote! {};
 
     for trat_fn in trat.items.iter_mut() {
         match trat_fn {
             syn::TraitItem::Fn(f) => {
-                // remove attributes of all functions
-                f.attrs.clear();
+                // remove attributes of all functions except doc comments
+                f.attrs.retain(|attr| attr.path().is_ident("doc"));
             }
             _ => sbail!("only fn is supported"),
         }
     }
 
-    let mut out = quote! {#trat};
+    // convert async fn return impl future, which is spawned and must be Send and 'static.
+    for (trat_fn, fn_repr) in trat.items.iter_mut().zip(trat_repr.fns()) {
+        if let (syn::TraitItem::Fn(f), true) = (trat_fn, fn_repr.is_async()) {
+            let output = match fn_repr.ret() {
+                Some(ret) => quote! { #ret },
+                None => quote! { () },
+            };
+            f.sig.asyncness = None;
+            f.sig.output = syn::parse_quote! { -> impl ::std::future::Future<Output = #output> + Send + 'static };
+        }
+    }
+
+    let mut out = quote! {#trat #warnings};
     out.extend(trat_repr.generate_rs()?);
-    Ok(out.into())
+    Ok(out)
 }
 
 fn r2g_trait(
-    binding_path: Option<syn::Path>,
-    queue_size: Option<usize>,
+    args: R2GTraitArgs,
     mut trat: syn::ItemTrait,
-) -> syn::Result<TokenStream> {
-    let trat_repr = R2GTraitRepr::try_from(&trat)?;
+) -> syn::Result<proc_macro2::TokenStream> {
+    let trat_repr = R2GTraitRepr::new(&trat, &args)?;
+    #[cfg(feature = "lint")]
+    let warnings = lint_warnings(rust2go_common::lint::lint_r2g_trait(&trat, &trat_repr));
+    #[cfg(not(feature = "lint"))]
+    let warnings = quote! {};
 
     for (fn_repr, trat_fn) in trat_repr.fns().iter().zip(trat.items.iter_mut()) {
         match trat_fn {
             syn::TraitItem::Fn(f) => {
-                // remove attributes of all functions
-                f.attrs.clear();
+                // remove attributes of all functions except doc comments
+                f.attrs.retain(|attr| attr.path().is_ident("doc"));
 
                 // for shm based oneway call, add unsafe
                 if fn_repr.ret().is_none() && !fn_repr.is_async() && fn_repr.mem_call_id().is_some()
@@ -177,10 +179,7 @@ fn r2g_trait(
 
                 // convert async fn return impl future
                 if fn_repr.is_async() {
-                    let orig = match fn_repr.ret() {
-                        None => quote! { () },
-                        Some(ret) => quote! { #ret },
-                    };
+                    let output = fn_repr.rs_future_output();
                     let auto_t = match (fn_repr.ret_send(), fn_repr.ret_static()) {
                         (true, true) => quote!( + Send + Sync + 'static),
                         (true, false) => quote!( + Send + Sync),
@@ -188,25 +187,81 @@ fn r2g_trait(
                         (false, false) => quote!(),
                     };
                     f.sig.asyncness = None;
-                    if fn_repr.drop_safe_ret_params() {
-                        // for all functions with #[drop_safe_ret], change the return type.
-                        let tys = fn_repr.params().iter().map(|p| p.ty());
-                        f.sig.output = syn::parse_quote! { -> impl ::std::future::Future<Output = (#orig, (#(#tys,)*))> #auto_t };
-                    } else {
-                        f.sig.output = syn::parse_quote! { -> impl ::std::future::Future<Output = #orig> #auto_t };
-                    }
+                    // for functions with #[drop_safe_ret], a deadline or recovered go panics, the output
+                    // also changes.
+                    f.sig.output = syn::parse_quote! { -> impl ::std::future::Future<Output = #output> #auto_t };
 
                     // for all functions with safe=false, add unsafe
                     if !fn_repr.is_safe() {
                         f.sig.unsafety = Some(syn::token::Unsafe::default());
                     }
+                } else if fn_repr.go_panic().is_some() {
+                    // sync functions returning the recovered go panic as error.
+                    if let Some(ret) = fn_repr.rs_ret() {
+                        f.sig.output = syn::parse_quote! { -> #ret };
+                    }
                 }
             }
             _ => sbail!("only fn is supported"),
         }
     }
 
-    let mut out = quote! {#trat};
-    out.extend(trat_repr.generate_rs(binding_path.as_ref(), queue_size)?);
-    Ok(out.into())
+    let mut out = quote! {#trat #warnings};
+    out.extend(trat_repr.generate_rs(args.binding.as_ref())?);
+    Ok(out)
+}
+
+// Stable proc macros cannot emit warnings, so use a deprecated item at the offending span instead.
+#[cfg(feature = "lint")]
+fn lint_warnings(warnings: Vec<rust2go_common::lint::Warning>) -> proc_macro2::TokenStream {
+    warnings
+        .into_iter()
+        .enumerate()
+        .map(|(idx, w)| {
+            let name = format_ident!("rust2go_lint_{idx}", span = w.span);
+            let note = w.message;
+            quote::quote_spanned! {w.span=>
+                const _: () = {
+                    #[deprecated(note = #note)]
+                    #[allow(non_upper_case_globals)]
+                    const #name: () = ();
+                    #name
+                };
+            }
+        })
+        .collect()
+}
+
+#[cfg(all(test, feature = "lint"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn g2r_lint_warnings() {
+        let trat: syn::ItemTrait = syn::parse_quote! {
+            pub trait DemoCallback {
+                #[drop_safe]
+                fn demo_get(id: u32) -> u32;
+            }
+        };
+        let out = g2r_trait(G2RTraitArgs::default(), trat)
+            .unwrap()
+            .to_string();
+        assert!(out.contains("#[drop_safe] has no effect on g2r methods"));
+        assert!(out.contains("rust2go_lint_0"));
+    }
+
+    #[test]
+    fn r2g_lint_warnings() {
+        let trat: syn::ItemTrait = syn::parse_quote! {
+            pub trait DemoCall {
+                #[cgo_callback]
+                fn demo_oneway(id: u32);
+            }
+        };
+        let out = r2g_trait(R2GTraitArgs::default(), trat)
+            .unwrap()
+            .to_string();
+        assert!(out.contains("#[cgo_callback] has no effect on methods without return value"));
+    }
 }
@@ -120,8 +120,24 @@ pub unsafe fn init_mem_ffi<const N: usize>(
     size: usize,
     handlers: [TaskHandler; N],
 ) -> (WriteQueue<Payload>, SharedSlab) {
-    let (read_queue, write_queue) =
-        init_rings(peer_init_function_pointer, size).expect("unable to init ring");
+    type RingInitFunc = unsafe extern "C" fn(QueueMeta, QueueMeta);
+    let init_func: RingInitFunc = std::mem::transmute(peer_init_function_pointer);
+    init_mem_ffi_with(|rmeta, wmeta| init_func(rmeta, wmeta), size, handlers)
+        .expect("unable to init ring")
+}
+
+/// Same as `init_mem_ffi`, but the peer is initialized by the given function,
+/// which allows passing extra arguments to it, and failures of creating the
+/// rings are returned.
+/// # Safety
+/// peer_init must init the peer with the queue metas.
+// Must be called for each thread.
+pub unsafe fn init_mem_ffi_with<const N: usize>(
+    peer_init: impl FnOnce(QueueMeta, QueueMeta),
+    size: usize,
+    handlers: [TaskHandler; N],
+) -> Result<(WriteQueue<Payload>, SharedSlab), io::Error> {
+    let (read_queue, write_queue) = init_rings(peer_init, size)?;
 
     #[cfg(all(feature = "tokio", not(feature = "monoio")))]
     let shared_slab = std::sync::Arc::new(std::sync::Mutex::new(Slab::new()));
@@ -130,63 +146,53 @@ pub unsafe fn init_mem_ffi<const N: usize>(
 
     let wq = write_queue.clone();
     let sb = shared_slab.clone();
-    let guard = read_queue
-        .run_handler(move |payload: Payload| {
-            if payload.flag & Payload::QUIT_ACK == Payload::QUIT_ACK {
-                return;
-            }
-            let Some(call_handle) = handlers.get(payload.call_id as usize) else {
-                panic!("call handler {} not found", payload.call_id);
-            };
-            let sid = payload.user_data;
-            let desc = {
-                #[cfg(not(all(feature = "tokio", not(feature = "monoio"))))]
-                let locked = unsafe { &mut *sb.get() };
-                #[cfg(all(feature = "tokio", not(feature = "monoio")))]
-                let mut locked = sb.lock().unwrap();
-                locked.remove(sid)
-            };
-
-            if call_handle(payload.ptr, desc) {
-                let drop_payload = Payload::new_drop(payload.call_id, payload.next_user_data);
-                wq.push(drop_payload);
-            }
-        })
-        .expect("unable to run ffi handler");
+    let guard = read_queue.run_handler(move |payload: Payload| {
+        if payload.flag & Payload::QUIT_ACK == Payload::QUIT_ACK {
+            return;
+        }
+        let Some(call_handle) = handlers.get(payload.call_id as usize) else {
+            panic!("call handler {} not found", payload.call_id);
+        };
+        let sid = payload.user_data;
+        let desc = {
+            #[cfg(not(all(feature = "tokio", not(feature = "monoio"))))]
+            let locked = unsafe { &mut *sb.get() };
+            #[cfg(all(feature = "tokio", not(feature = "monoio")))]
+            let mut locked = sb.lock().unwrap();
+            locked.remove(sid)
+        };
+
+        if call_handle(payload.ptr, desc) {
+            let drop_payload = Payload::new_drop(payload.call_id, payload.next_user_data);
+            wq.push(drop_payload);
+        }
+    })?;
     Box::leak(Box::new(guard));
-    (write_queue, shared_slab)
+    Ok((write_queue, shared_slab))
 }
 
 /// # Safety
-/// peer_init_function_pointer must be a valid function.
+/// peer_init must init the peer with the queue metas.
 #[cfg(not(all(feature = "tokio", not(feature = "monoio"))))]
 pub unsafe fn init_rings<T: 'static>(
-    peer_init_function_pointer: *const (),
+    peer_init: impl FnOnce(QueueMeta, QueueMeta),
     size: usize,
 ) -> Result<(ReadQueue<T>, WriteQueue<T>), io::Error> {
-    type RingInitFunc = unsafe extern "C" fn(QueueMeta, QueueMeta);
     let (rqueue, rmeta) = Queue::new(size)?;
     let (wqueue, wmeta) = Queue::new(size)?;
-
-    let init_func: RingInitFunc = std::mem::transmute(peer_init_function_pointer);
-    init_func(rmeta, wmeta);
-
+    peer_init(rmeta, wmeta);
     Ok((rqueue.read(), wqueue.write()?))
 }
 
 /// # Safety
-/// peer_init_function_pointer must be a valid function.
+/// peer_init must init the peer with the queue metas.
 #[cfg(all(feature = "tokio", not(feature = "monoio")))]
 pub unsafe fn init_rings<T: 'static + Send>(
-    peer_init_function_pointer: *const (),
+    peer_init: impl FnOnce(QueueMeta, QueueMeta),
     size: usize,
 ) -> Result<(ReadQueue<T>, WriteQueue<T>), io::Error> {
-    type RingInitFunc = unsafe extern "C" fn(QueueMeta, QueueMeta);
     let (rqueue, rmeta) = Queue::new(size)?;
     let (wqueue, wmeta) = Queue::new(size)?;
-
-    let init_func: RingInitFunc = std::mem::transmute(peer_init_function_pointer);
-    init_func(rmeta, wmeta);
-
+    peer_init(rmeta, wmeta);
     Ok((rqueue.read(), wqueue.write()?))
 }
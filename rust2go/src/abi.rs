@@ -0,0 +1,23 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::fmt;
+
+/// The linked go library is generated from a different interface than the rust side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbiMismatch {
+    pub trait_name: &'static str,
+    pub rust: u64,
+    pub go: u64,
+}
+
+impl fmt::Display for AbiMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rust2go abi mismatch of trait {}: rust side {:#x}, go side {:#x}; please regenerate the go code and rebuild the go library",
+            self.trait_name, self.rust, self.go
+        )
+    }
+}
+
+impl std::error::Error for AbiMismatch {}
@@ -324,7 +324,19 @@ impl<GOC: GoCompiler> Builder<PathBuf, GOC> {
             .unwrap_or(crate::DEFAULT_BINDING_FILE);
         // Regenerate go code.
         if !self.regen_arg.src.is_empty() && !self.regen_arg.dst.is_empty() {
-            rust2go_cli::generate(&self.regen_arg);
+            if let Err(e) = rust2go_cli::generate(&self.regen_arg) {
+                // Show the location in cargo output, and the full diagnostics in stderr.
+                if let rust2go_cli::Error::Source(diagnostics) = &e {
+                    for d in diagnostics.iter() {
+                        println!(
+                            "cargo:warning={}:{}:{}: {}",
+                            d.file, d.line, d.column, d.message
+                        );
+                    }
+                }
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
         }
         self.go_comp
             .build(&self.go_src, binding_name, self.link, &self.copy_lib);
@@ -0,0 +1,175 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::marker::PhantomData;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::{set_rust_panic, FromRef};
+
+/// Rust closure passed to a go method, which is called with the ref of the argument.
+#[doc(hidden)]
+#[repr(C)]
+pub struct Callback<T, F> {
+    call: unsafe fn(*mut (), *const ()),
+    release: unsafe fn(*mut ()),
+    f: F,
+    _arg: PhantomData<fn(T)>,
+}
+
+impl<T: FromRef, F: FnMut(T) + Send> Callback<T, F> {
+    /// Closure borrowed by a sync call, which may be called on another thread by go side.
+    pub fn new(f: F) -> Self {
+        Self {
+            call: call::<T, F>,
+            release: release::<T, F>,
+            f,
+            _arg: PhantomData,
+        }
+    }
+
+    /// Pointer passed to go, which is valid until the callback is moved or dropped.
+    pub fn as_ptr(&mut self) -> *mut () {
+        self as *mut Self as *mut ()
+    }
+}
+
+impl<T: FromRef, F: FnMut(T) + Send + 'static> Callback<T, F> {
+    /// Closure owned by go side after the async call is sent.
+    pub fn boxed(f: F) -> OwnedCallback {
+        OwnedCallback(Box::into_raw(Box::new(Self::new(f))) as *mut ())
+    }
+}
+
+unsafe fn call<T: FromRef, F: FnMut(T)>(ptr: *mut (), arg: *const ()) {
+    let cb = &mut *(ptr as *mut Callback<T, F>);
+    (cb.f)(T::from_ref(&*(arg as *const T::Ref)));
+}
+
+unsafe fn release<T, F>(ptr: *mut ()) {
+    drop(Box::from_raw(ptr as *mut Callback<T, F>));
+}
+
+/// Boxed closure of an async call, which is dropped unless it's passed to go.
+#[doc(hidden)]
+pub struct OwnedCallback(*mut ());
+
+// The closure is Send, and it's never accessed with a shared reference.
+unsafe impl Send for OwnedCallback {}
+unsafe impl Sync for OwnedCallback {}
+
+impl OwnedCallback {
+    /// Pass the closure to go, which releases it when the go method returns.
+    pub fn into_raw(self) -> *mut () {
+        let ptr = self.0;
+        std::mem::forget(self);
+        ptr
+    }
+}
+
+impl Drop for OwnedCallback {
+    fn drop(&mut self) {
+        unsafe { c_rust2go_callback_release(self.0) };
+    }
+}
+
+// A panic of the closure is reported in the slot like the g2r calls, and resumed by go side.
+#[no_mangle]
+unsafe extern "C" fn c_rust2go_callback_call(
+    ptr: *mut (),
+    arg: *const (),
+    slot: *mut [*const (); 3],
+) {
+    let call = *(ptr as *const unsafe fn(*mut (), *const ()));
+    if let Err(e) = catch_unwind(AssertUnwindSafe(|| call(ptr, arg))) {
+        set_rust_panic(slot, e);
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn c_rust2go_callback_release(ptr: *mut ()) {
+    let release = *(ptr as *const unsafe fn(*mut ())).add(1);
+    release(ptr);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::{StringRef, ToRef};
+
+    thread_local! {
+        static DROPS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct Guard;
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            DROPS.with(|d| d.set(d.get() + 1));
+        }
+    }
+
+    // The panic message of the closure if it panics.
+    unsafe fn call_with(ptr: *mut (), arg: &str) -> Option<String> {
+        let arg = arg.to_string();
+        let (_buf, arg_ref) = ToRef::calc_ref(&arg);
+        let mut slot = [std::ptr::null(); 3];
+        c_rust2go_callback_call(ptr, &arg_ref as *const _ as *const (), &mut slot);
+        if slot[2].is_null() {
+            return None;
+        }
+        let message = String::from_ref(&*(slot[2] as *const StringRef));
+        drop(Box::from_raw(slot[1] as *mut (String, StringRef, Vec<u8>)));
+        Some(message)
+    }
+
+    #[test]
+    fn borrowed_callback() {
+        let mut seen = Vec::new();
+        let mut cb = Callback::<String, _>::new(|s| seen.push(s));
+        let ptr = cb.as_ptr();
+        unsafe {
+            assert_eq!(call_with(ptr, "a"), None);
+            assert_eq!(call_with(ptr, "b"), None);
+        }
+        assert_eq!(seen, ["a", "b"]);
+    }
+
+    #[test]
+    fn panicking_callback() {
+        let mut cb = Callback::<String, _>::new(|s| {
+            if s == "boom" {
+                panic!("callback {s}");
+            }
+        });
+        let ptr = cb.as_ptr();
+        unsafe {
+            assert_eq!(call_with(ptr, "boom").as_deref(), Some("callback boom"));
+            // The closure is still callable after a caught panic.
+            assert_eq!(call_with(ptr, "a"), None);
+        }
+    }
+
+    #[test]
+    fn owned_callback() {
+        DROPS.with(|d| d.set(0));
+        let guard = Guard;
+        let ptr = Callback::<String, _>::boxed(move |s| {
+            let _guard = &guard;
+            assert_eq!(s, "a");
+        })
+        .into_raw();
+        assert_eq!(unsafe { call_with(ptr, "a") }, None);
+        assert_eq!(DROPS.with(Cell::get), 0);
+        // Go side releases it when the method returns.
+        unsafe { c_rust2go_callback_release(ptr) };
+        assert_eq!(DROPS.with(Cell::get), 1);
+
+        // The closure of an async call which is never sent is dropped with it.
+        let guard = Guard;
+        drop(Callback::<String, _>::boxed(move |_| {
+            let _guard = &guard;
+        }));
+        assert_eq!(DROPS.with(Cell::get), 2);
+    }
+}
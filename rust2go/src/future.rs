@@ -1,5 +1,6 @@
 // Copyright 2024 ihciah. All Rights Reserved.
 
+use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -8,7 +9,7 @@ use crate::SlotReader;
 
 impl<Req, Resp, Exec> ResponseFuture<Req, Resp, Exec> {
     pub fn new(exec: Exec, req: Req, callback: *const ()) -> Self {
-        Self::Init(exec, req, callback)
+        Self::Init(exec, req, callback, None)
     }
 
     pub fn new_without_req(
@@ -16,13 +17,22 @@ impl<Req, Resp, Exec> ResponseFuture<Req, Resp, Exec> {
         req: Req,
         callback: *const (),
     ) -> ResponseFutureWithoutReq<Req, Resp, Exec> {
-        ResponseFutureWithoutReq(Self::Init(exec, req, callback))
+        ResponseFutureWithoutReq(Self::Init(exec, req, callback, None))
+    }
+
+    /// Call `cancel` with the slot pointer if the future is dropped before the response is
+    /// written, so the callee can stop the work.
+    pub fn with_cancel(mut self, cancel: fn(*const ())) -> Self {
+        if let Self::Init(_, _, _, c) = &mut self {
+            *c = Some(cancel);
+        }
+        self
     }
 }
 
 pub enum ResponseFuture<Req, Resp, Exec> {
-    // go ffi function, request, callback function ptr
-    Init(Exec, Req, *const ()),
+    // go ffi function, request, callback function ptr, cancel function
+    Init(Exec, Req, *const (), Option<fn(*const ())>),
     // slot
     Executed(SlotReader<Resp, (Req, Vec<u8>)>),
     Fused,
@@ -55,8 +65,9 @@ where
                 // replace to take ownership
                 let (reader, mut writer) = crate::slot::new_atomic_slot::<Resp, (Req, Vec<u8>)>();
 
-                let (exec, req, cb) = match std::mem::replace(this, Self::Executed(reader)) {
-                    Self::Init(exec, req, cb) => (exec, req, cb),
+                let (exec, req, cb, cancel) = match std::mem::replace(this, Self::Executed(reader))
+                {
+                    Self::Init(exec, req, cb, cancel) => (exec, req, cb, cancel),
                     Self::Executed(_) => unsafe { std::hint::unreachable_unchecked() },
                     Self::Fused => unsafe { std::hint::unreachable_unchecked() },
                 };
@@ -64,6 +75,9 @@ where
                 let (buf, req_ref) = req.calc_ref();
                 writer.attach((req, buf));
                 writer.set_waker(cx.waker().clone());
+                if let Some(cancel) = cancel {
+                    writer.set_cancel(cancel);
+                }
 
                 // execute the ffi function
                 let w_ptr = writer.into_ptr();
@@ -79,6 +93,13 @@ where
 
 pub struct ResponseFutureWithoutReq<Req, Resp, Exec>(pub ResponseFuture<Req, Resp, Exec>);
 
+impl<Req, Resp, Exec> ResponseFutureWithoutReq<Req, Resp, Exec> {
+    /// See [`ResponseFuture::with_cancel`].
+    pub fn with_cancel(self, cancel: fn(*const ())) -> Self {
+        Self(self.0.with_cancel(cancel))
+    }
+}
+
 impl<Req, Resp, Exec> Future for ResponseFutureWithoutReq<Req, Resp, Exec>
 where
     ResponseFuture<Req, Resp, Exec>: Future<Output = (Resp, Req)>,
@@ -91,3 +112,15 @@ where
             .map(|r| r.0)
     }
 }
+
+/// The deadline of the call is exceeded before go side responds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("rust2go call deadline exceeded")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
@@ -0,0 +1,148 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::any::Any;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+
+use crate::{set_rust_panic, ToRef};
+
+pub type G2RFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static SPAWNER: OnceLock<Box<dyn Fn(G2RFuture) + Send + Sync>> = OnceLock::new();
+
+/// Register the spawner of async g2r methods, e.g. with a tokio runtime handle:
+/// `rust2go::set_g2r_spawner(move |f| { handle.spawn(f); })`.
+/// Only the first spawner is registered, false is returned for others.
+pub fn set_g2r_spawner(spawner: impl Fn(G2RFuture) + Send + Sync + 'static) -> bool {
+    SPAWNER.set(Box::new(spawner)).is_ok()
+}
+
+/// Go callback waiting for the output of an async g2r call.
+#[doc(hidden)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct G2RWaker {
+    wake: unsafe extern "C" fn(usize, *const [*const (); 3]),
+    handle: usize,
+}
+
+/// Write the return value of a g2r call to the slot, go side reads it with the first slot and
+/// drops it with the second one.
+///
+/// # Safety
+/// `slot` must be valid for writes.
+#[doc(hidden)]
+pub unsafe fn set_g2r_ret<T>(slot: *mut [*const (); 3], out: T)
+where
+    T: ToRef + 'static,
+    T::Ref: 'static,
+{
+    let (buf, out_ref) = ToRef::calc_ref(&out);
+    let storage = Box::new((out, out_ref, buf));
+    let ret_ptr = &storage.as_ref().1 as *const _ as *const ();
+    let drop_ptr = Box::leak(storage as Box<dyn Any>) as *mut dyn Any as *mut ();
+    *slot = [ret_ptr, drop_ptr, std::ptr::null()];
+}
+
+/// Run the future of an async g2r method with the registered spawner, and wake go side with
+/// the output written by `write`, or the message if it panics.
+#[doc(hidden)]
+pub fn spawn_g2r<F>(waker: G2RWaker, fut: F, write: unsafe fn(*mut [*const (); 3], F::Output))
+where
+    F: Future + Send + 'static,
+{
+    let spawner = SPAWNER
+        .get()
+        .expect("async g2r method is called before rust2go::set_g2r_spawner");
+    spawner(Box::pin(async move {
+        let out = CatchUnwind(fut).await;
+        let mut slot = [std::ptr::null(); 3];
+        match out {
+            Ok(out) => unsafe { write(&mut slot, out) },
+            Err(e) => unsafe { set_rust_panic(&mut slot, e) },
+        }
+        unsafe { (waker.wake)(waker.handle, &slot) };
+    }));
+}
+
+struct CatchUnwind<F>(F);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let fut = unsafe { self.map_unchecked_mut(|f| &mut f.0) };
+        match catch_unwind(AssertUnwindSafe(|| fut.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(out)) => Poll::Ready(Ok(out)),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+
+    use super::*;
+    use crate::{FromRef, StringRef};
+
+    thread_local! {
+        // Return value and panic message of the woken calls.
+        static WOKEN: RefCell<Vec<(Option<u32>, Option<String>)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    // Futures in the tests are ready on the first poll, so they are run inline.
+    fn spawner() {
+        set_g2r_spawner(|mut f: G2RFuture| {
+            let waker = Waker::from(Arc::new(Noop));
+            assert!(f.as_mut().poll(&mut Context::from_waker(&waker)).is_ready());
+        });
+    }
+
+    unsafe extern "C" fn wake(handle: usize, slot: *const [*const (); 3]) {
+        assert_eq!(handle, 7);
+        let [ret, drop_ptr, message] = *slot;
+        let ret = (!ret.is_null()).then(|| *(ret as *const u32));
+        let message =
+            (!message.is_null()).then(|| String::from_ref(&*(message as *const StringRef)));
+        WOKEN.with(|w| w.borrow_mut().push((ret, message)));
+        match ret {
+            Some(_) => drop(Box::from_raw(drop_ptr as *mut (u32, u32, Vec<u8>))),
+            None => drop(Box::from_raw(drop_ptr as *mut (String, StringRef, Vec<u8>))),
+        }
+    }
+
+    #[test]
+    fn g2r_ret() {
+        let mut slot = [std::ptr::null(); 3];
+        unsafe { set_g2r_ret(&mut slot, "hi".to_string()) };
+        assert!(slot[2].is_null());
+        let ret = unsafe { &*(slot[0] as *const StringRef) };
+        assert_eq!(String::from_ref(ret), "hi");
+        drop(unsafe { Box::from_raw(slot[1] as *mut (String, StringRef, Vec<u8>)) });
+    }
+
+    #[test]
+    fn spawn() {
+        spawner();
+        let waker = G2RWaker { wake, handle: 7 };
+        spawn_g2r(waker, async { 1u32 }, set_g2r_ret);
+        spawn_g2r(waker, async { panic!("boom") }, set_g2r_ret::<u32>);
+        assert_eq!(
+            WOKEN.with(|w| w.take()),
+            [(Some(1), None), (None, Some("boom".to_string()))]
+        );
+    }
+}
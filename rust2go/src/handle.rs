@@ -0,0 +1,194 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::any::TypeId;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::{layout_hash, FromRef, MemType, ToRef, Writer};
+
+/// Go object held by a `cgo.Handle`, created by the constructors of r2g traits with go objects.
+/// The handle is deleted on drop, after which the object can be collected by go GC.
+pub struct GoHandle {
+    handle: usize,
+    release: unsafe extern "C" fn(usize),
+}
+
+impl GoHandle {
+    /// # Safety
+    /// `handle` must be a `cgo.Handle` which is released only by `release`.
+    #[doc(hidden)]
+    pub unsafe fn new(handle: usize, release: unsafe extern "C" fn(usize)) -> Self {
+        Self { handle, release }
+    }
+
+    /// The value of the `cgo.Handle`.
+    pub fn raw(&self) -> usize {
+        self.handle
+    }
+}
+
+impl fmt::Debug for GoHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("GoHandle").field(&self.handle).finish()
+    }
+}
+
+impl Drop for GoHandle {
+    fn drop(&mut self) {
+        unsafe { (self.release)(self.handle) }
+    }
+}
+
+/// Rust object shared with go. It is an opaque type named `T` on go side, which holds a
+/// reference of the object until it's closed or collected by go GC.
+///
+/// Each conversion to the go value creates a reference owned by go side, so a converted value
+/// which never reaches go (e.g. an async call dropped before it's sent) leaks the object.
+/// Passing back a closed handle, or a handle of another type, panics.
+pub struct Handle<T>(Arc<T>);
+
+impl<T> Handle<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+
+    pub fn into_arc(self) -> Arc<T> {
+        self.0
+    }
+}
+
+impl<T> From<Arc<T>> for Handle<T> {
+    fn from(value: Arc<T>) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Handle").field(&self.0).finish()
+    }
+}
+
+// References held by go side, which are checked before use.
+static LIVE: Mutex<Option<HashSet<usize>>> = Mutex::new(None);
+
+// The set is never left inconsistent by the checks panicking with the lock held.
+fn live() -> MutexGuard<'static, Option<HashSet<usize>>> {
+    LIVE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// The reference held by go side, which is released with the type erased function.
+#[repr(C)]
+struct Shared<T> {
+    release: unsafe fn(usize),
+    type_id: TypeId,
+    value: Arc<T>,
+}
+
+unsafe fn release<T>(ptr: usize) {
+    drop(Box::from_raw(ptr as *mut Shared<T>));
+}
+
+impl<T: Send + Sync + 'static> ToRef for Handle<T> {
+    const MEM_TYPE: MemType = MemType::SimpleWrapper;
+    const LAYOUT_HASH: u64 = layout_hash("Handle", &[]);
+    type Ref = usize;
+
+    #[inline]
+    fn to_size(&self, _: &mut usize) {}
+
+    // Go side takes the reference, it's leaked if the ref is not sent to go.
+    fn to_ref(&self, _: &mut Writer) -> Self::Ref {
+        let ptr = Box::into_raw(Box::new(Shared {
+            release: release::<T>,
+            type_id: TypeId::of::<T>(),
+            value: self.0.clone(),
+        })) as usize;
+        live().get_or_insert_with(HashSet::new).insert(ptr);
+        ptr
+    }
+}
+
+impl<T: Send + Sync + 'static> FromRef for Handle<T> {
+    type Ref = usize;
+
+    fn from_ref(ref_: &Self::Ref) -> Self {
+        assert!(*ref_ != 0, "rust2go handle is used after Close");
+        // The lock keeps the reference alive until it's cloned.
+        let live = live();
+        assert!(
+            live.as_ref().is_some_and(|l| l.contains(ref_)),
+            "rust2go handle is used after it's released"
+        );
+        let shared = unsafe { &*(*ref_ as *const Shared<T>) };
+        assert!(
+            shared.type_id == TypeId::of::<T>(),
+            "rust2go handle is not a {}",
+            std::any::type_name::<T>()
+        );
+        Self(shared.value.clone())
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn c_rust2go_handle_release(ptr: usize) {
+    // Released references are skipped, it's called by go side so it can't panic.
+    if !live().as_mut().is_some_and(|l| l.remove(&ptr)) {
+        return;
+    }
+    let release = *(ptr as *const unsafe fn(usize));
+    release(ptr);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn go_ref<T: Send + Sync + 'static>(handle: &Handle<T>) -> usize {
+        ToRef::calc_ref(handle).1
+    }
+
+    #[test]
+    fn round_trip() {
+        let handle = Handle::new(1u32);
+        let ptr = go_ref(&handle);
+        let back = <Handle<u32> as FromRef>::from_ref(&ptr);
+        assert!(Arc::ptr_eq(&handle.0, &back.0));
+        assert_eq!(Arc::strong_count(&handle.0), 3);
+        unsafe { c_rust2go_handle_release(ptr) };
+        assert_eq!(Arc::strong_count(&handle.0), 2);
+        unsafe { c_rust2go_handle_release(ptr) };
+        assert_eq!(Arc::strong_count(&handle.0), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "rust2go handle is not a")]
+    fn wrong_type() {
+        let ptr = go_ref(&Handle::new(1u32));
+        <Handle<String> as FromRef>::from_ref(&ptr);
+    }
+
+    #[test]
+    #[should_panic(expected = "rust2go handle is used after it's released")]
+    fn released() {
+        let ptr = go_ref(&Handle::new(1u32));
+        unsafe { c_rust2go_handle_release(ptr) };
+        <Handle<u32> as FromRef>::from_ref(&ptr);
+    }
+}
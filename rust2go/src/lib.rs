@@ -3,14 +3,38 @@
 use std::any::Any;
 
 pub use rust2go_convert::{
-    max_mem_type, CopyStruct, DataView, FromRef, ListRef, MemType, StringRef, ToRef, Writer,
+    layout_hash, max_mem_type, CopyStruct, DataView, FromRef, ListRef, MemType, StringRef, ToRef,
+    Writer, UNKNOWN_LAYOUT,
 };
 
+mod abi;
+pub use abi::AbiMismatch;
+
+mod shm;
+pub use shm::{Rust2goShmConfig, ShmConfigError};
+
 mod slot;
 pub use slot::{new_atomic_slot, SlotReader, SlotWriter};
 
 mod future;
-pub use future::{ResponseFuture, ResponseFutureWithoutReq};
+pub use future::{DeadlineExceeded, ResponseFuture, ResponseFutureWithoutReq};
+
+mod recover;
+pub use recover::{
+    set_rust_panic, CallError, GoPanic, RecoveredRef, ResumeGoPanic, ResumeGoPanicFuture,
+};
+
+mod g2r;
+pub use g2r::{set_g2r_ret, set_g2r_spawner, spawn_g2r, G2RFuture, G2RWaker};
+
+mod handle;
+pub use handle::{GoHandle, Handle};
+
+mod callback;
+pub use callback::{Callback, OwnedCallback};
+
+mod stream;
+pub use stream::{GoStream, OwnedRustStream, RustStream, Stream, STREAM_BUFFER};
 
 pub use rust2go_macro::{g2r, r2g, R2G};
 
@@ -0,0 +1,203 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::{DeadlineExceeded, FromRef, StringRef, ToRef};
+
+/// A panic of the go implementation, recovered by the generated go code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoPanic {
+    pub message: String,
+}
+
+impl fmt::Display for GoPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "go panicked: {}", self.message)
+    }
+}
+
+impl std::error::Error for GoPanic {}
+
+/// Error of a call with both a deadline and recovered go panics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallError {
+    DeadlineExceeded,
+    GoPanic(GoPanic),
+}
+
+impl From<DeadlineExceeded> for CallError {
+    fn from(_: DeadlineExceeded) -> Self {
+        CallError::DeadlineExceeded
+    }
+}
+
+impl From<GoPanic> for CallError {
+    fn from(p: GoPanic) -> Self {
+        CallError::GoPanic(p)
+    }
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::DeadlineExceeded => DeadlineExceeded.fmt(f),
+            CallError::GoPanic(p) => p.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+/// Response of a go call with recovered panics.
+/// Go side never sends an empty message, so the response is valid if the message is empty.
+#[repr(C)]
+pub struct RecoveredRef<T> {
+    pub resp: T,
+    pub panic: StringRef,
+}
+
+impl<T> RecoveredRef<T> {
+    #[inline]
+    pub fn panic(&self) -> Option<GoPanic> {
+        let message = String::from_ref(&self.panic);
+        (!message.is_empty()).then_some(GoPanic { message })
+    }
+}
+
+/// Resume the recovered go panic on the calling task.
+#[doc(hidden)]
+pub trait ResumeGoPanic {
+    type Output;
+    fn resume(self) -> Self::Output;
+}
+
+impl<T> ResumeGoPanic for Result<T, GoPanic> {
+    type Output = T;
+
+    #[inline]
+    fn resume(self) -> T {
+        self.unwrap_or_else(|p| panic!("{p}"))
+    }
+}
+
+impl<T> ResumeGoPanic for Result<T, CallError> {
+    type Output = Result<T, DeadlineExceeded>;
+
+    #[inline]
+    fn resume(self) -> Self::Output {
+        match self {
+            Ok(v) => Ok(v),
+            Err(CallError::DeadlineExceeded) => Err(DeadlineExceeded),
+            Err(CallError::GoPanic(p)) => panic!("{p}"),
+        }
+    }
+}
+
+// Output of #[drop_safe_ret] methods.
+impl<R: ResumeGoPanic, P> ResumeGoPanic for (R, P) {
+    type Output = (R::Output, P);
+
+    #[inline]
+    fn resume(self) -> Self::Output {
+        (self.0.resume(), self.1)
+    }
+}
+
+#[doc(hidden)]
+pub struct ResumeGoPanicFuture<F>(pub F);
+
+impl<F> Future for ResumeGoPanicFuture<F>
+where
+    F: Future,
+    F::Output: ResumeGoPanic,
+{
+    type Output = <F::Output as ResumeGoPanic>::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        unsafe { self.map_unchecked_mut(|f| &mut f.0) }
+            .poll(cx)
+            .map(ResumeGoPanic::resume)
+    }
+}
+
+/// Report a rust panic caught in the g2r entry point to go side, which reads the message from
+/// the third slot and drops it with the second one.
+///
+/// # Safety
+/// `slot` must point to the slot passed by the generated go code.
+#[doc(hidden)]
+pub unsafe fn set_rust_panic(slot: *mut [*const (); 3], payload: Box<dyn Any + Send>) {
+    let message = match payload.downcast::<String>() {
+        Ok(s) => *s,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(s) => s.to_string(),
+            Err(_) => "panic".to_string(),
+        },
+    };
+    let (buf, message_ref) = ToRef::calc_ref(&message);
+    let storage = Box::new((message, message_ref, buf));
+    let message_ptr = &storage.as_ref().1 as *const _ as *const ();
+    let drop_ptr = Box::leak(storage as Box<dyn Any>) as *mut dyn Any as *mut ();
+    *slot = [std::ptr::null(), drop_ptr, message_ptr];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recovered(panic: &str) -> Option<GoPanic> {
+        let message = panic.to_string();
+        let (_buf, panic) = ToRef::calc_ref(&message);
+        RecoveredRef { resp: 1u32, panic }.panic()
+    }
+
+    #[test]
+    fn recovered_ref() {
+        assert_eq!(recovered(""), None);
+        assert_eq!(
+            recovered("boom"),
+            Some(GoPanic {
+                message: "boom".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn resume() {
+        assert_eq!(Ok::<u32, GoPanic>(1).resume(), 1);
+        assert_eq!(
+            Err::<u32, CallError>(CallError::DeadlineExceeded).resume(),
+            Err(DeadlineExceeded)
+        );
+        assert_eq!((Ok::<u32, GoPanic>(1), "req").resume(), (1, "req"));
+    }
+
+    #[test]
+    #[should_panic(expected = "go panicked: boom")]
+    fn resume_panic() {
+        let panic = GoPanic {
+            message: "boom".to_string(),
+        };
+        Err::<u32, CallError>(panic.into()).resume().ok();
+    }
+
+    #[test]
+    fn rust_panic_message() {
+        for (payload, message) in [
+            (Box::new("boom") as Box<dyn Any + Send>, "boom"),
+            (Box::new(format!("boom {}", 1)), "boom 1"),
+            (Box::new(1u32), "panic"),
+        ] {
+            let mut slot = [std::ptr::null(); 3];
+            unsafe { set_rust_panic(&mut slot, payload) };
+            assert!(slot[0].is_null());
+            let message_ref = unsafe { &*(slot[2] as *const StringRef) };
+            assert_eq!(String::from_ref(message_ref), message);
+            drop(unsafe { Box::from_raw(slot[1] as *mut (String, StringRef, Vec<u8>)) });
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::{collections::BTreeMap, fmt, sync::Mutex};
+
+static CONFIGS: Mutex<BTreeMap<String, Rust2goShmConfig>> = Mutex::new(BTreeMap::new());
+
+const ENV_VARS: [&str; 3] = [
+    "RUST2GO_SHM_QUEUE_SIZE",
+    "RUST2GO_SHM_GO_POOLS",
+    "RUST2GO_SHM_GO_POOL_SIZE",
+];
+
+/// Runtime settings of shm rings, overriding the ones in `#[r2g(...)]` and `#[mem(...)]`.
+///
+/// Rings are created on the first shm call of each thread, so the config must be set before
+/// that. Unset fields fall back to the ring config, the global config, the environment variables
+/// `RUST2GO_SHM_QUEUE_SIZE`, `RUST2GO_SHM_GO_POOLS` and `RUST2GO_SHM_GO_POOL_SIZE`, and the
+/// attributes in order.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rust2goShmConfig {
+    /// Capacity of the rings.
+    pub queue_size: Option<usize>,
+    /// Number of go worker pools.
+    pub go_pools: Option<usize>,
+    /// Workers of each go pool.
+    pub go_pool_size: Option<usize>,
+}
+
+/// A shm setting is not a positive integer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShmConfigError {
+    /// Name of the field or the environment variable.
+    pub name: &'static str,
+    pub value: String,
+}
+
+impl fmt::Display for ShmConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid rust2go shm setting {}: {:?}, it must be a positive integer",
+            self.name, self.value
+        )
+    }
+}
+
+impl std::error::Error for ShmConfigError {}
+
+impl Rust2goShmConfig {
+    /// Set the config of all rings.
+    pub fn set_global(self) -> Result<(), ShmConfigError> {
+        self.validate()?;
+        CONFIGS.lock().unwrap().insert(String::new(), self);
+        Ok(())
+    }
+
+    /// Set the config of one ring, which is named after the trait for the ring shared by its
+    /// methods, or `Trait::method` for a method with a dedicated ring.
+    pub fn set_ring(self, ring: &str) -> Result<(), ShmConfigError> {
+        assert!(!ring.is_empty(), "ring name must not be empty");
+        self.validate()?;
+        CONFIGS.lock().unwrap().insert(ring.to_string(), self);
+        Ok(())
+    }
+
+    /// Check that the set fields are positive.
+    pub fn validate(&self) -> Result<(), ShmConfigError> {
+        let fields = [
+            ("queue_size", self.queue_size),
+            ("go_pools", self.go_pools),
+            ("go_pool_size", self.go_pool_size),
+        ];
+        match fields.into_iter().find(|(_, v)| *v == Some(0)) {
+            Some((name, _)) => Err(ShmConfigError {
+                name,
+                value: "0".to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Read the config from environment variables.
+    pub fn from_env() -> Result<Self, ShmConfigError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, ShmConfigError> {
+        let mut values = [None; 3];
+        for (name, slot) in ENV_VARS.into_iter().zip(values.iter_mut()) {
+            let Some(v) = var(name) else {
+                continue;
+            };
+            match v.trim().parse() {
+                Ok(n) if n > 0 => *slot = Some(n),
+                _ => return Err(ShmConfigError { name, value: v }),
+            }
+        }
+        let [queue_size, go_pools, go_pool_size] = values;
+        Ok(Rust2goShmConfig {
+            queue_size,
+            go_pools,
+            go_pool_size,
+        })
+    }
+
+    /// Runtime config of the ring, used by the generated code.
+    #[doc(hidden)]
+    pub fn resolve(ring: &str) -> Result<Self, ShmConfigError> {
+        let env = Self::from_env()?;
+        Ok(Self::resolve_in(&CONFIGS.lock().unwrap(), ring, env))
+    }
+
+    fn resolve_in(configs: &BTreeMap<String, Self>, ring: &str, env: Self) -> Self {
+        let get = |name: &str| configs.get(name).copied().unwrap_or_default();
+        get(ring).or(get("")).or(env)
+    }
+
+    fn or(self, other: Self) -> Self {
+        Rust2goShmConfig {
+            queue_size: self.queue_size.or(other.queue_size),
+            go_pools: self.go_pools.or(other.go_pools),
+            go_pool_size: self.go_pool_size.or(other.go_pool_size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence() {
+        let config = |queue_size, go_pools, go_pool_size| Rust2goShmConfig {
+            queue_size,
+            go_pools,
+            go_pool_size,
+        };
+        let configs = BTreeMap::from([
+            (String::new(), config(Some(1), Some(2), None)),
+            ("Demo".to_string(), config(Some(10), None, None)),
+        ]);
+        let env = config(Some(100), Some(200), Some(300));
+        // ring > global > env
+        assert_eq!(
+            Rust2goShmConfig::resolve_in(&configs, "Demo", env),
+            config(Some(10), Some(2), Some(300))
+        );
+        assert_eq!(
+            Rust2goShmConfig::resolve_in(&configs, "Demo::hot", env),
+            config(Some(1), Some(2), Some(300))
+        );
+        // Unset fields fall back to the attributes.
+        assert_eq!(
+            Rust2goShmConfig::resolve_in(&BTreeMap::new(), "Demo", Rust2goShmConfig::default()),
+            Rust2goShmConfig::default()
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        let zero = Rust2goShmConfig {
+            go_pools: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(zero.validate().unwrap_err().name, "go_pools");
+        assert!(zero.set_ring("Demo::zero").is_err());
+        assert!(!CONFIGS.lock().unwrap().contains_key("Demo::zero"));
+
+        assert_eq!(
+            Rust2goShmConfig::from_vars(|name: &str| {
+                (name == "RUST2GO_SHM_GO_POOLS").then(|| " 4 ".to_string())
+            }),
+            Ok(Rust2goShmConfig {
+                go_pools: Some(4),
+                ..Default::default()
+            })
+        );
+        for v in ["0", "-1", "many"] {
+            let err = Rust2goShmConfig::from_vars(move |name: &str| {
+                (name == "RUST2GO_SHM_QUEUE_SIZE").then(|| v.to_string())
+            })
+            .unwrap_err();
+            assert_eq!(err.name, "RUST2GO_SHM_QUEUE_SIZE");
+        }
+    }
+}
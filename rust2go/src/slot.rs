@@ -29,6 +29,8 @@ struct SlotInner<T, A = ()> {
     data: MaybeUninit<T>,
     attachment: Option<A>,
     waker: Mutex<Option<Waker>>,
+    // Called with the slot pointer when the reader is dropped before the writer.
+    cancel: Option<fn(*const ())>,
 }
 
 impl<T, A> Drop for SlotInner<T, A> {
@@ -42,6 +44,7 @@ impl<T, A> Drop for SlotInner<T, A> {
 // 0b00x: x=1 means writer is dropped, x=0 means writer is alive.
 // 0b0x0: x=1 means reader is dropped, x=0 means reader is alive.
 // 0bx00: x=1 means data is written, x=0 means data is not written.
+// 0bx000: x=1 means the reader has notified go side to give up.
 #[repr(transparent)]
 struct State(AtomicU8);
 
@@ -81,6 +84,7 @@ impl<T, A> SlotInner<T, A> {
             data: MaybeUninit::uninit(),
             attachment: None,
             waker: Mutex::new(None),
+            cancel: None,
         }
     }
 
@@ -161,6 +165,21 @@ impl<T, A> Drop for SlotReader<T, A> {
     #[inline]
     fn drop(&mut self) {
         unsafe {
+            // The writer is still held by go side and nothing is written, notify it to give up.
+            // The decision is made with the state transition, and the slot is kept alive by the
+            // reader bit until go side is notified, so the pointer can't be reused meanwhile.
+            let inner = self.0.as_ref();
+            if let Some(cancel) = inner.cancel {
+                if inner
+                    .state
+                    .fetch_update_action(|curr| match curr & 0b1101 == 0 {
+                        true => (true, Some(0b1000 | curr)),
+                        false => (false, None),
+                    })
+                {
+                    cancel(self.0.as_ptr() as *const ());
+                }
+            }
             if self
                 .0
                 .as_ref()
@@ -213,6 +232,13 @@ impl<T, A> SlotWriter<T, A> {
     pub(crate) fn set_waker(&mut self, waker: Waker) {
         unsafe { *self.0.as_mut().waker.lock().unwrap() = Some(waker) };
     }
+
+    /// Set the function called with the slot pointer if the reader is dropped before the data
+    /// is written. It must be set before the writer is passed to the other side.
+    #[inline]
+    pub(crate) fn set_cancel(&mut self, cancel: fn(*const ())) {
+        unsafe { self.0.as_mut() }.cancel = Some(cancel);
+    }
 }
 
 impl<T, A> Drop for SlotWriter<T, A> {
@@ -230,3 +256,57 @@ impl<T, A> Drop for SlotWriter<T, A> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    thread_local! {
+        static CANCELS: Cell<usize> = const { Cell::new(0) };
+        static DROPS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct Data;
+
+    impl Drop for Data {
+        fn drop(&mut self) {
+            DROPS.with(|d| d.set(d.get() + 1));
+        }
+    }
+
+    fn slot() -> (SlotReader<Data>, SlotWriter<Data>) {
+        CANCELS.with(|c| c.set(0));
+        DROPS.with(|d| d.set(0));
+        let (reader, mut writer) = new_atomic_slot::<Data, ()>();
+        writer.set_cancel(|_| CANCELS.with(|c| c.set(c.get() + 1)));
+        (reader, writer)
+    }
+
+    #[test]
+    fn cancel_before_write() {
+        let (reader, writer) = slot();
+        drop(reader);
+        assert_eq!(CANCELS.with(Cell::get), 1);
+        // The late response is dropped with the slot.
+        writer.write(Data);
+        assert_eq!(DROPS.with(Cell::get), 1);
+    }
+
+    #[test]
+    fn write_before_drop() {
+        let (reader, writer) = slot();
+        writer.write(Data);
+        drop(reader);
+        assert_eq!(CANCELS.with(Cell::get), 0);
+        assert_eq!(DROPS.with(Cell::get), 1);
+
+        let (reader, writer) = slot();
+        writer.write(Data);
+        assert!(reader.read().is_some());
+        assert_eq!(DROPS.with(Cell::get), 1);
+        drop(reader);
+        assert_eq!((CANCELS.with(Cell::get), DROPS.with(Cell::get)), (0, 1));
+    }
+}
@@ -0,0 +1,472 @@
+// Copyright 2024 ihciah. All Rights Reserved.
+
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Wake, Waker};
+
+pub use futures_core::Stream;
+
+use crate::{FromRef, ToRef};
+
+/// Items received by rust side before the go sender waits.
+pub const STREAM_BUFFER: usize = 16;
+
+/// Stream of the items pushed by a go method. Dropping it cancels the context of the go method.
+pub struct GoStream<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// The shared state, go side holds a reference until the go method returns.
+#[repr(C)]
+struct Shared<T> {
+    send: unsafe fn(*const (), *mut SendArg),
+    close: unsafe fn(*const ()),
+    wake_go: unsafe extern "C" fn(*mut c_void),
+    cancel_go: unsafe extern "C" fn(*mut c_void),
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    waker: Option<Waker>,
+    // Go sender waits for the buffer.
+    go_waiting: bool,
+    // The go method returns.
+    closed: bool,
+    dropped: bool,
+}
+
+// Item sent by go side, with the status written by rust side: 0 for accepted, 1 for accepted but
+// the buffer is full, and 2 for rejected since the stream is dropped.
+#[repr(C)]
+struct SendArg {
+    item: *const (),
+    status: u8,
+}
+
+impl<T: FromRef + Send + 'static> GoStream<T> {
+    /// Create the stream with the go exports waking the waiting sender and cancelling the go
+    /// method.
+    #[doc(hidden)]
+    pub fn new(
+        wake_go: unsafe extern "C" fn(*mut c_void),
+        cancel_go: unsafe extern "C" fn(*mut c_void),
+    ) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                send: send::<T>,
+                close: close::<T>,
+                wake_go,
+                cancel_go,
+                state: Mutex::new(State {
+                    items: VecDeque::new(),
+                    waker: None,
+                    go_waiting: false,
+                    closed: false,
+                    dropped: false,
+                }),
+            }),
+        }
+    }
+
+    /// Reference held by go side, which is released when the go method returns.
+    #[doc(hidden)]
+    pub fn go_ref(&self) -> *mut () {
+        Arc::into_raw(self.shared.clone()) as *mut ()
+    }
+}
+
+impl<T> GoStream<T> {
+    fn go_ptr(&self) -> *mut c_void {
+        Arc::as_ptr(&self.shared) as *mut c_void
+    }
+}
+
+impl<T> Stream for GoStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+        let Some(item) = state.items.pop_front() else {
+            if state.closed {
+                return Poll::Ready(None);
+            }
+            state.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        };
+        let wake_go = std::mem::take(&mut state.go_waiting);
+        drop(state);
+        if wake_go {
+            unsafe { (self.shared.wake_go)(self.go_ptr()) };
+        }
+        Poll::Ready(Some(item))
+    }
+}
+
+impl<T> Drop for GoStream<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.dropped = true;
+        let items = std::mem::take(&mut state.items);
+        let closed = state.closed;
+        drop(state);
+        drop(items);
+        if !closed {
+            unsafe { (self.shared.cancel_go)(self.go_ptr()) };
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for GoStream<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GoStream").finish_non_exhaustive()
+    }
+}
+
+unsafe fn send<T: FromRef>(ptr: *const (), arg: *mut SendArg) {
+    let shared = &*(ptr as *const Shared<T>);
+    let arg = &mut *arg;
+    let mut state = shared.state.lock().unwrap();
+    if state.dropped {
+        arg.status = 2;
+        return;
+    }
+    state
+        .items
+        .push_back(T::from_ref(&*(arg.item as *const T::Ref)));
+    arg.status = match state.items.len() >= STREAM_BUFFER {
+        true => {
+            state.go_waiting = true;
+            1
+        }
+        false => 0,
+    };
+    let waker = state.waker.take();
+    drop(state);
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+unsafe fn close<T>(ptr: *const ()) {
+    let shared = Arc::from_raw(ptr as *const Shared<T>);
+    let mut state = shared.state.lock().unwrap();
+    state.closed = true;
+    state.go_waiting = false;
+    let waker = state.waker.take();
+    drop(state);
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn c_rust2go_stream_send(ptr: *const (), arg: *mut ()) {
+    let send = *(ptr as *const unsafe fn(*const (), *mut SendArg));
+    send(ptr, arg as *mut SendArg);
+}
+
+#[no_mangle]
+unsafe extern "C" fn c_rust2go_stream_close(ptr: *const ()) {
+    let close = *(ptr as *const unsafe fn(*const ())).add(1);
+    close(ptr);
+}
+
+/// Rust stream passed to a go method, which is polled by go side for one item at a time.
+#[doc(hidden)]
+#[repr(C)]
+pub struct RustStream<T: ToRef, S> {
+    next: unsafe fn(*mut (), *mut NextArg),
+    release: unsafe fn(*mut ()),
+    wake_go: unsafe extern "C" fn(*mut c_void),
+    // Wakers cloned by the stream may outlive it, so they are invalidated on drop.
+    waker: Option<(Arc<GoWaker>, Waker)>,
+    // The last item with its ref, held until the next poll.
+    item: Option<(T, T::Ref, Vec<u8>)>,
+    stream: S,
+}
+
+// Item polled by go side, with the status written by rust side: 0 for an item, 1 for pending
+// until go side is woken, and 2 for the end of the stream.
+#[repr(C)]
+struct NextArg {
+    item: *const (),
+    status: u8,
+}
+
+impl<T: ToRef, S: Stream<Item = T> + Send> RustStream<T, S> {
+    /// Stream borrowed by a sync call, go side is woken by `wake_go` when it's ready.
+    pub fn new(stream: S, wake_go: unsafe extern "C" fn(*mut c_void)) -> Self {
+        Self {
+            next: next::<T, S>,
+            release: release::<T, S>,
+            wake_go,
+            waker: None,
+            item: None,
+            stream,
+        }
+    }
+
+    /// Pointer passed to go, which is valid until the stream is moved or dropped.
+    pub fn as_ptr(&mut self) -> *mut () {
+        self as *mut Self as *mut ()
+    }
+}
+
+impl<T: ToRef, S: Stream<Item = T> + Send + 'static> RustStream<T, S> {
+    /// Stream owned by go side after the async call is sent.
+    pub fn boxed(stream: S, wake_go: unsafe extern "C" fn(*mut c_void)) -> OwnedRustStream {
+        OwnedRustStream(Box::into_raw(Box::new(Self::new(stream, wake_go))) as *mut ())
+    }
+}
+
+impl<T: ToRef, S> Drop for RustStream<T, S> {
+    fn drop(&mut self) {
+        if let Some((go_waker, _)) = &self.waker {
+            *go_waker
+                .target
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = None;
+        }
+    }
+}
+
+// Wake go side waiting for the stream, which is ignored after the stream is dropped.
+struct GoWaker {
+    // The lock is held while waking, so the stream is never dropped during it.
+    target: Mutex<Option<(unsafe extern "C" fn(*mut c_void), usize)>>,
+}
+
+impl Wake for GoWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let target = self.target.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((wake_go, ptr)) = *target {
+            unsafe { wake_go(ptr as *mut c_void) };
+        }
+    }
+}
+
+unsafe fn next<T: ToRef, S: Stream<Item = T>>(ptr: *mut (), arg: *mut NextArg) {
+    let s = &mut *(ptr as *mut RustStream<T, S>);
+    let arg = &mut *arg;
+    s.item = None;
+    let wake_go = s.wake_go;
+    let (_, waker) = s.waker.get_or_insert_with(|| {
+        let go_waker = Arc::new(GoWaker {
+            target: Mutex::new(Some((wake_go, ptr as usize))),
+        });
+        (go_waker.clone(), Waker::from(go_waker))
+    });
+    let mut cx = Context::from_waker(waker);
+    // The stream is never moved after it's passed to go.
+    let stream = Pin::new_unchecked(&mut s.stream);
+    // A panicking stream ends.
+    match catch_unwind(AssertUnwindSafe(|| stream.poll_next(&mut cx))) {
+        Ok(Poll::Ready(Some(item))) => {
+            let (buf, item_ref) = ToRef::calc_ref(&item);
+            let item = s.item.insert((item, item_ref, buf));
+            arg.item = &item.1 as *const T::Ref as *const ();
+            arg.status = 0;
+        }
+        Ok(Poll::Pending) => arg.status = 1,
+        Ok(Poll::Ready(None)) | Err(_) => arg.status = 2,
+    }
+}
+
+unsafe fn release<T: ToRef, S>(ptr: *mut ()) {
+    drop(Box::from_raw(ptr as *mut RustStream<T, S>));
+}
+
+/// Boxed stream of an async call, which is dropped unless it's passed to go.
+#[doc(hidden)]
+pub struct OwnedRustStream(*mut ());
+
+// The stream is Send, and it's never accessed with a shared reference.
+unsafe impl Send for OwnedRustStream {}
+unsafe impl Sync for OwnedRustStream {}
+
+impl OwnedRustStream {
+    /// Pass the stream to go, which releases it when the go method returns.
+    pub fn into_raw(self) -> *mut () {
+        let ptr = self.0;
+        std::mem::forget(self);
+        ptr
+    }
+}
+
+impl Drop for OwnedRustStream {
+    fn drop(&mut self) {
+        unsafe { c_rust2go_rust_stream_release(self.0) };
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn c_rust2go_rust_stream_next(ptr: *mut (), arg: *mut ()) {
+    let next = *(ptr as *const unsafe fn(*mut (), *mut NextArg));
+    next(ptr, arg as *mut NextArg);
+}
+
+#[no_mangle]
+unsafe extern "C" fn c_rust2go_rust_stream_release(ptr: *mut ()) {
+    let release = *(ptr as *const unsafe fn(*mut ())).add(1);
+    release(ptr);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    thread_local! {
+        static GO_WAKES: Cell<usize> = const { Cell::new(0) };
+        static GO_CANCELS: Cell<usize> = const { Cell::new(0) };
+        static DROPS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn wake_go(_: *mut c_void) {
+        GO_WAKES.with(|w| w.set(w.get() + 1));
+    }
+
+    unsafe extern "C" fn cancel_go(_: *mut c_void) {
+        GO_CANCELS.with(|c| c.set(c.get() + 1));
+    }
+
+    fn reset() {
+        for counter in [&GO_WAKES, &GO_CANCELS, &DROPS] {
+            counter.with(|c| c.set(0));
+        }
+    }
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn poll<S: Stream + Unpin>(s: &mut S) -> Poll<Option<S::Item>> {
+        let waker = Waker::from(Arc::new(Noop));
+        Pin::new(s).poll_next(&mut Context::from_waker(&waker))
+    }
+
+    fn send(ptr: *mut (), item: u32) -> u8 {
+        let mut arg = SendArg {
+            item: &item as *const u32 as *const (),
+            status: u8::MAX,
+        };
+        unsafe { c_rust2go_stream_send(ptr, &mut arg as *mut SendArg as *mut ()) };
+        arg.status
+    }
+
+    #[test]
+    fn go_stream_backpressure() {
+        reset();
+        let mut s = GoStream::<u32>::new(wake_go, cancel_go);
+        let ptr = s.go_ref();
+        for i in 0..STREAM_BUFFER as u32 - 1 {
+            assert_eq!(send(ptr, i), 0);
+        }
+        // Go sender waits until an item is taken.
+        assert_eq!(send(ptr, STREAM_BUFFER as u32 - 1), 1);
+        assert_eq!(poll(&mut s), Poll::Ready(Some(0)));
+        assert_eq!(GO_WAKES.with(Cell::get), 1);
+        for i in 1..STREAM_BUFFER as u32 {
+            assert_eq!(poll(&mut s), Poll::Ready(Some(i)));
+        }
+        assert_eq!(GO_WAKES.with(Cell::get), 1);
+        assert_eq!(poll(&mut s), Poll::Pending);
+
+        unsafe { c_rust2go_stream_close(ptr) };
+        assert_eq!(poll(&mut s), Poll::Ready(None));
+        drop(s);
+        assert_eq!(GO_CANCELS.with(Cell::get), 0);
+    }
+
+    #[test]
+    fn drop_go_stream() {
+        reset();
+        let s = GoStream::<u32>::new(wake_go, cancel_go);
+        let ptr = s.go_ref();
+        assert_eq!(send(ptr, 1), 0);
+        drop(s);
+        assert_eq!(GO_CANCELS.with(Cell::get), 1);
+        // Items sent before go side sees the cancellation are rejected.
+        assert_eq!(send(ptr, 2), 2);
+        unsafe { c_rust2go_stream_close(ptr) };
+    }
+
+    // Yields the items and stores the waker when it runs out.
+    struct Items {
+        items: VecDeque<u32>,
+        waker: Arc<Mutex<Option<Waker>>>,
+    }
+
+    impl Stream for Items {
+        type Item = u32;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u32>> {
+            match self.items.pop_front() {
+                Some(item) => Poll::Ready(Some(item)),
+                None => {
+                    *self.waker.lock().unwrap() = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    impl Drop for Items {
+        fn drop(&mut self) {
+            DROPS.with(|d| d.set(d.get() + 1));
+        }
+    }
+
+    fn next(ptr: *mut ()) -> (u8, Option<u32>) {
+        let mut arg = NextArg {
+            item: std::ptr::null(),
+            status: u8::MAX,
+        };
+        unsafe { c_rust2go_rust_stream_next(ptr, &mut arg as *mut NextArg as *mut ()) };
+        let item = (arg.status == 0).then(|| unsafe { *(arg.item as *const u32) });
+        (arg.status, item)
+    }
+
+    #[test]
+    fn drop_rust_stream() {
+        reset();
+        let waker = Arc::new(Mutex::new(None));
+        let items = Items {
+            items: VecDeque::from([1]),
+            waker: waker.clone(),
+        };
+        let ptr = RustStream::boxed(items, wake_go).into_raw();
+        assert_eq!(next(ptr), (0, Some(1)));
+        assert_eq!(next(ptr), (1, None));
+        let waker = waker.lock().unwrap().take().unwrap();
+        waker.wake_by_ref();
+        assert_eq!(GO_WAKES.with(Cell::get), 1);
+
+        unsafe { c_rust2go_rust_stream_release(ptr) };
+        assert_eq!(DROPS.with(Cell::get), 1);
+        // The waker outlives the stream and is ignored.
+        waker.wake();
+        assert_eq!(GO_WAKES.with(Cell::get), 1);
+
+        // The stream of an async call which is never sent is dropped with it.
+        drop(RustStream::boxed(
+            Items {
+                items: VecDeque::new(),
+                waker: Arc::default(),
+            },
+            wake_go,
+        ));
+        assert_eq!(DROPS.with(Cell::get), 2);
+    }
+}
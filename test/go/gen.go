@@ -59,15 +59,189 @@ typedef struct PMFriendResponseRef {
   bool succ;
   struct StringRef message;
 } PMFriendResponseRef;
+
+typedef struct Tuple_u32_u32Ref {
+  uint32_t f0;
+  uint32_t f1;
+} Tuple_u32_u32Ref;
+
+const void c_rust2go_internal_drop(void*);
+
+void c_rust2go_callback_call(void*, void*, void*);
+void c_rust2go_callback_release(void*);
+
+void c_rust2go_stream_send(void*, void*);
+void c_rust2go_stream_close(void*);
+
+void c_rust2go_rust_stream_next(void*, void*);
+void c_rust2go_rust_stream_release(void*);
 */
 import "C"
 import (
+	"context"
+	"fmt"
 	"reflect"
 	"runtime"
+	"sync"
+	"time"
 	"unsafe"
 
 	"github.com/ihciah/rust2go/asmcall"
 )
+func r2gRecover(msg *string) {
+	if r := recover(); r != nil {
+		*msg = fmt.Sprint(r)
+		if *msg == "" {
+			*msg = "panic"
+		}
+	}
+}
+
+type r2gCallback struct {
+	mu    sync.Mutex
+	ptr   unsafe.Pointer
+	owned bool
+}
+
+func (c *r2gCallback) call(callFn func(unsafe.Pointer, unsafe.Pointer, unsafe.Pointer, unsafe.Pointer), dropFn func(unsafe.Pointer, unsafe.Pointer), arg unsafe.Pointer) bool {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	if c.ptr == nil {
+		return false
+	}
+	slot := [3]unsafe.Pointer{}
+	callFn(unsafe.Pointer(C.c_rust2go_callback_call), c.ptr, arg, unsafe.Pointer(&slot))
+	if slot[2] != nil {
+		msg := ownString(*(*C.StringRef)(slot[2]))
+		dropFn(unsafe.Pointer(C.c_rust2go_internal_drop), slot[1])
+		panic("rust panicked: " + msg)
+	}
+	return true
+}
+func (c *r2gCallback) done() {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	if c.owned {
+		C.c_rust2go_callback_release(c.ptr)
+	}
+	c.ptr = nil
+}
+
+var r2gStreams sync.Map
+
+type r2gStream struct {
+	ptr    unsafe.Pointer
+	ready  chan struct{}
+	cancel context.CancelFunc
+}
+
+type r2gStreamSend struct {
+	item   unsafe.Pointer
+	status uint8
+}
+
+func (s *r2gStream) send(ctx context.Context, callFn func(unsafe.Pointer, unsafe.Pointer, unsafe.Pointer), item unsafe.Pointer) bool {
+	arg := r2gStreamSend{item: item}
+	callFn(unsafe.Pointer(C.c_rust2go_stream_send), s.ptr, unsafe.Pointer(&arg))
+	switch arg.status {
+	case 0:
+		return true
+	case 1:
+		select {
+		case <-s.ready:
+			return true
+		case <-ctx.Done():
+			return false
+		}
+	default:
+		return false
+	}
+}
+
+//export rust2go_stream_wake
+func rust2go_stream_wake(stream unsafe.Pointer) {
+	if s, ok := r2gStreams.Load(uintptr(stream)); ok {
+		select {
+		case s.(*r2gStream).ready <- struct{}{}:
+		default:
+		}
+	}
+}
+
+//export rust2go_stream_cancel
+func rust2go_stream_cancel(stream unsafe.Pointer) {
+	if s, ok := r2gStreams.Load(uintptr(stream)); ok {
+		s.(*r2gStream).cancel()
+	}
+}
+
+var r2gRustStreams sync.Map
+
+type r2gRustStream struct {
+	ptr     unsafe.Pointer
+	owned   bool
+	ready   chan struct{}
+	stopped chan struct{}
+	exited  chan struct{}
+}
+
+type r2gRustStreamNext struct {
+	item   unsafe.Pointer
+	status uint8
+}
+
+func newR2gRustStream(ptr unsafe.Pointer, owned bool) *r2gRustStream {
+	s := &r2gRustStream{ptr: ptr, owned: owned, ready: make(chan struct{}, 1), stopped: make(chan struct{}), exited: make(chan struct{})}
+	r2gRustStreams.Store(uintptr(ptr), s)
+	return s
+}
+func (s *r2gRustStream) next() unsafe.Pointer {
+	for {
+		arg := r2gRustStreamNext{}
+		C.c_rust2go_rust_stream_next(s.ptr, unsafe.Pointer(&arg))
+		switch arg.status {
+		case 0:
+			return arg.item
+		case 1:
+			select {
+			case <-s.ready:
+			case <-s.stopped:
+				return nil
+			}
+		default:
+			return nil
+		}
+	}
+}
+func (s *r2gRustStream) run(send func(unsafe.Pointer) bool) {
+	defer func() {
+		r2gRustStreams.Delete(uintptr(s.ptr))
+		if s.owned {
+			C.c_rust2go_rust_stream_release(s.ptr)
+		}
+		close(s.exited)
+	}()
+	for {
+		item := s.next()
+		if item == nil || !send(item) {
+			return
+		}
+	}
+}
+func (s *r2gRustStream) stop() {
+	close(s.stopped)
+	<-s.exited
+}
+
+//export rust2go_rust_stream_wake
+func rust2go_rust_stream_wake(stream unsafe.Pointer) {
+	if s, ok := r2gRustStreams.Load(uintptr(stream)); ok {
+		select {
+		case s.(*r2gRustStream).ready <- struct{}{}:
+		default:
+		}
+	}
+}
 
 var TestCallImpl TestCall
 
@@ -163,6 +337,200 @@ func CTestCall_multi_param_test(user C.UserRef, message C.StringRef, token C.Lis
 	}()
 }
 
+//export CTestCall_abi_hash
+func CTestCall_abi_hash() C.uint64_t {
+	return C.uint64_t(0xdbb041a2ccb322bb)
+}
+
+var FeatureCallImpl FeatureCall
+
+type FeatureCall interface {
+	divide(a *uint32, b *uint32) (uint32, uint32)
+	sleep(ctx context.Context, ms *uint32) uint32
+	scan(n *uint32, progress func(uint32) bool) uint32
+	count(ctx context.Context, n *uint32, yield func(uint32) bool)
+	sum(items <-chan uint32) uint32
+}
+
+//export CFeatureCall_divide
+func CFeatureCall_divide(a C.uint32_t, b C.uint32_t, slot *C.void, cb *C.void) {
+	_new_a := newC_uint32_t(a)
+	_new_b := newC_uint32_t(b)
+	resp, _panic := func() (_resp Tuple_u32_u32, _panic string) {
+		defer r2gRecover(&_panic)
+		resp_0, resp_1 := FeatureCallImpl.divide(&_new_a, &_new_b)
+		resp := Tuple_u32_u32{resp_0, resp_1}
+		return resp, ""
+	}()
+	if _panic != "" {
+		_ret := struct {
+			resp  C.Tuple_u32_u32Ref
+			panic C.StringRef
+		}{panic: refString(&_panic, nil)}
+		asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+		runtime.KeepAlive(_panic)
+		return
+	}
+	resp_ref, buffer := cvt_ref(cntTuple_u32_u32, refTuple_u32_u32)(&resp)
+	_ret := struct {
+		resp  C.Tuple_u32_u32Ref
+		panic C.StringRef
+	}{resp: resp_ref}
+	asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+	runtime.KeepAlive(resp_ref)
+	runtime.KeepAlive(resp)
+	runtime.KeepAlive(buffer)
+}
+
+//export CFeatureCall_sleep
+func CFeatureCall_sleep(ms C.uint32_t, _timeout C.int64_t, slot *C.void, cb *C.void) {
+	_new_ms := newC_uint32_t(ms)
+	_ctx, _cancel := context.WithTimeout(context.Background(), time.Duration(_timeout))
+	go func() {
+		_done := make(chan uint32, 1)
+		_panicked := make(chan string, 1)
+		go func() {
+			resp, _panic := func() (_resp uint32, _panic string) {
+				defer r2gRecover(&_panic)
+				resp := FeatureCallImpl.sleep(_ctx, &_new_ms)
+				return resp, ""
+			}()
+			if _panic != "" {
+				_panicked <- _panic
+				return
+			}
+			_done <- resp
+		}()
+		select {
+		case resp := <-_done:
+			_cancel()
+			resp_ref, buffer := cvt_ref(cntC_uint32_t, refC_uint32_t)(&resp)
+			_ret := struct {
+				resp  C.uint32_t
+				panic C.StringRef
+			}{resp: resp_ref}
+			asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+			runtime.KeepAlive(resp_ref)
+			runtime.KeepAlive(resp)
+			runtime.KeepAlive(buffer)
+		case _panic := <-_panicked:
+			_cancel()
+			_ret := struct {
+				resp  C.uint32_t
+				panic C.StringRef
+			}{panic: refString(&_panic, nil)}
+			asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+			runtime.KeepAlive(_panic)
+		case <-_ctx.Done():
+			_cancel()
+			asmcall.CallFuncG0P2(unsafe.Pointer(cb), nil, unsafe.Pointer(slot))
+		}
+	}()
+}
+
+//export CFeatureCall_scan
+func CFeatureCall_scan(n C.uint32_t, progress *C.void, slot *C.void, cb *C.void) {
+	_new_n := newC_uint32_t(n)
+	_cb_progress := &r2gCallback{ptr: unsafe.Pointer(progress), owned: false}
+	_new_progress := func(_arg uint32) bool {
+		_arg_ref, buffer := cvt_ref(cntC_uint32_t, refC_uint32_t)(&_arg)
+		_ok := _cb_progress.call(asmcall.CallFuncG0P3, asmcall.CallFuncG0P1, unsafe.Pointer(&_arg_ref))
+		runtime.KeepAlive(_arg)
+		runtime.KeepAlive(buffer)
+		return _ok
+	}
+	resp, _panic := func() (_resp uint32, _panic string) {
+		defer r2gRecover(&_panic)
+		resp := FeatureCallImpl.scan(&_new_n, _new_progress)
+		return resp, ""
+	}()
+	_cb_progress.done()
+	if _panic != "" {
+		_ret := struct {
+			resp  C.uint32_t
+			panic C.StringRef
+		}{panic: refString(&_panic, nil)}
+		asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+		runtime.KeepAlive(_panic)
+		return
+	}
+	resp_ref, buffer := cvt_ref(cntC_uint32_t, refC_uint32_t)(&resp)
+	_ret := struct {
+		resp  C.uint32_t
+		panic C.StringRef
+	}{resp: resp_ref}
+	asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+	runtime.KeepAlive(resp_ref)
+	runtime.KeepAlive(resp)
+	runtime.KeepAlive(buffer)
+}
+
+//export CFeatureCall_count
+func CFeatureCall_count(n C.uint32_t, _stream *C.void) {
+	_new_n := newC_uint32_t(n)
+	_ctx, _cancel := context.WithCancel(context.Background())
+	_s := &r2gStream{ptr: unsafe.Pointer(_stream), ready: make(chan struct{}, 1), cancel: _cancel}
+	r2gStreams.Store(uintptr(unsafe.Pointer(_stream)), _s)
+	go func() {
+		FeatureCallImpl.count(_ctx, &_new_n, func(_item uint32) bool {
+			_item_ref, buffer := cvt_ref(cntC_uint32_t, refC_uint32_t)(&_item)
+			_ok := _s.send(_ctx, asmcall.CallFuncG0P2, unsafe.Pointer(&_item_ref))
+			runtime.KeepAlive(_item)
+			runtime.KeepAlive(buffer)
+			return _ok
+		})
+		r2gStreams.Delete(uintptr(unsafe.Pointer(_stream)))
+		_cancel()
+		C.c_rust2go_stream_close(unsafe.Pointer(_stream))
+	}()
+}
+
+//export CFeatureCall_sum
+func CFeatureCall_sum(items *C.void, slot *C.void, cb *C.void) {
+	_rs_items := newR2gRustStream(unsafe.Pointer(items), false)
+	_new_items := make(chan uint32)
+	go func() {
+		_rs_items.run(func(_item unsafe.Pointer) bool {
+			select {
+			case _new_items <- newC_uint32_t(*(*C.uint32_t)(_item)):
+				return true
+			case <-_rs_items.stopped:
+				return false
+			}
+		})
+		close(_new_items)
+	}()
+	resp, _panic := func() (_resp uint32, _panic string) {
+		defer r2gRecover(&_panic)
+		resp := FeatureCallImpl.sum(_new_items)
+		return resp, ""
+	}()
+	_rs_items.stop()
+	if _panic != "" {
+		_ret := struct {
+			resp  C.uint32_t
+			panic C.StringRef
+		}{panic: refString(&_panic, nil)}
+		asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+		runtime.KeepAlive(_panic)
+		return
+	}
+	resp_ref, buffer := cvt_ref(cntC_uint32_t, refC_uint32_t)(&resp)
+	_ret := struct {
+		resp  C.uint32_t
+		panic C.StringRef
+	}{resp: resp_ref}
+	asmcall.CallFuncG0P2(unsafe.Pointer(cb), unsafe.Pointer(&_ret), unsafe.Pointer(slot))
+	runtime.KeepAlive(resp_ref)
+	runtime.KeepAlive(resp)
+	runtime.KeepAlive(buffer)
+}
+
+//export CFeatureCall_abi_hash
+func CFeatureCall_abi_hash() C.uint64_t {
+	return C.uint64_t(0x1fb7299b23cb6aeb)
+}
+
 // An alternative impl of unsafe.String for go1.18
 func unsafeString(ptr *byte, length int) string {
 	sliceHeader := &reflect.SliceHeader{
@@ -565,4 +933,33 @@ func refPMFriendResponse(p *PMFriendResponse, buffer *[]byte) C.PMFriendResponse
 		message: refString(&p.message, buffer),
 	}
 }
+
+type Tuple_u32_u32 struct {
+	f0 uint32
+	f1 uint32
+}
+
+func newTuple_u32_u32(p C.Tuple_u32_u32Ref) Tuple_u32_u32 {
+	return Tuple_u32_u32{
+		f0: newC_uint32_t(p.f0),
+		f1: newC_uint32_t(p.f1),
+	}
+}
+func ownTuple_u32_u32(p C.Tuple_u32_u32Ref) Tuple_u32_u32 {
+	return Tuple_u32_u32{
+		f0: newC_uint32_t(p.f0),
+		f1: newC_uint32_t(p.f1),
+	}
+}
+func cntTuple_u32_u32(s *Tuple_u32_u32, cnt *uint) [0]C.Tuple_u32_u32Ref {
+	_ = s
+	_ = cnt
+	return [0]C.Tuple_u32_u32Ref{}
+}
+func refTuple_u32_u32(p *Tuple_u32_u32, buffer *[]byte) C.Tuple_u32_u32Ref {
+	return C.Tuple_u32_u32Ref{
+		f0: refC_uint32_t(&p.f0, buffer),
+		f1: refC_uint32_t(&p.f1, buffer),
+	}
+}
 func main() {}
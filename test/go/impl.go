@@ -1,7 +1,9 @@
 package main
 
 import (
+	"context"
 	"fmt"
+	"time"
 )
 
 type Demo struct {
@@ -172,3 +174,45 @@ func (d *Demo) multi_param_test(user *User, message *string, token *[]uint8) Log
 		token:   *token,
 	}
 }
+
+type Features struct{}
+
+func init() {
+	FeatureCallImpl = Features{}
+}
+
+// Panics with a zero divisor, which is returned as an error.
+func (Features) divide(a *uint32, b *uint32) (uint32, uint32) {
+	return *a / *b, *a % *b
+}
+
+func (Features) sleep(ctx context.Context, ms *uint32) uint32 {
+	select {
+	case <-time.After(time.Duration(*ms) * time.Millisecond):
+	case <-ctx.Done():
+	}
+	return *ms
+}
+
+func (Features) scan(n *uint32, progress func(uint32) bool) uint32 {
+	for i := uint32(1); i <= *n; i++ {
+		progress(i)
+	}
+	return *n
+}
+
+func (Features) count(ctx context.Context, n *uint32, yield func(uint32) bool) {
+	for i := uint32(0); i < *n; i++ {
+		if !yield(i) {
+			return
+		}
+	}
+}
+
+func (Features) sum(items <-chan uint32) uint32 {
+	var sum uint32
+	for item := range items {
+		sum += item
+	}
+	return sum
+}
@@ -2,6 +2,13 @@ mod user;
 
 #[cfg(test)]
 mod tests {
+    use std::future::poll_fn;
+    use std::pin::{pin, Pin};
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    use rust2go::Stream;
+
     use super::user::*;
 
     #[test]
@@ -361,4 +368,63 @@ mod tests {
         // For now, we just ensure the function can be called without panic
         assert!(!response.message.is_empty());
     }
+
+    #[test]
+    fn go_panic_result() {
+        assert_eq!(FeatureCallImpl::divide(7, 2).unwrap(), (3, 1));
+        let err = FeatureCallImpl::divide(1, 0).unwrap_err();
+        assert!(err.message.contains("divide by zero"), "{err}");
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn deadline() {
+        assert_eq!(FeatureCallImpl::sleep(1).await.unwrap(), 1);
+        assert!(matches!(
+            FeatureCallImpl::sleep(1000).await,
+            Err(rust2go::CallError::DeadlineExceeded)
+        ));
+        let r = FeatureCallImpl::sleep_with_timeout(50, Duration::from_millis(500)).await;
+        assert_eq!(r.unwrap(), 50);
+    }
+
+    #[test]
+    fn callback() {
+        let mut seen = Vec::new();
+        let n = FeatureCallImpl::scan(3, |i| seen.push(i)).unwrap();
+        assert_eq!(n, 3);
+        assert_eq!(seen, vec![1, 2, 3]);
+
+        // The panic of the closure is resumed in go and recovered.
+        let err = FeatureCallImpl::scan(3, |i| assert!(i < 2, "progress {i}")).unwrap_err();
+        assert!(err.message.contains("rust panicked: progress 2"), "{err}");
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn go_stream() {
+        let mut items = Vec::new();
+        let mut stream = pin!(FeatureCallImpl::count(100));
+        while let Some(item) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            items.push(item);
+        }
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+
+        // Go side stops when the stream is dropped early.
+        let mut stream = pin!(FeatureCallImpl::count(u32::MAX));
+        assert_eq!(poll_fn(|cx| stream.as_mut().poll_next(cx)).await, Some(0));
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn rust_stream() {
+        struct Iter(std::ops::RangeInclusive<u32>);
+
+        impl Stream for Iter {
+            type Item = u32;
+
+            fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<u32>> {
+                Poll::Ready(self.0.next())
+            }
+        }
+
+        assert_eq!(FeatureCallImpl::sum(Iter(1..=100)).unwrap(), 5050);
+    }
 }
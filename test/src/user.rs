@@ -1,3 +1,5 @@
+use rust2go::Stream;
+
 pub mod binding {
     #![allow(warnings)]
     rust2go::r2g_include_binding!();
@@ -71,3 +73,14 @@ pub trait TestCall {
     #[mem_call]
     async fn multi_param_test(user: &User, message: &String, token: &Vec<u8>) -> LoginResponse;
 }
+
+#[rust2go::r2g(go_panic = "result")]
+pub trait FeatureCall {
+    fn divide(a: u32, b: u32) -> (u32, u32);
+    #[drop_safe]
+    #[timeout_ms = 200]
+    async fn sleep(ms: u32) -> u32;
+    fn scan(n: u32, progress: impl FnMut(u32) + Send) -> u32;
+    fn count(n: u32) -> impl Stream<Item = u32> + Send;
+    fn sum(items: impl Stream<Item = u32> + Send) -> u32;
+}